@@ -0,0 +1,338 @@
+use std::{ collections::HashMap, fmt::Display };
+use crate::analysis::irgen::{ IrFunction, IrInst, IrType };
+
+/// A straight run of instructions that is only entered at the top and only left at the bottom.
+#[derive(Debug, Clone)]
+pub(crate) struct BasicBlock {
+    /// The label that starts this block, blocks that are only reached by falling through
+    /// from the previous one don't have one
+    pub label: Option<usize>,
+    /// Every instruction in the block except the label
+    pub body: Vec<IrInst>,
+    pub preds: Vec<usize>,
+    pub succs: Vec<usize>,
+}
+
+/// The control flow graph of one function. The first block is the entry.
+#[derive(Debug)]
+pub(crate) struct Cfg {
+    pub id: String,
+    pub params: Vec<(String, IrType)>,
+    pub ret: IrType,
+    pub blocks: Vec<BasicBlock>,
+    /// Immediate dominator of every block, `None` for the entry and for unreachable blocks
+    pub idom: Vec<Option<usize>>,
+    /// Blocks reachable from the entry in reverse postorder, starting with the entry
+    pub order: Vec<usize>,
+}
+
+impl Cfg {
+    /// Splits the body of `func` into basic blocks, links them up and computes dominators.
+    pub(crate) fn build(func: &IrFunction) -> Cfg {
+        let mut blocks: Vec<BasicBlock> = vec![];
+        let mut current = BasicBlock { label: None, body: vec![], preds: vec![], succs: vec![] };
+        let mut started = false;
+
+        for inst in &func.body {
+            match inst {
+                // a label always starts a new block, unless it's the first thing in an empty one
+                IrInst::Label { id } => {
+                    if started || current.label.is_some() {
+                        blocks.push(current);
+                        current = BasicBlock { label: None, body: vec![], preds: vec![], succs: vec![] };
+                    }
+                    current.label = Some(*id);
+                    started = false;
+                }
+                _ if inst.is_terminator() => {
+                    current.body.push(inst.clone());
+                    blocks.push(current);
+                    current = BasicBlock { label: None, body: vec![], preds: vec![], succs: vec![] };
+                    started = false;
+                }
+                _ => {
+                    current.body.push(inst.clone());
+                    started = true;
+                }
+            }
+        }
+        if started || current.label.is_some() || blocks.is_empty() {
+            blocks.push(current);
+        }
+
+        let mut cfg = Cfg {
+            id: func.id.clone(),
+            params: func.params.clone(),
+            ret: func.ret.clone(),
+            blocks,
+            idom: vec![],
+            order: vec![],
+        };
+        cfg.link();
+        cfg.compute_dominators();
+        return cfg;
+    }
+
+    /// Puts the blocks back together into a function, in their current order.
+    pub(crate) fn to_function(&self) -> IrFunction {
+        let mut body = vec![];
+        for block in &self.blocks {
+            if let Some(id) = block.label {
+                body.push(IrInst::Label { id });
+            }
+            body.extend(block.body.iter().cloned());
+        }
+        return IrFunction { id: self.id.clone(), params: self.params.clone(), ret: self.ret.clone(), body };
+    }
+
+    pub(crate) fn is_reachable(&self, block: usize) -> bool {
+        return block == 0 || self.idom[block].is_some();
+    }
+
+    /// Blocks that can never run, in order.
+    pub(crate) fn unreachable(&self) -> Vec<usize> {
+        return (0..self.blocks.len()).filter(|b| !self.is_reachable(*b)).collect();
+    }
+
+    /// Drops every block that can't be reached from the entry, after working out reachability
+    /// again in case edges were removed. Blocks are renumbered, along with the edges and phi
+    /// arguments referring to them.
+    pub(crate) fn remove_unreachable(&mut self) {
+        self.compute_dominators();
+        let unreachable = self.unreachable();
+        if unreachable.is_empty() {
+            return;
+        }
+
+        let mut renumber: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        let mut next = 0;
+        for (b, slot) in renumber.iter_mut().enumerate() {
+            if !unreachable.contains(&b) {
+                *slot = Some(next);
+                next += 1;
+            }
+        }
+
+        let blocks = std::mem::take(&mut self.blocks);
+        for (b, mut block) in blocks.into_iter().enumerate() {
+            if renumber[b].is_none() {
+                continue;
+            }
+            block.preds = block.preds.iter().filter_map(|p| renumber[*p]).collect();
+            block.succs = block.succs.iter().filter_map(|s| renumber[*s]).collect();
+            for inst in block.body.iter_mut() {
+                if let IrInst::Phi { dest: _, ty: _, args } = inst {
+                    args.retain(|(p, _)| renumber[*p].is_some());
+                    for (p, _) in args.iter_mut() {
+                        *p = renumber[*p].unwrap();
+                    }
+                }
+            }
+            self.blocks.push(block);
+        }
+        self.compute_dominators();
+    }
+
+    /// Removes the edge from `from` to `to`, along with what the phis in `to` take from it.
+    pub(crate) fn remove_edge(&mut self, from: usize, to: usize) {
+        self.blocks[from].succs.retain(|s| *s != to);
+        self.blocks[to].preds.retain(|p| *p != from);
+        for inst in self.blocks[to].body.iter_mut() {
+            if let IrInst::Phi { dest: _, ty: _, args } = inst {
+                args.retain(|(p, _)| *p != from);
+            }
+        }
+    }
+
+    /// The dominance frontier of every block, the blocks where its dominance stops. These
+    /// are where SSA form needs phis for the values it defines.
+    pub(crate) fn frontiers(&self) -> Vec<Vec<usize>> {
+        let mut frontiers: Vec<Vec<usize>> = vec![vec![]; self.blocks.len()];
+        for b in 0..self.blocks.len() {
+            let preds = &self.blocks[b].preds;
+            if preds.len() < 2 || !self.is_reachable(b) {
+                continue;
+            }
+            for &p in preds {
+                let mut runner = Some(p);
+                while let Some(r) = runner
+                    && self.is_reachable(r)
+                    && Some(r) != self.idom[b]
+                {
+                    if !frontiers[r].contains(&b) {
+                        frontiers[r].push(b);
+                    }
+                    runner = self.idom[r];
+                }
+            }
+        }
+        return frontiers;
+    }
+
+    /// The children of every block in the dominator tree.
+    pub(crate) fn dominator_tree(&self) -> Vec<Vec<usize>> {
+        let mut children: Vec<Vec<usize>> = vec![vec![]; self.blocks.len()];
+        for (b, idom) in self.idom.iter().enumerate() {
+            if let Some(d) = idom {
+                children[*d].push(b);
+            }
+        }
+        return children;
+    }
+}
+
+impl Cfg {
+    /// Fills in the successors and predecessors of every block.
+    fn link(&mut self) {
+        let labels: HashMap<usize, usize> = self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.label.map(|l| (l, i)))
+            .collect();
+
+        for i in 0..self.blocks.len() {
+            let succs = match self.blocks[i].body.last() {
+                Some(IrInst::Jump { label }) => vec![labels[label]],
+                Some(IrInst::Branch { cond: _, then, otherwise }) if then == otherwise => vec![labels[then]],
+                Some(IrInst::Branch { cond: _, then, otherwise }) => vec![labels[then], labels[otherwise]],
+                Some(IrInst::Return { val: _ }) => vec![],
+                // anything else falls through into the next block
+                _ if i + 1 < self.blocks.len() => vec![i + 1],
+                _ => vec![],
+            };
+            for s in &succs {
+                self.blocks[*s].preds.push(i);
+            }
+            self.blocks[i].succs = succs;
+        }
+    }
+
+    /// Computes immediate dominators with the iterative algorithm from Cooper, Harvey and
+    /// Kennedy, "A Simple, Fast Dominance Algorithm".
+    fn compute_dominators(&mut self) {
+        let count = self.blocks.len();
+
+        // postorder by an explicit stack so long functions can't overflow
+        let mut visited = vec![false; count];
+        let mut postorder = vec![];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            if let Some(&succ) = self.blocks[block].succs.get(next) {
+                stack.push((block, next + 1));
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                postorder.push(block);
+            }
+        }
+
+        let mut rank = vec![usize::MAX; count];
+        for (i, b) in postorder.iter().enumerate() {
+            rank[*b] = i;
+        }
+        self.order = postorder.into_iter().rev().collect();
+
+        // the entry temporarily dominates itself so intersections have somewhere to stop
+        let mut idom: Vec<Option<usize>> = vec![None; count];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in self.order.iter().skip(1) {
+                let mut new: Option<usize> = None;
+                for &p in &self.blocks[b].preds {
+                    if idom[p].is_none() {
+                        continue;
+                    }
+                    new = match new {
+                        None => Some(p),
+                        Some(n) => Some(intersect(&idom, &rank, p, n)),
+                    };
+                }
+                if new.is_some() && idom[b] != new {
+                    idom[b] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        self.idom = idom;
+    }
+}
+
+impl Display for Cfg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|(id, ty)| format!("{}: {}", id, ty)).collect();
+        writeln!(f, "fn {}({}) -> {} {{", self.id, params.join(", "), self.ret)?;
+        for (b, block) in self.blocks.iter().enumerate() {
+            match block.label {
+                Some(l) => writeln!(f, "  b{} (L{}):", b, l)?,
+                None => writeln!(f, "  b{}:", b)?,
+            }
+            for inst in &block.body {
+                writeln!(f, "    {}", inst)?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Renders the graphs of every function as one Graphviz DOT digraph, with a cluster per
+/// function. Unreachable blocks are dashed.
+pub(crate) fn to_dot(cfgs: &[Cfg]) -> String {
+    let mut out = String::from("digraph program {\n");
+    out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for (f, cfg) in cfgs.iter().enumerate() {
+        out.push_str(&format!("    subgraph cluster_{} {{\n", f));
+        out.push_str(&format!("        label=\"{}\";\n", escape(&cfg.id)));
+
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            let mut label = format!("b{}", b);
+            if let Some(l) = block.label {
+                label.push_str(&format!(" (L{})", l));
+            }
+            if let Some(d) = cfg.idom[b] {
+                label.push_str(&format!(" idom=b{}", d));
+            }
+            label.push_str("\\l");
+            for inst in &block.body {
+                label.push_str(&escape(&inst.to_string()));
+                label.push_str("\\l");
+            }
+
+            let style = if cfg.is_reachable(b) { "" } else { ", style=dashed" };
+            out.push_str(&format!("        f{}_b{} [label=\"{}\"{}];\n", f, b, label, style));
+        }
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            for s in &block.succs {
+                out.push_str(&format!("        f{}_b{} -> f{}_b{};\n", f, b, f, s));
+            }
+        }
+        out.push_str("    }\n");
+    }
+
+    out.push_str("}\n");
+    return out;
+}
+
+/// Walks both blocks up the dominator tree until they meet.
+fn intersect(idom: &[Option<usize>], rank: &[usize], a: usize, b: usize) -> usize {
+    let (mut a, mut b) = (a, b);
+    while a != b {
+        while rank[a] < rank[b] {
+            a = idom[a].unwrap();
+        }
+        while rank[b] < rank[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    return a;
+}
+
+fn escape(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('"', "\\\"");
+}
@@ -1,85 +1,614 @@
-use crate::common::{ ast::{ Node, NodeKind }, token::TokenKind };
+use std::{ collections::HashMap, fmt::Display };
+use crate::common::{
+    ast::{ Node, NodeKind, TypeName },
+    error::{ ChaoError, ErrorBase, ErrorSeverity },
+    span::Span,
+    token::TokenKind,
+};
 
-#[derive(Debug)]
-pub(super) enum IrValue {
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum IrType {
+    Int,
+    Float,
+    Bool,
+    Char,
+    Str,
+    Nil,
+    Void,
+    Optional(Box<IrType>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum IrValue {
+    /// Temporaries are numbered from zero within each function
     Temp(usize),
     Identifier(String),
     ConstInt(i32),
+    ConstFloat(f32),
+    ConstStr(String),
+    ConstChar(char),
+    ConstBool(bool),
+    Nil,
 }
 
-#[derive(Debug)]
+/// A single three-address instruction. Every instruction that defines a value carries the
+/// type of that value.
+#[derive(Debug, Clone)]
 pub(crate) enum IrInst {
+    /// Declares a new binding, constants and variables look the same in the IR
     Bind {
         id: String,
+        ty: IrType,
         val: IrValue,
     },
     Store {
         id: IrValue,
         val: IrValue,
+    },
+    Copy {
+        dest: IrValue,
+        ty: IrType,
+        val: IrValue,
+    },
+    BinOp {
+        dest: IrValue,
+        ty: IrType,
+        lhs: IrValue,
+        op: TokenKind,
+        rhs: IrValue,
+    },
+    UnOp {
+        dest: IrValue,
+        ty: IrType,
+        op: TokenKind,
+        val: IrValue,
+    },
+    Label {
+        id: usize,
+    },
+    Jump {
+        label: usize,
+    },
+    /// Jumps to `then` if `cond` is true, otherwise to `otherwise`
+    Branch {
+        cond: IrValue,
+        then: usize,
+        otherwise: usize,
+    },
+    /// `dest` is `None` when the function returns `void`
+    Call {
+        dest: Option<IrValue>,
+        ty: IrType,
+        func: String,
+        args: Vec<IrValue>,
+    },
+    Return {
+        val: Option<IrValue>,
+    },
+    /// Only appears in SSA form, at the top of a block. Takes the value from the argument
+    /// whose block control came from.
+    Phi {
+        dest: IrValue,
+        ty: IrType,
+        args: Vec<(usize, IrValue)>,
+    },
+}
+
+impl IrInst {
+    /// The value this instruction writes, stores count as writing their target.
+    pub(crate) fn dest(&self) -> Option<IrValue> {
+        return match self {
+            Self::Bind { id, ty: _, val: _ } => Some(IrValue::Identifier(id.clone())),
+            Self::Store { id, val: _ } => Some(id.clone()),
+            Self::Copy { dest, ty: _, val: _ } |
+            Self::BinOp { dest, ty: _, lhs: _, op: _, rhs: _ } |
+            Self::UnOp { dest, ty: _, op: _, val: _ } |
+            Self::Phi { dest, ty: _, args: _ } => Some(dest.clone()),
+            Self::Call { dest, ty: _, func: _, args: _ } => dest.clone(),
+            _ => None,
+        };
+    }
+
+    /// Every value this instruction reads. Phi arguments are included even though they're
+    /// really read at the end of the block they come from.
+    pub(crate) fn uses(&self) -> Vec<&IrValue> {
+        return match self {
+            Self::Bind { id: _, ty: _, val } |
+            Self::Store { id: _, val } |
+            Self::Copy { dest: _, ty: _, val } |
+            Self::UnOp { dest: _, ty: _, op: _, val } => vec![val],
+            Self::BinOp { dest: _, ty: _, lhs, op: _, rhs } => vec![lhs, rhs],
+            Self::Branch { cond, then: _, otherwise: _ } => vec![cond],
+            Self::Call { dest: _, ty: _, func: _, args } => args.iter().collect(),
+            Self::Return { val } => val.iter().collect(),
+            Self::Phi { dest: _, ty: _, args } => args.iter().map(|(_, v)| v).collect(),
+            Self::Label { id: _ } | Self::Jump { label: _ } => vec![],
+        };
+    }
+
+    pub(crate) fn uses_mut(&mut self) -> Vec<&mut IrValue> {
+        return match self {
+            Self::Bind { id: _, ty: _, val } |
+            Self::Store { id: _, val } |
+            Self::Copy { dest: _, ty: _, val } |
+            Self::UnOp { dest: _, ty: _, op: _, val } => vec![val],
+            Self::BinOp { dest: _, ty: _, lhs, op: _, rhs } => vec![lhs, rhs],
+            Self::Branch { cond, then: _, otherwise: _ } => vec![cond],
+            Self::Call { dest: _, ty: _, func: _, args } => args.iter_mut().collect(),
+            Self::Return { val } => val.iter_mut().collect(),
+            Self::Phi { dest: _, ty: _, args } => args.iter_mut().map(|(_, v)| v).collect(),
+            Self::Label { id: _ } | Self::Jump { label: _ } => vec![],
+        };
+    }
+
+    /// Whether control never falls through to the next instruction.
+    pub(crate) fn is_terminator(&self) -> bool {
+        return matches!(
+            self,
+            Self::Jump { label: _ } | Self::Branch { cond: _, then: _, otherwise: _ } | Self::Return { val: _ }
+        );
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct IrFunction {
+    pub id: String,
+    pub params: Vec<(String, IrType)>,
+    pub ret: IrType,
+    pub body: Vec<IrInst>,
+}
+
+/// Every function in a program. The top level statements are collected into the first one.
+#[derive(Debug)]
+pub(crate) struct IrProgram {
+    pub functions: Vec<IrFunction>,
+}
+
 pub(crate) struct IrCompiler {
-    temps: usize,
+    /// Types of the temporaries in the function being generated, indexed by number
+    temps: Vec<IrType>,
+    labels: usize,
+    ir: Vec<IrInst>,
+    functions: Vec<IrFunction>,
+    /// Maps source names to unique IR names, innermost scope last. Shadowed names get a
+    /// numeric suffix so every binding in the IR has a distinct identifier.
+    scopes: Vec<HashMap<String, String>>,
+    shadows: HashMap<String, usize>,
+    /// `(continue, break)` labels of the enclosing loops, innermost last
+    loops: Vec<(usize, usize)>,
+    /// Types of every binding by IR name, which is unique across the whole program
+    vars: HashMap<String, IrType>,
+    /// Return types of functions by IR name
+    returns: HashMap<String, IrType>,
 }
 
 impl IrCompiler {
     pub(crate) fn new() -> IrCompiler {
-        return IrCompiler { temps: 0 };
-    }
+        // builtins are reserved so user declarations of the same name get a distinct IR name
+        let shadows = HashMap::from([("print".to_string(), 1)]);
 
-    pub(crate) fn compile<'a>(&mut self, ast: Vec<Node<'a>>) -> Vec<IrInst> {
-        let mut ir: Vec<IrInst> = vec![];
+        let returns = HashMap::from([("print".to_string(), IrType::Void)]);
 
+        return IrCompiler {
+            temps: vec![],
+            labels: 0,
+            ir: vec![],
+            functions: vec![],
+            scopes: vec![HashMap::new()],
+            shadows,
+            loops: vec![],
+            vars: HashMap::new(),
+            returns,
+        };
+    }
+
+    pub(crate) fn compile<'a>(&mut self, ast: Vec<Node>) -> Result<IrProgram, ChaoError<'a>> {
         for node in ast {
-            match node.kind {
-                NodeKind::StmtConstant { id, val } => {
-                    let ir_val = self.expr(*val);
-                    ir.push(IrInst::Bind { id, val: ir_val });
-                }
-                NodeKind::StmtExpression { expr } => {
-                    match expr.kind {
-                        NodeKind::ExprAssignment { id, op: _, val } => {
-                            let ir_id = self.expr(*id);
-                            let ir_val = self.expr(*val);
-                            ir.push(IrInst::Store { id: ir_id, val: ir_val });
-                        }
-                        _ => {}
-                    }
-                }
-                
-                _ => unimplemented!("IrCompiler->compile()"),
-            }
+            self.stmt(node)?;
         }
 
-        return ir;
+        let script = IrFunction {
+            id: "<script>".to_string(),
+            params: vec![],
+            ret: IrType::Void,
+            body: std::mem::take(&mut self.ir),
+        };
+        let mut functions = vec![script];
+        functions.append(&mut self.functions);
+        return Ok(IrProgram { functions });
     }
 }
 
 impl IrCompiler {
-    fn temp(&mut self) -> IrValue {
-        self.temps += 1;
-        return IrValue::Temp(self.temps);
+    fn temp(&mut self, ty: IrType) -> IrValue {
+        self.temps.push(ty);
+        return IrValue::Temp(self.temps.len() - 1);
+    }
+
+    fn type_of(&self, val: &IrValue) -> IrType {
+        return match val {
+            IrValue::Temp(t) => self.temps[*t].clone(),
+            IrValue::Identifier(id) => self.vars.get(id).cloned().unwrap_or(IrType::Void),
+            IrValue::ConstInt(_) => IrType::Int,
+            IrValue::ConstFloat(_) => IrType::Float,
+            IrValue::ConstStr(_) => IrType::Str,
+            IrValue::ConstChar(_) => IrType::Char,
+            IrValue::ConstBool(_) => IrType::Bool,
+            IrValue::Nil => IrType::Nil,
+        };
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        return self.labels;
+    }
+
+    /// Declares `id` in the innermost scope and returns its unique IR name.
+    fn declare(&mut self, id: String) -> String {
+        let count = self.shadows.entry(id.clone()).or_insert(0);
+        let name = if *count == 0 { id.clone() } else { format!("{}.{}", id, count) };
+        *count += 1;
+
+        self.scopes.last_mut().unwrap().insert(id, name.clone());
+        return name;
+    }
+
+    fn lookup(&self, id: &String) -> String {
+        return self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(id))
+            .cloned()
+            .unwrap_or(id.clone());
+    }
+
+    fn stmt<'a>(&mut self, node: Node) -> Result<(), ChaoError<'a>> {
+        let span = node.span;
+        match node.kind {
+            NodeKind::StmtConstant { id, val } | NodeKind::StmtVariable { id, val } => {
+                let ir_val = self.expr(*val)?;
+                let ty = self.type_of(&ir_val);
+                let id = self.declare(id);
+                self.vars.insert(id.clone(), ty.clone());
+                self.ir.push(IrInst::Bind { id, ty, val: ir_val });
+            }
+            NodeKind::StmtExpression { expr } => {
+                _ = self.expr(*expr)?;
+            }
+            NodeKind::StmtBlock { body } => {
+                self.scopes.push(HashMap::new());
+                for n in body {
+                    self.stmt(n)?;
+                }
+                self.scopes.pop();
+            }
+            NodeKind::StmtIf { cond, then, otherwise } => {
+                let ir_cond = self.expr(*cond)?;
+                let then_label = self.label();
+                let else_label = self.label();
+                let end_label = self.label();
+
+                self.ir.push(IrInst::Branch { cond: ir_cond, then: then_label, otherwise: else_label });
+                self.ir.push(IrInst::Label { id: then_label });
+                self.stmt(*then)?;
+                self.ir.push(IrInst::Jump { label: end_label });
+                self.ir.push(IrInst::Label { id: else_label });
+                if let Some(otherwise) = otherwise {
+                    self.stmt(*otherwise)?;
+                }
+                self.ir.push(IrInst::Jump { label: end_label });
+                self.ir.push(IrInst::Label { id: end_label });
+            }
+            NodeKind::StmtWhile { cond, body } => {
+                let cond_label = self.label();
+                let body_label = self.label();
+                let end_label = self.label();
+
+                self.ir.push(IrInst::Jump { label: cond_label });
+                self.ir.push(IrInst::Label { id: cond_label });
+                let ir_cond = self.expr(*cond)?;
+                self.ir.push(IrInst::Branch { cond: ir_cond, then: body_label, otherwise: end_label });
+                self.ir.push(IrInst::Label { id: body_label });
+
+                self.loops.push((cond_label, end_label));
+                self.stmt(*body)?;
+                self.loops.pop();
+
+                self.ir.push(IrInst::Jump { label: cond_label });
+                self.ir.push(IrInst::Label { id: end_label });
+            }
+            NodeKind::StmtLoop { body } => {
+                let body_label = self.label();
+                let end_label = self.label();
+
+                self.ir.push(IrInst::Jump { label: body_label });
+                self.ir.push(IrInst::Label { id: body_label });
+
+                self.loops.push((body_label, end_label));
+                self.stmt(*body)?;
+                self.loops.pop();
+
+                self.ir.push(IrInst::Jump { label: body_label });
+                self.ir.push(IrInst::Label { id: end_label });
+            }
+            NodeKind::StmtBreak => {
+                let Some((_, end)) = self.loops.last().copied() else {
+                    return Err(unsupported(span, "'break' can only be lowered inside a loop"));
+                };
+                self.ir.push(IrInst::Jump { label: end });
+
+                // anything after the jump is unreachable but still needs a block to live in
+                let dead = self.label();
+                self.ir.push(IrInst::Label { id: dead });
+            }
+            NodeKind::StmtContinue => {
+                let Some((start, _)) = self.loops.last().copied() else {
+                    return Err(unsupported(span, "'continue' can only be lowered inside a loop"));
+                };
+                self.ir.push(IrInst::Jump { label: start });
+
+                let dead = self.label();
+                self.ir.push(IrInst::Label { id: dead });
+            }
+
+            NodeKind::StmtFunction { id, params, ret, body } => {
+                let id = self.declare(id);
+                let ret = ret.as_ref().map(ir_type).unwrap_or(IrType::Void);
+
+                // registered before the body so recursive calls know what they return
+                self.returns.insert(id.clone(), ret.clone());
+
+                // the body is generated into its own buffer with a scope holding the parameters
+                let outer = std::mem::take(&mut self.ir);
+                let outer_temps = std::mem::take(&mut self.temps);
+                self.scopes.push(HashMap::new());
+                let mut ir_params = vec![];
+                for p in params {
+                    let ty = ir_type(&p.ty);
+                    let name = self.declare(p.id);
+                    self.vars.insert(name.clone(), ty.clone());
+                    ir_params.push((name, ty));
+                }
+                self.stmt(*body)?;
+                self.scopes.pop();
+
+                // falling off the end of the body returns nothing
+                self.ir.push(IrInst::Return { val: None });
+                let body = std::mem::replace(&mut self.ir, outer);
+                self.temps = outer_temps;
+                self.functions.push(IrFunction { id, params: ir_params, ret, body });
+            }
+            NodeKind::StmtReturn { val } => {
+                let val = val.map(|v| self.expr(*v)).transpose()?;
+                self.ir.push(IrInst::Return { val });
+
+                let dead = self.label();
+                self.ir.push(IrInst::Label { id: dead });
+            }
+
+            _ => {
+                return Err(unsupported(span, "this statement cannot be lowered to IR yet"));
+            }
+        }
+        return Ok(());
     }
 
-    fn expr<'a>(&mut self, node: Node<'a>) -> IrValue {
+    fn expr<'a>(&mut self, node: Node) -> Result<IrValue, ChaoError<'a>> {
+        let span = node.span;
         match node.kind {
-            NodeKind::LiteralIdent { id } => IrValue::Identifier(id),
-            NodeKind::LiteralInt { val } => IrValue::ConstInt(val),
+            NodeKind::LiteralIdent { id } => {
+                let id = self.lookup(&id);
+                if !self.vars.contains_key(&id) {
+                    return Err(unsupported(span, "functions can only be called by name in IR"));
+                }
+                return Ok(IrValue::Identifier(id));
+            }
+            NodeKind::LiteralInt { val } => Ok(IrValue::ConstInt(val)),
+            NodeKind::LiteralFloat { val } => Ok(IrValue::ConstFloat(val)),
+            NodeKind::LiteralStr { val } => Ok(IrValue::ConstStr(val)),
+            NodeKind::LiteralChar { val } => Ok(IrValue::ConstChar(val)),
+            NodeKind::LiteralTrue => Ok(IrValue::ConstBool(true)),
+            NodeKind::LiteralFalse => Ok(IrValue::ConstBool(false)),
+            NodeKind::LiteralNil => Ok(IrValue::Nil),
+            NodeKind::ExprAssignment { id, op: _, val } => {
+                let ir_id = self.expr(*id)?;
+                let ir_val = self.expr(*val)?;
+                self.ir.push(IrInst::Store { id: ir_id.clone(), val: ir_val });
+                return Ok(ir_id);
+            }
+            NodeKind::ExprBinary { lhs, op: op @ (TokenKind::AmpAmp | TokenKind::PipePipe), rhs } => {
+                // short circuit by only evaluating the rhs when the lhs doesn't decide the result
+                let dest = self.temp(IrType::Bool);
+                let ir_l = self.expr(*lhs)?;
+                self.ir.push(IrInst::Copy { dest: dest.clone(), ty: IrType::Bool, val: ir_l });
+
+                let rhs_label = self.label();
+                let end_label = self.label();
+                let (then, otherwise) = if op == TokenKind::AmpAmp {
+                    (rhs_label, end_label)
+                } else {
+                    (end_label, rhs_label)
+                };
+                self.ir.push(IrInst::Branch { cond: dest.clone(), then, otherwise });
+
+                self.ir.push(IrInst::Label { id: rhs_label });
+                let ir_r = self.expr(*rhs)?;
+                self.ir.push(IrInst::Copy { dest: dest.clone(), ty: IrType::Bool, val: ir_r });
+                self.ir.push(IrInst::Jump { label: end_label });
+                self.ir.push(IrInst::Label { id: end_label });
+                return Ok(dest);
+            }
             NodeKind::ExprBinary { lhs, op, rhs } => {
-                match op {
-                    TokenKind::Plus => {
-                        let ir_l = self.expr(*lhs);
-                        let ir_r = self.expr(*rhs);
-                        let temp = self.temp();
-                        println!("TEMP {:?} = {:?} + {:?}", temp, ir_l, ir_r);
-                        return temp;
+                let ir_l = self.expr(*lhs)?;
+                let ir_r = self.expr(*rhs)?;
+                let ty = match op {
+                    TokenKind::EqualEqual | TokenKind::BangEqual |
+                    TokenKind::Less | TokenKind::LessEqual |
+                    TokenKind::Greater | TokenKind::GreaterEqual => IrType::Bool,
+                    _ => self.type_of(&ir_l),
+                };
+                let temp = self.temp(ty.clone());
+                self.ir.push(IrInst::BinOp { dest: temp.clone(), ty, lhs: ir_l, op, rhs: ir_r });
+                return Ok(temp);
+            }
+            NodeKind::ExprUnary { op: op @ (TokenKind::PlusPlus | TokenKind::MinusMinus), operand, postfix } => {
+                let ir_id = self.expr(*operand)?;
+                let ty = self.type_of(&ir_id);
+
+                // postfix evaluates to a copy of the value from before the update
+                let old = if postfix {
+                    let temp = self.temp(ty.clone());
+                    self.ir.push(IrInst::Copy { dest: temp.clone(), ty: ty.clone(), val: ir_id.clone() });
+                    Some(temp)
+                } else {
+                    None
+                };
+
+                let bin_op = if op == TokenKind::PlusPlus { TokenKind::Plus } else { TokenKind::Minus };
+                let one = if ty == IrType::Float { IrValue::ConstFloat(1.0) } else { IrValue::ConstInt(1) };
+                let new = self.temp(ty.clone());
+                self.ir.push(IrInst::BinOp {
+                    dest: new.clone(),
+                    ty,
+                    lhs: ir_id.clone(),
+                    op: bin_op,
+                    rhs: one,
+                });
+                self.ir.push(IrInst::Store { id: ir_id, val: new.clone() });
+                return Ok(old.unwrap_or(new));
+            }
+            NodeKind::ExprUnary { op, operand, postfix: _ } => {
+                let ir_val = self.expr(*operand)?;
+                let ty = if op == TokenKind::Bang { IrType::Bool } else { self.type_of(&ir_val) };
+                let temp = self.temp(ty.clone());
+                self.ir.push(IrInst::UnOp { dest: temp.clone(), ty, op, val: ir_val });
+                return Ok(temp);
+            }
+
+            NodeKind::ExprCall { callee, args } => {
+                let func = match callee.kind {
+                    NodeKind::LiteralIdent { id } => self.lookup(&id),
+                    _ => {
+                        return Err(unsupported(span, "only named functions can be lowered to IR"));
                     }
-                    _ => unimplemented!("Non + BinaryExprs"),
+                };
+                let args = args.into_iter().map(|a| self.expr(a)).collect::<Result<_, _>>()?;
+                let ty = self.returns.get(&func).cloned().unwrap_or(IrType::Void);
+
+                // a call to a void function has no result, the expression itself is nil
+                let dest = if ty == IrType::Void { None } else { Some(self.temp(ty.clone())) };
+                self.ir.push(IrInst::Call { dest: dest.clone(), ty, func, args });
+                return Ok(dest.unwrap_or(IrValue::Nil));
+            }
+
+            _ => Err(unsupported(span, "this expression cannot be lowered to IR yet")),
+        }
+    }
+}
+
+impl Display for IrType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int => write!(f, "int"),
+            Self::Float => write!(f, "float"),
+            Self::Bool => write!(f, "bool"),
+            Self::Char => write!(f, "char"),
+            Self::Str => write!(f, "str"),
+            Self::Nil => write!(f, "nil"),
+            Self::Void => write!(f, "void"),
+            Self::Optional(inner) => write!(f, "{}?", inner),
+        }
+    }
+}
+
+impl Display for IrValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Temp(t) => write!(f, "t{}", t),
+            Self::Identifier(id) => write!(f, "{}", id),
+            Self::ConstInt(n) => write!(f, "{}", n),
+            Self::ConstFloat(n) => write!(f, "{:?}", n),
+            Self::ConstStr(s) => write!(f, "{:?}", s),
+            Self::ConstChar(c) => write!(f, "{:?}", c),
+            Self::ConstBool(b) => write!(f, "{}", b),
+            Self::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+impl Display for IrInst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bind { id, ty, val } => write!(f, "let {}: {} = {}", id, ty, val),
+            Self::Store { id, val } => write!(f, "{} = {}", id, val),
+            Self::Copy { dest, ty, val } => write!(f, "{}: {} = {}", dest, ty, val),
+            Self::BinOp { dest, ty, lhs, op, rhs } => {
+                write!(f, "{}: {} = {} {} {}", dest, ty, lhs, op.symbol().unwrap_or("?"), rhs)
+            }
+            Self::UnOp { dest, ty, op, val } => {
+                write!(f, "{}: {} = {}{}", dest, ty, op.symbol().unwrap_or("?"), val)
+            }
+            Self::Label { id } => write!(f, "L{}:", id),
+            Self::Jump { label } => write!(f, "jump L{}", label),
+            Self::Branch { cond, then, otherwise } => write!(f, "branch {}, L{}, L{}", cond, then, otherwise),
+            Self::Call { dest, ty, func, args } => {
+                if let Some(dest) = dest {
+                    write!(f, "{}: {} = ", dest, ty)?;
                 }
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "call {}({})", func, args.join(", "))
+            }
+            Self::Return { val: Some(val) } => write!(f, "return {}", val),
+            Self::Return { val: None } => write!(f, "return"),
+            Self::Phi { dest, ty, args } => {
+                let args: Vec<String> = args.iter().map(|(b, v)| format!("b{}: {}", b, v)).collect();
+                write!(f, "{}: {} = phi [{}]", dest, ty, args.join(", "))
             }
+        }
+    }
+}
 
-            _ => unimplemented!("IrCompiler->expr()"),
+impl Display for IrFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|(id, ty)| format!("{}: {}", id, ty)).collect();
+        writeln!(f, "fn {}({}) -> {} {{", self.id, params.join(", "), self.ret)?;
+        for inst in &self.body {
+            // labels are outdented so the blocks they start stand out
+            match inst {
+                IrInst::Label { id: _ } => writeln!(f, "  {}", inst)?,
+                _ => writeln!(f, "    {}", inst)?,
+            }
         }
+        write!(f, "}}")
     }
 }
+
+impl Display for IrProgram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, func) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", func)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Converts a type written in the source, which the resolver has already checked.
+fn ir_type(name: &TypeName) -> IrType {
+    let ty = match name.name.as_str() {
+        "int" => IrType::Int,
+        "float" => IrType::Float,
+        "bool" => IrType::Bool,
+        "char" => IrType::Char,
+        "str" => IrType::Str,
+        _ => IrType::Void,
+    };
+    return if name.optional { IrType::Optional(Box::new(ty)) } else { ty };
+}
+
+fn unsupported<'a>(span: Span, msg: &'static str) -> ChaoError<'a> {
+    let eb = ErrorBase::UnsupportedConstruct { span };
+    return ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+}
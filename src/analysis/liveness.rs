@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+use crate::analysis::{ cfg::Cfg, irgen::IrInst, ssa::Var };
+
+/// Which temporaries and bindings are live, meaning they may still be read later, at the
+/// start and end of every block of a function.
+///
+/// Works on SSA form too, where a phi reads its argument at the end of the block the argument
+/// comes from rather than at the top of its own block.
+pub(crate) struct Liveness {
+    pub live_in: Vec<HashSet<Var>>,
+    pub live_out: Vec<HashSet<Var>>,
+}
+
+impl Liveness {
+    pub(crate) fn compute(cfg: &Cfg) -> Liveness {
+        let count = cfg.blocks.len();
+
+        // what each block reads before writing it, and everything it writes
+        let mut uses: Vec<HashSet<Var>> = vec![HashSet::new(); count];
+        let mut defs: Vec<HashSet<Var>> = vec![HashSet::new(); count];
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            for inst in &block.body {
+                if !matches!(inst, IrInst::Phi { dest: _, ty: _, args: _ }) {
+                    for var in inst.uses().into_iter().filter_map(Var::of) {
+                        if !defs[b].contains(&var) {
+                            uses[b].insert(var);
+                        }
+                    }
+                }
+                if let Some(var) = inst.dest().as_ref().and_then(Var::of) {
+                    defs[b].insert(var);
+                }
+            }
+        }
+
+        let mut live_in: Vec<HashSet<Var>> = uses.clone();
+        let mut live_out: Vec<HashSet<Var>> = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            // backwards through the blocks so most changes flow through in one pass
+            for &b in cfg.order.iter().rev() {
+                let mut out = HashSet::new();
+                for &s in &cfg.blocks[b].succs {
+                    out.extend(live_in[s].iter().cloned());
+                    out.extend(phi_reads(cfg, s, b));
+                }
+
+                let mut new = uses[b].clone();
+                new.extend(out.iter().filter(|v| !defs[b].contains(*v)).cloned());
+                if new.len() != live_in[b].len() || out.len() != live_out[b].len() {
+                    changed = true;
+                }
+                live_in[b] = new;
+                live_out[b] = out;
+            }
+        }
+        return Liveness { live_in, live_out };
+    }
+}
+
+/// What the phis at the top of `block` read when control comes from `pred`.
+fn phi_reads(cfg: &Cfg, block: usize, pred: usize) -> Vec<Var> {
+    let mut reads = vec![];
+    for inst in &cfg.blocks[block].body {
+        let IrInst::Phi { dest: _, ty: _, args } = inst else {
+            break;
+        };
+        reads.extend(args.iter().filter(|(b, _)| *b == pred).filter_map(|(_, v)| Var::of(v)));
+    }
+    return reads;
+}
@@ -1,2 +1,6 @@
+pub(crate) mod cfg;
 pub(crate) mod irgen;
+pub(crate) mod liveness;
+pub(crate) mod opt;
 pub(crate) mod resolver;
+pub(crate) mod ssa;
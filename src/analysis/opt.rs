@@ -0,0 +1,252 @@
+use std::collections::{ HashMap, HashSet };
+use crate::{
+    analysis::{
+        cfg::Cfg,
+        irgen::{ IrInst, IrProgram, IrType, IrValue },
+        liveness::Liveness,
+        ssa::{ self, Var },
+    },
+    common::token::TokenKind,
+    runtime::value::Value,
+};
+
+/// Runs every optimization pass over a program, going through SSA form and back.
+pub(crate) fn optimize(program: &IrProgram) -> IrProgram {
+    let mut cfgs = ssa::construct(program);
+    run(&mut cfgs);
+    return ssa::destruct(cfgs);
+}
+
+/// Runs every optimization pass over functions that are already in SSA form.
+pub(crate) fn run(cfgs: &mut [Cfg]) {
+    // a branch that goes away can make more values constant, which can decide more branches
+    loop {
+        propagate_constants(cfgs);
+        if !fold_branches(cfgs) {
+            break;
+        }
+    }
+    eliminate_dead_code(cfgs);
+}
+
+/// Replaces every read of a value that is known to be constant with the constant itself, and
+/// folds operations on constants, until nothing changes. This works across the whole program
+/// since IR names are unique, so a global bound to a constant is also replaced inside the
+/// functions reading it. Globals that anything stores to are left alone.
+///
+/// Operations are folded with the same operators as the runtime. One that would fail, like an
+/// overflow the resolver couldn't see, is left in place for the runtime to report.
+pub(crate) fn propagate_constants(cfgs: &mut [Cfg]) {
+    // bindings written more than once aren't constant, even if every write is
+    let mut bound: HashSet<String> = HashSet::new();
+    let mut unstable: HashSet<String> = HashSet::new();
+    for inst in cfgs.iter().flat_map(|c| &c.blocks).flat_map(|b| &b.body) {
+        match inst {
+            IrInst::Bind { id, ty: _, val: _ } if !bound.insert(id.clone()) => {
+                unstable.insert(id.clone());
+            }
+            IrInst::Store { id: IrValue::Identifier(id), val: _ } => {
+                unstable.insert(id.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let mut names: HashMap<String, IrValue> = HashMap::new();
+    let mut temps: Vec<HashMap<usize, IrValue>> = vec![HashMap::new(); cfgs.len()];
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (f, cfg) in cfgs.iter_mut().enumerate() {
+            for inst in cfg.blocks.iter_mut().flat_map(|b| b.body.iter_mut()) {
+                for val in inst.uses_mut() {
+                    let known = match val {
+                        IrValue::Temp(t) => temps[f].get(t),
+                        IrValue::Identifier(id) => names.get(id),
+                        _ => None,
+                    };
+                    if let Some(known) = known {
+                        *val = known.clone();
+                        changed = true;
+                    }
+                }
+
+                if let Some(folded) = fold(inst) {
+                    *inst = folded;
+                    changed = true;
+                }
+
+                let (dest, val) = match inst {
+                    IrInst::Bind { id, ty: _, val } if !unstable.contains(id) => {
+                        (IrValue::Identifier(id.clone()), val.clone())
+                    }
+                    IrInst::Copy { dest: dest @ IrValue::Temp(_), ty: _, val } => (dest.clone(), val.clone()),
+                    // a phi is constant when every way into its block brings the same constant
+                    IrInst::Phi { dest, ty: _, args } if !args.is_empty() && args.iter().all(|(_, v)| *v == args[0].1) => {
+                        (dest.clone(), args[0].1.clone())
+                    }
+                    _ => continue,
+                };
+                if !is_constant(&val) {
+                    continue;
+                }
+                let fresh = match dest {
+                    IrValue::Temp(t) => temps[f].insert(t, val).is_none(),
+                    IrValue::Identifier(id) => names.insert(id, val).is_none(),
+                    _ => false,
+                };
+                changed |= fresh;
+            }
+        }
+    }
+}
+
+/// Turns branches on a constant condition into jumps and removes the blocks that can no
+/// longer be reached. Returns whether any branch was turned.
+pub(crate) fn fold_branches(cfgs: &mut [Cfg]) -> bool {
+    let mut folded = false;
+    for cfg in cfgs.iter_mut() {
+        let mut changed = false;
+        for b in 0..cfg.blocks.len() {
+            let Some(IrInst::Branch { cond: IrValue::ConstBool(cond), then, otherwise }) = cfg.blocks[b].body.last() else {
+                continue;
+            };
+            let (taken, skipped) = if *cond { (*then, *otherwise) } else { (*otherwise, *then) };
+            *cfg.blocks[b].body.last_mut().unwrap() = IrInst::Jump { label: taken };
+
+            // both labels lead to the same block when there's only one successor
+            if taken != skipped {
+                let skipped = cfg.blocks.iter().position(|blk| blk.label == Some(skipped)).unwrap();
+                cfg.remove_edge(b, skipped);
+            }
+            changed = true;
+        }
+        if changed {
+            cfg.remove_unreachable();
+            folded = true;
+        }
+    }
+    return folded;
+}
+
+/// Removes every instruction whose result is never read, walking each block backwards from
+/// what's live at its end so values only read by dead instructions go as well. In SSA form
+/// this also takes care of dead stores, since each store to a local is its own binding.
+///
+/// Calls are kept since they can have effects, and so are writes to globals that other
+/// functions can see. Integer arithmetic that can fail at runtime is kept too so removing it
+/// doesn't hide an error.
+pub(crate) fn eliminate_dead_code(cfgs: &mut [Cfg]) {
+    let mentioned: Vec<HashSet<String>> = cfgs
+        .iter()
+        .map(|c| ssa::identifiers(c.blocks.iter().flat_map(|b| &b.body)))
+        .collect();
+
+    for (f, cfg) in cfgs.iter_mut().enumerate() {
+        let shared: HashSet<&String> = mentioned
+            .iter()
+            .enumerate()
+            .filter(|(g, _)| *g != f)
+            .flat_map(|(_, m)| m.iter())
+            .collect();
+
+        // removing one instruction can make the ones feeding it dead, so go until nothing changes
+        let mut removed = true;
+        while removed {
+            removed = false;
+            let liveness = Liveness::compute(cfg);
+            for (b, block) in cfg.blocks.iter_mut().enumerate() {
+                let mut live = liveness.live_out[b].clone();
+                let mut keep = vec![true; block.body.len()];
+                for (i, inst) in block.body.iter().enumerate().rev() {
+                    let dest = inst.dest().as_ref().and_then(Var::of);
+                    if let Some(var) = &dest
+                        && !live.contains(var)
+                        && is_pure(inst)
+                        && !matches!(var, Var::Named(id) if shared.contains(id))
+                    {
+                        keep[i] = false;
+                        removed = true;
+                        continue;
+                    }
+
+                    if let Some(var) = dest {
+                        live.remove(&var);
+                    }
+                    if !matches!(inst, IrInst::Phi { dest: _, ty: _, args: _ }) {
+                        live.extend(inst.uses().into_iter().filter_map(Var::of));
+                    }
+                }
+
+                let mut keep = keep.into_iter();
+                block.body.retain(|_| keep.next().unwrap());
+            }
+        }
+    }
+}
+
+/// Whether an instruction can be removed when nothing reads its result.
+fn is_pure(inst: &IrInst) -> bool {
+    return match inst {
+        IrInst::Bind { id: _, ty: _, val: _ } |
+        IrInst::Store { id: _, val: _ } |
+        IrInst::Copy { dest: _, ty: _, val: _ } |
+        IrInst::Phi { dest: _, ty: _, args: _ } => true,
+        IrInst::BinOp { dest: _, ty, lhs: _, op, rhs: _ } => {
+            *ty != IrType::Int || !matches!(
+                op,
+                TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash |
+                TokenKind::Percent | TokenKind::StarStar | TokenKind::LessLess | TokenKind::GreaterGreater
+            )
+        }
+        IrInst::UnOp { dest: _, ty, op, val: _ } => !(*ty == IrType::Int && *op == TokenKind::Minus),
+        _ => false,
+    };
+}
+
+/// Evaluates an operation whose operands are all constants, returning a copy of the result.
+fn fold(inst: &IrInst) -> Option<IrInst> {
+    let (dest, ty, val) = match inst {
+        IrInst::BinOp { dest, ty, lhs, op, rhs } => {
+            let val = Value::binary(*op, to_value(lhs)?, to_value(rhs)?).ok()?;
+            (dest, ty, val)
+        }
+        IrInst::UnOp { dest, ty, op, val } => {
+            let val = Value::unary(*op, to_value(val)?).ok()?;
+            (dest, ty, val)
+        }
+        _ => return None,
+    };
+    return Some(IrInst::Copy { dest: dest.clone(), ty: ty.clone(), val: from_value(val)? });
+}
+
+fn is_constant(val: &IrValue) -> bool {
+    return !matches!(val, IrValue::Temp(_) | IrValue::Identifier(_));
+}
+
+fn to_value(val: &IrValue) -> Option<Value> {
+    let v = match val {
+        IrValue::ConstInt(n) => Value::Integer(*n),
+        IrValue::ConstFloat(n) => Value::Float(*n),
+        IrValue::ConstStr(s) => Value::String(s.clone()),
+        IrValue::ConstChar(c) => Value::Char(*c),
+        IrValue::ConstBool(b) => Value::Bool(*b),
+        IrValue::Nil => Value::Nil,
+        IrValue::Temp(_) | IrValue::Identifier(_) => return None,
+    };
+    return Some(v);
+}
+
+fn from_value(val: Value) -> Option<IrValue> {
+    let v = match val {
+        Value::Integer(n) => IrValue::ConstInt(n),
+        Value::Float(n) => IrValue::ConstFloat(n),
+        Value::String(s) => IrValue::ConstStr(s),
+        Value::Char(c) => IrValue::ConstChar(c),
+        Value::Bool(b) => IrValue::ConstBool(b),
+        Value::Nil => IrValue::Nil,
+        Value::Function(_) | Value::Native(_) => return None,
+    };
+    return Some(v);
+}
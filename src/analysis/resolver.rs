@@ -1,16 +1,92 @@
-use std::collections::{HashMap, LinkedList};
-use crate::{common::{ast::NodeKind, error::{ChaoError, ErrorBase, ErrorSeverity}, token::TokenKind}, Node};
-use super::irgen::{IrInst, IrValue};
+use std::{collections::{HashMap, LinkedList}, fmt::Display};
+use crate::{common::{ast::{NodeKind, Param, TypeName}, error::{ChaoError, ErrorBase, ErrorSeverity}, span::Span, token::TokenKind}, runtime::value::Value, Node};
 
-static PLUS: &'static TokenKind = &TokenKind::Plus;
-static MINUS: &'static TokenKind = &TokenKind::Minus;
+/// How long an unknown name has to be before the closest known name is offered as a fix.
+const MIN_FIX_LEN: usize = 3;
+
+fn build_type_table() -> HashMap<(Type, TokenKind, Type), Type> {
+    let mut t = HashMap::<(Type, TokenKind, Type), Type>::new();
+
+    // Integers
+    let arithmetic = [
+        TokenKind::Plus,
+        TokenKind::Minus,
+        TokenKind::Star,
+        TokenKind::Slash,
+        TokenKind::Percent,
+        TokenKind::StarStar,
+    ];
+    let bitwise = [
+        TokenKind::Amp,
+        TokenKind::Pipe,
+        TokenKind::Caret,
+        TokenKind::LessLess,
+        TokenKind::GreaterGreater,
+    ];
+    let comparison = [
+        TokenKind::EqualEqual,
+        TokenKind::BangEqual,
+        TokenKind::Less,
+        TokenKind::LessEqual,
+        TokenKind::Greater,
+        TokenKind::GreaterEqual,
+    ];
+
+    for op in arithmetic.iter().chain(bitwise.iter()) {
+        t.insert((Type::Integer, *op, Type::Integer), Type::Integer);
+    }
+    for op in comparison {
+        t.insert((Type::Integer, op, Type::Integer), Type::Bool);
+    }
+
+    // Floats
+    for op in arithmetic {
+        t.insert((Type::Float, op, Type::Float), Type::Float);
+    }
+    for op in comparison {
+        t.insert((Type::Float, op, Type::Float), Type::Bool);
+    }
+
+    // Characters
+    for op in comparison {
+        t.insert((Type::Char, op, Type::Char), Type::Bool);
+    }
+
+    // Strings
+    t.insert((Type::String, TokenKind::Plus, Type::String), Type::String);
+    for op in comparison {
+        t.insert((Type::String, op, Type::String), Type::Bool);
+    }
+
+    // Booleans
+    t.insert((Type::Bool, TokenKind::AmpAmp, Type::Bool), Type::Bool);
+    t.insert((Type::Bool, TokenKind::PipePipe, Type::Bool), Type::Bool);
+    t.insert((Type::Bool, TokenKind::EqualEqual, Type::Bool), Type::Bool);
+    t.insert((Type::Bool, TokenKind::BangEqual, Type::Bool), Type::Bool);
+
+    // Nil
+    t.insert((Type::Nil, TokenKind::EqualEqual, Type::Nil), Type::Bool);
+    t.insert((Type::Nil, TokenKind::BangEqual, Type::Nil), Type::Bool);
+
+    return t;
+}
+
+fn build_unary_table() -> HashMap<(TokenKind, Type), Type> {
+    let mut t = HashMap::<(TokenKind, Type), Type>::new();
 
-fn build_type_table() -> HashMap<(Type, &'static TokenKind, Type), Type> {
-    let mut t = HashMap::<(Type, &'static TokenKind, Type), Type>::new();
-    
     // Integers
-    t.insert((Type::Integer, PLUS, Type::Integer), Type::Integer);
-    t.insert((Type::Integer, PLUS, Type::Void), Type::Integer);
+    t.insert((TokenKind::Minus, Type::Integer), Type::Integer);
+    t.insert((TokenKind::Tilde, Type::Integer), Type::Integer);
+    t.insert((TokenKind::PlusPlus, Type::Integer), Type::Integer);
+    t.insert((TokenKind::MinusMinus, Type::Integer), Type::Integer);
+
+    // Floats
+    t.insert((TokenKind::Minus, Type::Float), Type::Float);
+    t.insert((TokenKind::PlusPlus, Type::Float), Type::Float);
+    t.insert((TokenKind::MinusMinus, Type::Float), Type::Float);
+
+    // Booleans
+    t.insert((TokenKind::Bang, Type::Bool), Type::Bool);
 
     return t;
 }
@@ -18,27 +94,137 @@ fn build_type_table() -> HashMap<(Type, &'static TokenKind, Type), Type> {
 #[derive(PartialEq, Eq, Hash, Clone)]
 enum Type {
     Integer,
+    Float,
     String,
+    Char,
+    Bool,
+    /// The type of the `nil` literal
+    Nil,
+    /// Either a value of the inner type or `nil`, written `int?`
+    Optional(Box<Type>),
     Void,
+    /// Accepts an argument of any type, only used by builtin parameters
+    Any,
+    Function {
+        params: Vec<Type>,
+        ret: Box<Type>,
+    },
 }
 
-#[derive(PartialEq, Eq, Hash, Clone)]
+impl Type {
+    /// Maps the name of a type written in the source to the type.
+    fn from_name(name: &str) -> Option<Type> {
+        let ty = match name {
+            "int" => Type::Integer,
+            "float" => Type::Float,
+            "str" => Type::String,
+            "char" => Type::Char,
+            "bool" => Type::Bool,
+            "void" => Type::Void,
+            _ => return None,
+        };
+        return Some(ty);
+    }
+
+    /// Whether a value of type `found` can be stored where a value of this type is expected.
+    fn accepts(&self, found: &Type) -> bool {
+        match self {
+            Type::Any => *found != Type::Void,
+            Type::Optional(inner) => {
+                *found == Type::Nil || **inner == *found || self == found
+            }
+            _ => self == found,
+        }
+    }
+
+    /// Optionals can be compared against `nil` and against values of their inner type with
+    /// `==` and `!=`, which the type table can't express since the inner type varies.
+    fn optional_eq(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Optional(_), _) => self.accepts(other),
+            (_, Type::Optional(_)) => other.accepts(self),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Type {
+    /// Writes the type the way it's written in the source, functions as `fn(int): str`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Integer => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "str"),
+            Type::Char => write!(f, "char"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Optional(inner) => write!(f, "{}?", inner),
+            Type::Void => write!(f, "void"),
+            Type::Any => write!(f, "any"),
+            Type::Function { params, ret } => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                match **ret {
+                    Type::Void => write!(f, "fn({})", params.join(", ")),
+                    _ => write!(f, "fn({}): {}", params.join(", "), ret),
+                }
+            }
+        }
+    }
+}
+
+/// Whether every path through `node` ends in a `return`. Loops without a condition only
+/// finish through `break`, so they count as returning when they contain no `break`.
+fn always_returns(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::StmtReturn { val: _ } => true,
+        NodeKind::StmtBlock { body } => body.iter().any(always_returns),
+        NodeKind::StmtIf { cond: _, then, otherwise: Some(otherwise) } => {
+            always_returns(then) && always_returns(otherwise)
+        }
+        NodeKind::StmtLoop { body } => !contains_break(body),
+        _ => false,
+    }
+}
+
+/// Whether a loop body contains a `break` that belongs to that loop.
+fn contains_break(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::StmtBreak => true,
+        NodeKind::StmtBlock { body } => body.iter().any(contains_break),
+        NodeKind::StmtIf { cond: _, then, otherwise } => {
+            contains_break(then) || otherwise.as_ref().is_some_and(|o| contains_break(o))
+        }
+        _ => false,
+    }
+}
+
+#[derive(PartialEq, Clone)]
 struct Variable {
     id: String,
     ty: Type,
     mutable: bool,
+    /// Where the binding is declared, builtins don't have one
+    span: Option<Span>,
+    /// The value of a constant bound to a constant expression
+    value: Option<Value>,
+    /// Whether anything has read this since it was declared
+    used: bool,
 }
 
 impl Variable {
-    pub(self) fn new(id: String, ty: Type, mutable: bool) -> Variable {
+    pub(self) fn new(id: String, ty: Type, mutable: bool, span: Option<Span>) -> Variable {
         return Variable {
             id,
             ty,
             mutable,
+            span,
+            value: None,
+            used: false,
         };
     }
 }
 
+#[derive(Clone)]
 struct Scope {
     variables: HashMap<String, Variable>,
 }
@@ -59,130 +245,636 @@ impl Scope {
     }
 }
 
+/// A copy of the global declarations, used by the REPL to undo an input that failed.
+pub(crate) struct Checkpoint(Scope);
+
 pub(crate) struct Resolver {
     scopes: LinkedList<Scope>,
-    types: HashMap<(Type, &'static TokenKind, Type), Type>,
-    temps: Vec<IrValue>,
+    types: HashMap<(Type, TokenKind, Type), Type>,
+    unary_types: HashMap<(TokenKind, Type), Type>,
+    loop_depth: usize,
+    /// Return type of the function being resolved, `None` at the top level
+    ret_ty: Option<Type>,
+    /// Bindings in scopes that have been closed without ever being read, as where they're
+    /// declared, their name and the message to warn with
+    unused: Vec<(Span, String, &'static str)>,
 }
 
 impl Resolver {
     pub(crate) fn new() -> Resolver {
+        let mut globals = Scope::new();
+
+        // builtins are declared without a location in the source
+        let print_ty = Type::Function { params: vec![Type::Any], ret: Box::new(Type::Void) };
+        globals.store("print".to_string(), Variable::new("print".to_string(), print_ty, false, None));
+
         let mut scopes = LinkedList::<Scope>::new();
-        scopes.push_front(Scope::new());
+        scopes.push_front(globals);
         
         return Resolver {
             scopes,
-            types: build_type_table(), 
-            temps: vec![],
+            types: build_type_table(),
+            unary_types: build_unary_table(),
+            loop_depth: 0,
+            ret_ty: None,
+            unused: vec![],
         };
     }
 
-    pub(crate) fn resolve<'a>(&'a mut self, ast: Vec<Node>) -> Result<(), Vec<ChaoError<'a>>> {
+    pub(crate) fn resolve<'a>(&mut self, ast: &[Node]) -> Result<(), Vec<ChaoError<'a>>> {
         let mut errs = Vec::<ChaoError>::new();
 
         for node in ast {
             self.resolve_node(node).unwrap_or_else(|e| {
-                println!("got and error yeahhhh!");
                 errs.push(e);
             });
         }
 
-        return Err(errs);
+        if !errs.is_empty() {
+            return Err(errs);
+        }
+        return Ok(());
     }
 
-    fn resolve_node<'a>(&mut self, node: Node) -> Result<(), ChaoError<'a>> {
-        match node.kind {
-            NodeKind::StmtConstant { id, val } => self.def_const_id(id, *val),
-            NodeKind::StmtVariable { id, val } => self.def_variable_id(id, *val),
-            NodeKind::StmtExpression { expr } => self.resolve_node(*expr),
-            NodeKind::ExprAssignment { id, op: _, val } => self.check_assignment(*id, *val),
-            _ => todo!("resolve not assign or bind")
+    /// Resolves a lone expression typed into the REPL, returning whether it produces a value.
+    pub(crate) fn resolve_expression<'a>(&mut self, expr: &Node) -> Result<bool, ChaoError<'a>> {
+        let ty = self.type_res(expr)?;
+        self.fold(expr)?;
+        return Ok(ty != Type::Void);
+    }
+
+    /// Takes the warnings found so far, in source order. With `globals` the top level scope
+    /// is checked for unused bindings too, which only makes sense once the whole program has
+    /// been resolved.
+    pub(crate) fn take_warnings<'a>(&mut self, globals: bool) -> Vec<ChaoError<'a>> {
+        if globals {
+            let scope = self.scopes.back().unwrap().clone();
+            self.warn_unused(&scope);
+        }
+        let mut unused = std::mem::take(&mut self.unused);
+        unused.sort();
+        return unused
+            .into_iter()
+            .map(|(span, id, msg)| {
+                let eb = ErrorBase::UnusedBinding { span };
+                ChaoError::new(eb, ErrorSeverity::Warning, true, msg)
+                    .with_help(format!("if this is on purpose, name it '_{}' instead", id))
+            })
+            .collect();
+    }
+
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        return Checkpoint(self.scopes.back().unwrap().clone());
+    }
+
+    pub(crate) fn restore(&mut self, checkpoint: Checkpoint) {
+        *self.scopes.back_mut().unwrap() = checkpoint.0;
+    }
+
+    fn resolve_node<'a>(&mut self, node: &Node) -> Result<(), ChaoError<'a>> {
+        match &node.kind {
+            NodeKind::StmtConstant { id, val } => self.def_const_id(node, id, val),
+            NodeKind::StmtVariable { id, val } => self.def_variable_id(node, id, val),
+            NodeKind::StmtExpression { expr } => {
+                self.type_res(expr)?;
+                self.fold(expr)?;
+                return Ok(());
+            }
+            NodeKind::StmtBlock { body } => self.resolve_block(body),
+            NodeKind::StmtIf { cond, then, otherwise } => {
+                self.check_condition(cond)?;
+                self.resolve_node(then)?;
+                if let Some(otherwise) = otherwise {
+                    self.resolve_node(otherwise)?;
+                }
+                return Ok(());
+            }
+            NodeKind::StmtWhile { cond, body } => {
+                self.check_condition(cond)?;
+                self.loop_depth += 1;
+                let res = self.resolve_node(body);
+                self.loop_depth -= 1;
+                return res;
+            }
+            NodeKind::StmtLoop { body } => {
+                self.loop_depth += 1;
+                let res = self.resolve_node(body);
+                self.loop_depth -= 1;
+                return res;
+            }
+            NodeKind::StmtFunction { id, params, ret, body } => self.def_function(node, id, params, ret, body),
+            NodeKind::StmtReturn { val } => self.check_return(node, val.as_deref()),
+            NodeKind::StmtBreak | NodeKind::StmtContinue => {
+                if self.loop_depth == 0 {
+                    let eb = ErrorBase::OutsideLoop { span: node.span };
+                    return Err(
+                        ChaoError::new(eb, ErrorSeverity::Error, false, "'break' and 'continue' can only be used inside a loop")
+                    );
+                }
+                return Ok(());
+            }
+            _ => Err(unsupported(node, "this statement is not supported yet")),
+        }
+    }
+
+    /// Resolves a list of statements in a new scope that is popped again afterwards,
+    /// regardless of whether resolution succeeded.
+    fn resolve_block<'a>(&mut self, body: &[Node]) -> Result<(), ChaoError<'a>> {
+        self.scopes.push_front(Scope::new());
+        let res = body.iter().try_for_each(|n| self.resolve_node(n));
+        let scope = self.scopes.pop_front().unwrap();
+        if res.is_ok() {
+            self.warn_unused(&scope);
+        }
+        return res;
+    }
+
+    /// Looks up an identifier starting from the innermost scope.
+    fn lookup(&self, id: &String) -> Option<&Variable> {
+        return self.scopes.iter().find_map(|s| s.get(id));
+    }
+
+    /// Reports that `id` isn't in scope, suggesting the name in scope closest to it when
+    /// there's one that's only a few edits away.
+    fn unknown_identifier<'a>(&self, node: &Node, id: &String) -> ChaoError<'a> {
+        let eb = ErrorBase::UnknownIdentifier { span: node.span };
+        let msg = format!("'{}' could not be found in this scope", id);
+        let err = ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+
+        let limit = (id.chars().count() / 3).max(1);
+        let mut closest: Vec<(usize, &String)> = self.scopes
+            .iter()
+            .flat_map(|s| s.variables.keys())
+            .map(|name| (distance(id, name), name))
+            .filter(|(d, _)| *d <= limit)
+            .collect();
+        closest.sort();
+        closest.dedup();
+        let best: Vec<&String> = closest
+            .iter()
+            .take_while(|(d, _)| *d == closest[0].0)
+            .map(|(_, name)| *name)
+            .collect();
+
+        // short names are a letter or two away from most others, so those are only pointed out
+        return match best.as_slice() {
+            [] => err,
+            [name] if id.chars().count() >= MIN_FIX_LEN => {
+                err.with_fix(node.span, name.to_string(), format!("'{}' has a similar name", name))
+            }
+            [name] => err.with_help(format!("'{}' has a similar name", name)),
+            names => {
+                let names: Vec<String> = names.iter().map(|n| format!("'{}'", n)).collect();
+                err.with_help(format!("{} have similar names", names.join(", ")))
+            }
+        };
+    }
+
+    /// Looks up an identifier whose value is being read, marking it as used.
+    fn read(&mut self, id: &String) -> Option<&Variable> {
+        let v = self.scopes.iter_mut().find_map(|s| s.variables.get_mut(id))?;
+        v.used = true;
+        return Some(v);
+    }
+
+    /// Warns about every constant and variable in `scope` that was never read. Functions are
+    /// left out, and so is anything starting with an underscore.
+    fn warn_unused(&mut self, scope: &Scope) {
+        for v in scope.variables.values() {
+            if v.used || v.id.starts_with('_') || matches!(v.ty, Type::Function { params: _, ret: _ }) {
+                continue;
+            }
+            let msg = if v.mutable { "this variable is never read" } else { "this constant is never read" };
+            if let Some(span) = v.span {
+                self.unused.push((span, v.id.clone(), msg));
+            }
+        }
+    }
+
+    fn check_condition<'a>(&mut self, cond: &Node) -> Result<(), ChaoError<'a>> {
+        let ty = self.type_res(cond)?;
+        if ty != Type::Bool {
+            let eb = ErrorBase::IncompatibleTypes { span: cond.span };
+            let msg = format!("condition must be a 'bool', found '{}'", ty);
+            return Err(ChaoError::new(eb, ErrorSeverity::Error, false, msg));
         }
+        self.fold(cond)?;
+        return Ok(());
     }
 }
 
 impl Resolver {
-    fn def_const_id<'a>(&mut self, id: String, val: Node) -> Result<(), ChaoError<'a>> {
-        let ty = self.type_res(val)?;
-        let variable = Variable::new(id.clone(), ty, false);
-        self.scopes.front_mut().unwrap().store(id, variable);
-        return Ok(())
+    fn def_const_id<'a>(&mut self, stmt: &Node, id: &String, val: &Node) -> Result<(), ChaoError<'a>> {
+        let ty = self.binding_type(val)?;
+        let mut variable = Variable::new(id.clone(), ty, false, Some(stmt.span.prefix(id.len())));
+        variable.value = self.fold(val)?;
+        return self.declare(id, variable);
+    }
+
+    fn def_variable_id<'a>(&mut self, stmt: &Node, id: &String, val: &Node) -> Result<(), ChaoError<'a>> {
+        let ty = self.binding_type(val)?;
+        self.fold(val)?;
+        let variable = Variable::new(id.clone(), ty, true, Some(stmt.span.prefix(id.len())));
+        return self.declare(id, variable);
+    }
+
+    /// Declares a binding in the innermost scope, which mustn't have one by that name yet.
+    /// Functions could otherwise see either of the two depending on the engine.
+    fn declare<'a>(&mut self, id: &String, variable: Variable) -> Result<(), ChaoError<'a>> {
+        let scope = self.scopes.front_mut().unwrap();
+        let Some(first) = scope.get(id) else {
+            scope.store(id.clone(), variable);
+            return Ok(());
+        };
+
+        let eb = ErrorBase::DuplicateDeclaration { span: variable.span.unwrap_or_default() };
+        let err = ChaoError::new(eb, ErrorSeverity::Error, false, format!("'{}' is already declared in this scope", id));
+        let err = match first.span {
+            Some(span) => err.with_label(span, "first declared here"),
+            None => err.with_note(format!("'{}' is built in", id)),
+        };
+        return Err(match first.mutable {
+            true => err.with_help(format!("assign the new value with '{} -> ...;' instead", id)),
+            false => err.with_help("give the new binding a different name"),
+        });
     }
 
-    fn def_variable_id<'a>(&mut self, id: String, val: Node) -> Result<(), ChaoError<'a>> {
+    /// Resolves the type of a value being bound to a name, rejecting calls that return nothing.
+    fn binding_type<'a>(&mut self, val: &Node) -> Result<Type, ChaoError<'a>> {
         let ty = self.type_res(val)?;
-        let variable = Variable::new(id.clone(), ty, true);
-        self.scopes.front_mut().unwrap().store(id, variable);
-        return Ok(())
+        if ty == Type::Void {
+            let eb = ErrorBase::IncompatibleTypes { span: val.span };
+            return Err(
+                ChaoError::new(eb, ErrorSeverity::Error, false, "cannot bind the result of a function that returns nothing")
+            );
+        }
+        return Ok(ty);
     }
 
-    fn check_assignment<'a>(&mut self, variable: Node, val: Node) -> Result<(), ChaoError<'a>> {
-        let line = val.line;
-        let offset = val.offset;
+    fn type_name<'a>(&self, ty: &TypeName) -> Result<Type, ChaoError<'a>> {
+        match Type::from_name(&ty.name) {
+            Some(Type::Void) if ty.optional => {
+                let eb = ErrorBase::UnknownType { span: ty.span };
+                return Err(
+                    ChaoError::new(eb, ErrorSeverity::Error, false, "'void' cannot be made optional")
+                );
+            }
+            Some(t) if ty.optional => Ok(Type::Optional(Box::new(t))),
+            Some(t) => Ok(t),
+            None => {
+                let eb = ErrorBase::UnknownType { span: ty.span };
+                let msg = format!("'{}' is not the name of a type", ty.name);
+                return Err(
+                    ChaoError::new(eb, ErrorSeverity::Error, false, msg)
+                        .with_note("the types are 'int', 'float', 'str', 'char', 'bool' and 'void'")
+                );
+            }
+        }
+    }
 
-        match variable.kind {
-            NodeKind::LiteralIdent { id } => {
-                let mutable: bool = self.scopes.front().unwrap().get(&id).is_some_and(|x| x.mutable);
-                let var_ty: Option<Type> = self.scopes.front().unwrap().get(&id).and_then(|x| Some(x.ty.clone()));
+    fn def_function<'a>(
+        &mut self,
+        stmt: &Node,
+        id: &String,
+        params: &[Param],
+        ret: &Option<TypeName>,
+        body: &Node
+    ) -> Result<(), ChaoError<'a>> {
+        let mut param_tys = Vec::<Type>::new();
+        for p in params {
+            let ty = self.type_name(&p.ty)?;
+            if ty == Type::Void {
+                let eb = ErrorBase::IncompatibleTypes { span: p.ty.span };
+                return Err(
+                    ChaoError::new(eb, ErrorSeverity::Error, false, "parameters cannot be void")
+                );
+            }
+            param_tys.push(ty);
+        }
+        let ret_ty = match ret {
+            Some(t) => self.type_name(t)?,
+            None => Type::Void,
+        };
+
+        // declare the function before its body so it can call itself
+        let fn_ty = Type::Function { params: param_tys.clone(), ret: Box::new(ret_ty.clone()) };
+        let variable = Variable::new(id.clone(), fn_ty, false, Some(stmt.span));
+        self.declare(id, variable)?;
+
+        self.scopes.push_front(Scope::new());
+        let mut res = Ok(());
+        for (p, ty) in params.iter().zip(param_tys) {
+            res = res.and_then(|_| self.declare(&p.id, Variable::new(p.id.clone(), ty, false, Some(p.span))));
+        }
+        let enclosing = self.ret_ty.replace(ret_ty.clone());
+        let res = res.and_then(|_| self.resolve_node(body));
+        self.ret_ty = enclosing;
+        self.scopes.pop_front();
+        res?;
+
+        if ret_ty != Type::Void && !always_returns(body) {
+            let eb = ErrorBase::MissingReturn { span: stmt.span };
+            return Err(
+                ChaoError::new(eb, ErrorSeverity::Error, false, "this function doesn't return a value on every path")
+                    .with_help(format!("end the function by returning a value of type '{}'", ret_ty))
+            );
+        }
+        return Ok(());
+    }
+
+    fn check_return<'a>(&mut self, stmt: &Node, val: Option<&Node>) -> Result<(), ChaoError<'a>> {
+        let expected = match &self.ret_ty {
+            Some(t) => t.clone(),
+            None => {
+                let eb = ErrorBase::OutsideFunction { span: stmt.span };
+                return Err(
+                    ChaoError::new(eb, ErrorSeverity::Error, false, "'return' can only be used inside a function")
+                );
+            }
+        };
+
+        let (found, span) = match val {
+            Some(v) => (self.type_res(v)?, v.span),
+            None => (Type::Void, stmt.span),
+        };
+
+        if !expected.accepts(&found) {
+            let eb = ErrorBase::IncompatibleTypes { span };
+            if expected == Type::Void {
+                let msg = "this function doesn't declare a return type";
+                return Err(
+                    ChaoError::new(eb, ErrorSeverity::Error, false, msg)
+                        .with_help(format!("declare one after the parameters, like '(...): {}'", found))
+                );
+            }
+            let msg = format!("expected the function to return '{}', found '{}'", expected, found);
+            return Err(ChaoError::new(eb, ErrorSeverity::Error, false, msg));
+        }
+        if let Some(v) = val {
+            self.fold(v)?;
+        }
+        return Ok(());
+    }
+
+    fn check_call<'a>(&mut self, call: &Node, callee: &Node, args: &[Node]) -> Result<Type, ChaoError<'a>> {
+        let callee_var = match &callee.kind {
+            NodeKind::LiteralIdent { id } => self.lookup(id).cloned(),
+            _ => None,
+        };
+
+        let (params, ret) = match callee_var.as_ref().map(|v| &v.ty) {
+            Some(Type::Function { params, ret }) => (params.clone(), ret.clone()),
+            Some(ty) => {
+                let eb = ErrorBase::NotCallable { span: callee.span };
+                let msg = format!("only functions can be called, found '{}'", ty);
+                return Err(ChaoError::new(eb, ErrorSeverity::Error, false, msg));
+            }
+            None => {
+                if let NodeKind::LiteralIdent { id } = &callee.kind {
+                    return Err(self.unknown_identifier(callee, id));
+                }
+                let eb = ErrorBase::NotCallable { span: callee.span };
+                return Err(
+                    ChaoError::new(eb, ErrorSeverity::Error, false, "only functions can be called")
+                );
+            }
+        };
 
-                // if !mutable {
-                //     todo!("reassign to a constant error");
-                // }
+        if params.len() != args.len() {
+            let eb = ErrorBase::ArgumentCount { span: call.span };
+            let noun = if params.len() == 1 { "argument" } else { "arguments" };
+            let msg = format!("expected {} {} for this function, found {}", params.len(), noun, args.len());
+            let mut err = ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+            if let Some(v) = &callee_var {
+                err = err.with_note(format!("'{}' has the type '{}'", v.id, v.ty));
+            }
+            return Err(err);
+        }
 
-                match var_ty {
-                    Some(t) => {
+        for (arg, param) in args.iter().zip(params.iter()) {
+            let arg_ty = self.type_res(arg)?;
+            if !param.accepts(&arg_ty) {
+                let eb = ErrorBase::ArgumentType { span: arg.span };
+                let msg = format!("expected '{}' for this argument, found '{}'", param, arg_ty);
+                return Err(ChaoError::new(eb, ErrorSeverity::Error, false, msg));
+            }
+        }
+
+        return Ok(*ret);
+    }
+
+    fn check_assignment<'a>(&mut self, variable: &Node, val: &Node) -> Result<Type, ChaoError<'a>> {
+        match &variable.kind {
+            NodeKind::LiteralIdent { id } => {
+                let var: Option<Variable> = self.lookup(id).cloned();
+                self.check_mutable(variable, id)?;
+
+                match var {
+                    Some(var) => {
+                        let t = var.ty;
                         let v_ty = self.type_res(val)?;
                         
                         // (todo) find a way to implement type coercion here and implicit casts
-                        if v_ty != t {
-                            let eb = ErrorBase::IncompatibleTypes { line, offset };
-                            return Err(
-                                ChaoError::new(eb, ErrorSeverity::Error, false, "cannot reassign '{}' to a different type")
-                            );
+                        if !t.accepts(&v_ty) {
+                            let eb = ErrorBase::IncompatibleTypes { span: val.span };
+                            let msg = format!("cannot reassign '{}' from '{}' to '{}'", id, t, v_ty);
+                            let mut err = ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+                            if let Some(span) = var.span {
+                                err = err.with_label(span, format!("declared as '{}' here", t));
+                            }
+                            return Err(err);
                         }
 
-                        return Ok(());
+                        return Ok(t);
                     } 
-                    None => {
-                        let eb = ErrorBase::UnknownIdentifier { line, offset };
-                        return Err(
-                            ChaoError::new(eb, ErrorSeverity::Error, false, "identifier could not be found in this scope")
-                        );
-                    }
+                    None => Err(self.unknown_identifier(variable, id)),
                 }
             }
-            _ => todo!("check assignment non-ident"),
+            _ => Err(unsupported(variable, "only identifiers can be assigned to")),
+        }
+    }
+
+    /// Returns an error pointing at both the write and the declaration when `id` names a
+    /// constant. Unknown identifiers are left for the caller to report.
+    fn check_mutable<'a>(&self, write: &Node, id: &String) -> Result<(), ChaoError<'a>> {
+        match self.lookup(id) {
+            Some(v) if !v.mutable => {
+                let eb = ErrorBase::ReassignConstant { span: write.span };
+                let err = ChaoError::new(eb, ErrorSeverity::Error, false, format!("cannot assign to the constant '{}'", id));
+
+                // builtins are declared without a location in the source
+                return Err(match v.span {
+                    Some(span) => err
+                        .with_label(span, "declared as a constant here")
+                        .with_help(format!("declare it with 'let {} = ...;' to make it mutable", id)),
+                    None => err.with_note(format!("'{}' is built in", id)),
+                });
+            }
+            _ => Ok(()),
         }
     }
 }
 
 impl Resolver {
-    fn type_res<'a>(&mut self, val: Node) -> Result<Type, ChaoError<'a>> {
-        match val.kind {
+    fn type_res<'a>(&mut self, val: &Node) -> Result<Type, ChaoError<'a>> {
+        match &val.kind {
             NodeKind::LiteralStr { val: _ } => Ok(Type::String),
-            NodeKind::LiteralInt { val: _ } => Ok(Type::Integer), 
-            NodeKind::ExprBinary { lhs, op, rhs } => {
-                let line = lhs.line;
-                let offset = lhs.offset;
-
-                let lhs_ty = self.type_res(*lhs)?;
-                let rhs_ty = self.type_res(*rhs)?;
-                let oper = match op {
-                    TokenKind::Plus => PLUS,
-                    _ => todo!()
-                };
+            NodeKind::LiteralInt { val: _ } => Ok(Type::Integer),
+            NodeKind::LiteralFloat { val: _ } => Ok(Type::Float),
+            NodeKind::LiteralChar { val: _ } => Ok(Type::Char),
+            NodeKind::LiteralTrue | NodeKind::LiteralFalse => Ok(Type::Bool),
+            NodeKind::LiteralNil => Ok(Type::Nil),
+            NodeKind::LiteralIdent { id } => {
+                match self.read(id) {
+                    Some(Variable { ty: Type::Function { params: _, ret: _ }, .. }) => {
+                        let eb = ErrorBase::IncompatibleTypes { span: val.span };
+                        return Err(
+                            ChaoError::new(eb, ErrorSeverity::Error, false, "functions can only be called, not used as values")
+                                .with_help(format!("call it like '{}(...)'", id))
+                        );
+                    }
+                    Some(v) => Ok(v.ty.clone()),
+                    None => Err(self.unknown_identifier(val, id)),
+                }
+            }
+            NodeKind::ExprAssignment { id, op: _, val } => self.check_assignment(id, val),
+            NodeKind::ExprCall { callee, args } => self.check_call(val, callee, args),
+            NodeKind::ExprUnary { op, operand, postfix: _ } => {
+                // increments and decrements write back to their operand
+                if *op == TokenKind::PlusPlus || *op == TokenKind::MinusMinus {
+                    match &operand.kind {
+                        NodeKind::LiteralIdent { id } => self.check_mutable(operand, id)?,
+                        _ => {
+                            let eb = ErrorBase::IncompatibleTypes { span: val.span };
+                            return Err(
+                                ChaoError::new(eb, ErrorSeverity::Error, false, "can only increment or decrement an identifier")
+                            );
+                        }
+                    }
+                }
 
-                match self.types.get(&(lhs_ty, oper, rhs_ty)) {
+                let operand_ty = self.type_res(operand)?;
+                match self.unary_types.get(&(*op, operand_ty.clone())) {
                     Some(result_ty) => return Ok(result_ty.clone()),
                     None => {
-                        let eb = ErrorBase::IncompatibleTypes { line, offset };
-                        return Err(
-                            ChaoError::new(eb, ErrorSeverity::Error, false, "invalid types for this operator")
-                        );
+                        let eb = ErrorBase::IncompatibleTypes { span: val.span };
+                        let msg = format!("cannot apply '{}' to '{}'", op.symbol().unwrap_or("?"), operand_ty);
+                        return Err(ChaoError::new(eb, ErrorSeverity::Error, false, msg));
                     }
                 }
             }
-            _ => todo!("type res non binary or int")
+            NodeKind::ExprBinary { lhs, op, rhs } => {
+                let lhs_ty = self.type_res(lhs)?;
+                let rhs_ty = self.type_res(rhs)?;
+                if (*op == TokenKind::EqualEqual || *op == TokenKind::BangEqual) && lhs_ty.optional_eq(&rhs_ty) {
+                    return Ok(Type::Bool);
+                }
+                match self.types.get(&(lhs_ty.clone(), *op, rhs_ty.clone())) {
+                    Some(result_ty) => return Ok(result_ty.clone()),
+                    None => {
+                        // both operands are underlined
+                        let eb = ErrorBase::IncompatibleTypes { span: val.span };
+                        let sym = op.symbol().unwrap_or("?");
+                        let msg = format!("cannot apply '{}' to '{}' and '{}'", sym, lhs_ty, rhs_ty);
+                        return Err(ChaoError::new(eb, ErrorSeverity::Error, false, msg));
+                    }
+                }
+            }
+            _ => Err(unsupported(val, "this expression is not supported yet")),
+        }
+    }
+}
+
+impl Resolver {
+    /// Evaluates the constant parts of an expression that has already been type checked,
+    /// using the same operators as the runtime. Returns the value if the whole expression is
+    /// constant, and an error for any operation on constants that is certain to fail, like an
+    /// overflow. Called once on every complete expression so each node is only visited once.
+    fn fold<'a>(&self, node: &Node) -> Result<Option<Value>, ChaoError<'a>> {
+        let val = match &node.kind {
+            NodeKind::LiteralInt { val } => Value::Integer(*val),
+            NodeKind::LiteralFloat { val } => Value::Float(*val),
+            NodeKind::LiteralStr { val } => Value::String(val.clone()),
+            NodeKind::LiteralChar { val } => Value::Char(*val),
+            NodeKind::LiteralTrue => Value::Bool(true),
+            NodeKind::LiteralFalse => Value::Bool(false),
+            NodeKind::LiteralNil => Value::Nil,
+            NodeKind::LiteralIdent { id } => return Ok(self.lookup(id).and_then(|v| v.value.clone())),
+            // only variables can be incremented, so these never have a known value
+            NodeKind::ExprUnary { op: TokenKind::PlusPlus | TokenKind::MinusMinus, operand: _, postfix: _ } => {
+                return Ok(None);
+            }
+            NodeKind::ExprUnary { op, operand, postfix: _ } => {
+                let Some(v) = self.fold(operand)? else {
+                    return Ok(None);
+                };
+                Value::unary(*op, v).map_err(|msg| arithmetic_error(node, msg))?
+            }
+            NodeKind::ExprBinary { lhs, op, rhs } => {
+                let l = self.fold(lhs)?;
+                let r = self.fold(rhs)?;
+
+                // dividing by a constant zero fails whatever the lhs turns out to be
+                if matches!(op, TokenKind::Slash | TokenKind::Percent) && r == Some(Value::Integer(0)) {
+                    return Err(arithmetic_error(node, "division by zero"));
+                }
+                let (Some(l), Some(r)) = (l, r) else {
+                    return Ok(None);
+                };
+                match (op, l, r) {
+                    (TokenKind::AmpAmp, Value::Bool(a), Value::Bool(b)) => Value::Bool(a && b),
+                    (TokenKind::PipePipe, Value::Bool(a), Value::Bool(b)) => Value::Bool(a || b),
+                    (op, l, r) => Value::binary(*op, l, r).map_err(|msg| arithmetic_error(node, msg))?,
+                }
+            }
+            NodeKind::ExprAssignment { id: _, op: _, val } => {
+                self.fold(val)?;
+                return Ok(None);
+            }
+            NodeKind::ExprCall { callee: _, args } => {
+                for arg in args {
+                    self.fold(arg)?;
+                }
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        };
+        return Ok(Some(val));
+    }
+}
+
+fn arithmetic_error<'a>(node: &Node, msg: &'static str) -> ChaoError<'a> {
+    let eb = ErrorBase::ArithmeticError { span: node.span };
+    return ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+}
+
+fn unsupported<'a>(node: &Node, msg: &'static str) -> ChaoError<'a> {
+    let eb = ErrorBase::UnsupportedConstruct { span: node.span };
+    return ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+}
+
+/// How many characters have to be inserted, removed, replaced or swapped with the next one
+/// to turn one name into the other.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // d[i][j] is the distance between the first i characters of a and the first j of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
         }
     }
-}
\ No newline at end of file
+    return d[a.len()][b.len()];
+}
@@ -0,0 +1,388 @@
+use std::collections::{ HashMap, HashSet };
+use crate::analysis::{
+    cfg::{ BasicBlock, Cfg },
+    irgen::{ IrFunction, IrInst, IrProgram, IrType, IrValue },
+    liveness::Liveness,
+};
+
+/// Something SSA construction renames, either a temporary or a binding. Unlike `IrValue`
+/// this can be used as a key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Var {
+    Temp(usize),
+    Named(String),
+}
+
+impl Var {
+    pub(crate) fn of(val: &IrValue) -> Option<Var> {
+        return match val {
+            IrValue::Temp(t) => Some(Var::Temp(*t)),
+            IrValue::Identifier(id) => Some(Var::Named(id.clone())),
+            _ => None,
+        };
+    }
+
+    pub(crate) fn value(&self) -> IrValue {
+        return match self {
+            Var::Temp(t) => IrValue::Temp(*t),
+            Var::Named(id) => IrValue::Identifier(id.clone()),
+        };
+    }
+}
+
+/// Converts every function to SSA form, where each value is written exactly once and phis
+/// merge values at the blocks where control flow joins.
+///
+/// Temporaries, parameters and bindings declared inside a function are renamed. Later
+/// definitions of a binding `x` become `x#1`, `x#2` and so on. Bindings that another function
+/// reads or writes are globals and keep going through `Bind` and `Store`. Unreachable blocks
+/// are removed since nothing could flow into them.
+pub(crate) fn construct(program: &IrProgram) -> Vec<Cfg> {
+    let mentioned: Vec<HashSet<String>> = program.functions.iter().map(|f| identifiers(&f.body)).collect();
+
+    let mut cfgs = vec![];
+    for (i, func) in program.functions.iter().enumerate() {
+        let shared: HashSet<&String> = mentioned
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .flat_map(|(_, m)| m.iter())
+            .collect();
+        cfgs.push(construct_function(func, &shared));
+    }
+    return cfgs;
+}
+
+/// Takes every function back out of SSA form, replacing each phi with copies at the end of
+/// the blocks it merges. Edges from a block with several successors into one with phis get a
+/// block of their own to hold the copies.
+pub(crate) fn destruct(cfgs: Vec<Cfg>) -> IrProgram {
+    return IrProgram { functions: cfgs.into_iter().map(destruct_function).collect() };
+}
+
+fn construct_function(func: &IrFunction, shared: &HashSet<&String>) -> Cfg {
+    let mut cfg = Cfg::build(func);
+    cfg.remove_unreachable();
+
+    // find what can be renamed, where it's written and its type
+    let mut types: HashMap<Var, IrType> = HashMap::new();
+    let mut sites: HashMap<Var, Vec<usize>> = HashMap::new();
+    for (id, ty) in &cfg.params {
+        types.insert(Var::Named(id.clone()), ty.clone());
+        sites.insert(Var::Named(id.clone()), vec![0]);
+    }
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        for inst in &block.body {
+            let Some(var) = inst.dest().as_ref().and_then(Var::of) else {
+                continue;
+            };
+            if let Some(ty) = def_type(inst) {
+                types.insert(var.clone(), ty);
+            }
+            sites.entry(var).or_default().push(b);
+        }
+    }
+    let renamed: HashSet<Var> = types
+        .keys()
+        .filter(|v| !matches!(v, Var::Named(id) if shared.contains(id)))
+        .cloned()
+        .collect();
+
+    let phi_vars = insert_phis(&mut cfg, &renamed, &types, &sites);
+    rename(&mut cfg, &renamed, &types, &phi_vars);
+    return cfg;
+}
+
+/// Places phis on the iterated dominance frontier of every definition, skipping blocks where
+/// the value is dead so that every phi has a defined value on each incoming edge. Returns the
+/// variable of each phi, in the order they sit at the top of each block.
+fn insert_phis(
+    cfg: &mut Cfg,
+    renamed: &HashSet<Var>,
+    types: &HashMap<Var, IrType>,
+    sites: &HashMap<Var, Vec<usize>>,
+) -> Vec<Vec<Var>> {
+    let frontiers = cfg.frontiers();
+    let live_in = Liveness::compute(cfg).live_in;
+
+    // sorted so the output doesn't depend on hash order
+    let mut vars: Vec<&Var> = renamed.iter().collect();
+    vars.sort();
+
+    let mut phi_vars: Vec<Vec<Var>> = vec![vec![]; cfg.blocks.len()];
+    for var in vars {
+        let mut work: Vec<usize> = sites.get(var).cloned().unwrap_or_default();
+        while let Some(b) = work.pop() {
+            for &f in &frontiers[b] {
+                if phi_vars[f].contains(var) || !live_in[f].contains(var) {
+                    continue;
+                }
+                phi_vars[f].push(var.clone());
+                work.push(f);
+            }
+        }
+    }
+
+    for (block, vars) in cfg.blocks.iter_mut().zip(&phi_vars) {
+        let mut body: Vec<IrInst> = vars
+            .iter()
+            .map(|v| IrInst::Phi { dest: v.value(), ty: types[v].clone(), args: vec![] })
+            .collect();
+        body.append(&mut block.body);
+        block.body = body;
+    }
+    return phi_vars;
+}
+
+/// Gives every definition a fresh name by walking the dominator tree, where the current name
+/// of each variable is whatever the closest dominating definition called it.
+fn rename(cfg: &mut Cfg, renamed: &HashSet<Var>, types: &HashMap<Var, IrType>, phi_vars: &[Vec<Var>]) {
+    enum Visit {
+        Enter(usize),
+        /// Pops the names pushed by a block once its subtree is done
+        Exit(Vec<Var>),
+    }
+
+    let children = cfg.dominator_tree();
+    let mut names: HashMap<Var, Vec<IrValue>> = HashMap::new();
+    let mut versions: HashMap<Var, usize> = HashMap::new();
+    let mut next_temp = next_temp(&cfg.blocks);
+
+    // parameters are written on entry and keep their name
+    for (id, _) in &cfg.params {
+        let var = Var::Named(id.clone());
+        names.insert(var.clone(), vec![var.value()]);
+        versions.insert(var, 1);
+    }
+
+    let mut work = vec![Visit::Enter(0)];
+    while let Some(visit) = work.pop() {
+        let b = match visit {
+            Visit::Enter(b) => b,
+            Visit::Exit(defined) => {
+                for var in defined {
+                    names.get_mut(&var).unwrap().pop();
+                }
+                continue;
+            }
+        };
+
+        let mut defined = vec![];
+        for inst in cfg.blocks[b].body.iter_mut() {
+            // phi arguments are filled in from the predecessors instead
+            if !matches!(inst, IrInst::Phi { dest: _, ty: _, args: _ }) {
+                for val in inst.uses_mut() {
+                    if let Some(var) = Var::of(val)
+                        && renamed.contains(&var)
+                    {
+                        *val = current(&names, &var);
+                    }
+                }
+            }
+
+            let Some(var) = inst.dest().as_ref().and_then(Var::of) else {
+                continue;
+            };
+            if !renamed.contains(&var) {
+                continue;
+            }
+
+            // the first definition keeps the original name
+            let version = versions.entry(var.clone()).or_insert(0);
+            let name = match &var {
+                _ if *version == 0 => var.value(),
+                Var::Temp(_) => {
+                    next_temp += 1;
+                    IrValue::Temp(next_temp - 1)
+                }
+                Var::Named(id) => IrValue::Identifier(format!("{}#{}", id, version)),
+            };
+            *version += 1;
+
+            set_dest(inst, name.clone(), &types[&var]);
+            names.entry(var.clone()).or_default().push(name);
+            defined.push(var);
+        }
+
+        // fill in this block's side of the phis in its successors
+        for s in cfg.blocks[b].succs.clone() {
+            for (inst, var) in cfg.blocks[s].body.iter_mut().zip(&phi_vars[s]) {
+                if let IrInst::Phi { dest: _, ty: _, args } = inst {
+                    args.push((b, current(&names, var)));
+                }
+            }
+        }
+
+        work.push(Visit::Exit(defined));
+        for c in children[b].iter().rev() {
+            work.push(Visit::Enter(*c));
+        }
+    }
+}
+
+fn destruct_function(mut cfg: Cfg) -> IrFunction {
+    let mut next_temp = next_temp(&cfg.blocks);
+    let mut next_label = cfg.blocks
+        .iter()
+        .flat_map(|b| b.label)
+        .max()
+        .map_or(0, |l| l + 1);
+    // copies on edges out of blocks with more than one successor, with the block they leave
+    let mut edges: Vec<(usize, BasicBlock)> = vec![];
+
+    for b in 0..cfg.blocks.len() {
+        let count = cfg.blocks[b].body
+            .iter()
+            .take_while(|i| matches!(i, IrInst::Phi { dest: _, ty: _, args: _ }))
+            .count();
+        if count == 0 {
+            continue;
+        }
+        let phis: Vec<IrInst> = cfg.blocks[b].body.drain(..count).collect();
+
+        for p in cfg.blocks[b].preds.clone() {
+            let copies = phi_copies(&phis, p, &mut next_temp);
+
+            if cfg.blocks[p].succs.len() < 2 {
+                let body = &mut cfg.blocks[p].body;
+                let at = if body.last().is_some_and(|i| i.is_terminator()) { body.len() - 1 } else { body.len() };
+                body.splice(at..at, copies);
+                continue;
+            }
+
+            // the copies can't go at the end of a block that also leads somewhere else, so they
+            // get a block of their own on the edge. A block with phis has several predecessors
+            // and can only be reached through its label.
+            let target = cfg.blocks[b].label.unwrap();
+            let label = next_label;
+            next_label += 1;
+            if let Some(IrInst::Branch { cond: _, then, otherwise }) = cfg.blocks[p].body.last_mut() {
+                if *then == target {
+                    *then = label;
+                }
+                if *otherwise == target {
+                    *otherwise = label;
+                }
+            }
+
+            let mut body = copies;
+            body.push(IrInst::Jump { label: target });
+            edges.push((p, BasicBlock { label: Some(label), body, preds: vec![p], succs: vec![b] }));
+        }
+    }
+
+    // each edge block goes right after the branch leading to it, since the last block of a
+    // function can fall off its end and would run into anything put after it
+    let blocks = std::mem::take(&mut cfg.blocks);
+    for (i, block) in blocks.into_iter().enumerate() {
+        cfg.blocks.push(block);
+        cfg.blocks.extend(edges.iter().filter(|(p, _)| *p == i).map(|(_, edge)| edge.clone()));
+    }
+    return cfg.to_function();
+}
+
+/// The copies that carry out `phis` when coming from block `pred`. All phis of a block
+/// happen at once, so when one reads what another writes the arguments go through fresh
+/// temporaries first.
+fn phi_copies(phis: &[IrInst], pred: usize, next_temp: &mut usize) -> Vec<IrInst> {
+    let mut moves: Vec<(IrValue, IrType, IrValue)> = vec![];
+    for phi in phis {
+        if let IrInst::Phi { dest, ty, args } = phi
+            && let Some((_, val)) = args.iter().find(|(b, _)| *b == pred)
+        {
+            moves.push((dest.clone(), ty.clone(), val.clone()));
+        }
+    }
+
+    let overlaps = moves.len() > 1 && moves.iter().any(|(_, _, val)| moves.iter().any(|(d, _, _)| d == val));
+    if !overlaps {
+        return moves
+            .into_iter()
+            .map(|(dest, ty, val)| IrInst::Copy { dest, ty, val })
+            .collect();
+    }
+
+    let mut copies = vec![];
+    let mut temps = vec![];
+    for (_, ty, val) in &moves {
+        let temp = IrValue::Temp(*next_temp);
+        *next_temp += 1;
+        copies.push(IrInst::Copy { dest: temp.clone(), ty: ty.clone(), val: val.clone() });
+        temps.push(temp);
+    }
+    for ((dest, ty, _), temp) in moves.into_iter().zip(temps) {
+        copies.push(IrInst::Copy { dest, ty, val: temp });
+    }
+    return copies;
+}
+
+/// Every identifier some instructions read or write.
+pub(crate) fn identifiers<'i>(insts: impl IntoIterator<Item = &'i IrInst>) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    for inst in insts {
+        for val in inst.uses().into_iter().chain(inst.dest().as_ref()) {
+            if let IrValue::Identifier(id) = val {
+                ids.insert(id.clone());
+            }
+        }
+    }
+    return ids;
+}
+
+/// The type of the value an instruction writes, stores don't say and take the type the
+/// binding was declared with.
+fn def_type(inst: &IrInst) -> Option<IrType> {
+    return match inst {
+        IrInst::Bind { id: _, ty, val: _ } |
+        IrInst::Copy { dest: _, ty, val: _ } |
+        IrInst::BinOp { dest: _, ty, lhs: _, op: _, rhs: _ } |
+        IrInst::UnOp { dest: _, ty, op: _, val: _ } |
+        IrInst::Call { dest: _, ty, func: _, args: _ } |
+        IrInst::Phi { dest: _, ty, args: _ } => Some(ty.clone()),
+        _ => None,
+    };
+}
+
+/// Points the value an instruction writes at `name`. A store to a renamed binding declares a
+/// new version of it, so it turns into a bind.
+fn set_dest(inst: &mut IrInst, name: IrValue, ty: &IrType) {
+    match inst {
+        IrInst::Bind { id, ty: _, val: _ } => {
+            if let IrValue::Identifier(name) = name {
+                *id = name;
+            }
+        }
+        IrInst::Store { id: _, val } => {
+            if let IrValue::Identifier(name) = name {
+                *inst = IrInst::Bind { id: name, ty: ty.clone(), val: val.clone() };
+            }
+        }
+        IrInst::Copy { dest, ty: _, val: _ } |
+        IrInst::BinOp { dest, ty: _, lhs: _, op: _, rhs: _ } |
+        IrInst::UnOp { dest, ty: _, op: _, val: _ } |
+        IrInst::Phi { dest, ty: _, args: _ } => *dest = name,
+        IrInst::Call { dest, ty: _, func: _, args: _ } => *dest = Some(name),
+        _ => {}
+    }
+}
+
+/// The current name of `var`. The resolver only lets a binding be read where its declaration
+/// dominates and phis are only placed where their value is live, so a name is always there.
+fn current(names: &HashMap<Var, Vec<IrValue>>, var: &Var) -> IrValue {
+    let name = names.get(var).and_then(|n| n.last());
+    debug_assert!(name.is_some(), "no definition of {:?} reaches its use", var);
+    return name.cloned().unwrap_or(var.value());
+}
+
+/// The first temporary number nothing in the blocks uses yet.
+fn next_temp(blocks: &[BasicBlock]) -> usize {
+    let mut next = 0;
+    for inst in blocks.iter().flat_map(|b| &b.body) {
+        for val in inst.uses().into_iter().chain(inst.dest().as_ref()) {
+            if let IrValue::Temp(t) = val {
+                next = next.max(t + 1);
+            }
+        }
+    }
+    return next;
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use crate::{
+    analysis::irgen::{ IrFunction, IrInst, IrProgram, IrType, IrValue },
+    backend::{ declarations, holds_string, mangle, named_dest, TypeMap },
+    common::token::TokenKind,
+};
+
+/// The runtime every generated file starts with, so the output compiles on its own.
+const RUNTIME: &str = include_str!("chao.h");
+
+/// Lowers a program that is out of SSA form to a standalone C file. The top level statements
+/// become a function that `main` calls, and every binding they declare becomes a global so
+/// the other functions can see it.
+pub(crate) fn generate(program: &IrProgram) -> String {
+    let mut generator = CGenerator::new(program);
+    return generator.program(program);
+}
+
+struct CGenerator {
+    types: TypeMap,
+    /// Parameter and return types of every function by IR name
+    signatures: HashMap<String, (Vec<IrType>, IrType)>,
+    out: String,
+}
+
+impl CGenerator {
+    fn new(program: &IrProgram) -> CGenerator {
+        let mut signatures = HashMap::new();
+        for func in &program.functions {
+            let params = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+            signatures.insert(func.id.clone(), (params, func.ret.clone()));
+        }
+
+        return CGenerator {
+            types: TypeMap::new(program),
+            signatures,
+            out: String::new(),
+        };
+    }
+
+    fn program(&mut self, program: &IrProgram) -> String {
+        self.out.push_str(RUNTIME);
+        self.out.push('\n');
+
+        let functions = program.functions.iter().skip(1);
+        for func in functions.clone() {
+            self.out.push_str(&format!("{};\n", self.signature(func)));
+        }
+        if program.functions.len() > 1 {
+            self.out.push('\n');
+        }
+
+        // bindings at the top level are globals, the ones holding strings keep them alive
+        let mut roots = vec![];
+        if let Some(script) = program.functions.first() {
+            let globals = declarations(script.body.iter().filter_map(named_dest));
+            for (id, ty) in &globals {
+                self.out.push_str(&format!("static {} {};\n", c_type(ty), mangle("v_", id)));
+                if holds_string(ty) {
+                    roots.push(mangle("v_", id));
+                }
+            }
+            if !roots.is_empty() {
+                self.out.push_str("\nstatic const chao_root chao_globals[] = {\n");
+                for root in &roots {
+                    self.out.push_str(&format!("    {{ &{}, sizeof {} }},\n", root, root));
+                }
+                self.out.push_str("};\n");
+            }
+            if !globals.is_empty() {
+                self.out.push('\n');
+            }
+            self.function(script, true);
+        }
+        for func in functions {
+            self.function(func, false);
+        }
+
+        let roots = if roots.is_empty() { "NULL, 0".to_string() } else { format!("chao_globals, {}", roots.len()) };
+        self.out.push_str("int main(void) {\n    void *base = NULL;\n");
+        self.out.push_str(&format!("    chao_start(&base, {});\n", roots));
+        self.out.push_str("    chao_script();\n    return 0;\n}\n");
+        return std::mem::take(&mut self.out);
+    }
+
+    fn signature(&self, func: &IrFunction) -> String {
+        let params: Vec<String> = func.params
+            .iter()
+            .map(|(id, ty)| format!("{} {}", c_type(ty), mangle("v_", id)))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        return format!("static {} {}({})", c_type(&func.ret), mangle("f_", &func.id), params);
+    }
+
+    fn function(&mut self, func: &IrFunction, script: bool) {
+        self.types.enter(func);
+
+        if script {
+            self.out.push_str("static void chao_script(void) {\n");
+        } else {
+            self.out.push_str(&format!("{} {{\n", self.signature(func)));
+        }
+
+        // everything is declared up front so jumps never cross a declaration
+        let mut temps: Vec<(&usize, &IrType)> = self.types.temps().iter().collect();
+        temps.sort_by_key(|(t, _)| **t);
+        for (t, ty) in temps {
+            self.out.push_str(&format!("    {} t{};\n", c_type(ty), t));
+        }
+        if !script {
+            for (id, ty) in declarations(func.body.iter().filter_map(named_dest)) {
+                self.out.push_str(&format!("    {} {};\n", c_type(&ty), mangle("v_", &id)));
+            }
+            self.out.push_str("    chao_enter();\n");
+        }
+
+        for inst in &func.body {
+            let line = self.inst(inst, func, script);
+            match inst {
+                IrInst::Label { id: _ } => self.out.push_str(&format!("{}\n", line)),
+                _ => self.out.push_str(&format!("    {}\n", line)),
+            }
+        }
+        self.out.push_str("}\n\n");
+    }
+
+    fn inst(&self, inst: &IrInst, func: &IrFunction, script: bool) -> String {
+        return match inst {
+            IrInst::Bind { id, ty, val } => format!("{} = {};", mangle("v_", id), self.convert(val, ty)),
+            IrInst::Store { id, val } => format!("{} = {};", self.value(id), self.convert(val, &self.types.of(id))),
+            IrInst::Copy { dest, ty, val } => format!("{} = {};", self.value(dest), self.convert(val, ty)),
+            IrInst::BinOp { dest, ty: _, lhs, op, rhs } => {
+                match self.binary(lhs, *op, rhs) {
+                    Some(expr) => format!("{} = {};", self.value(dest), expr),
+                    None => "chao_panic(\"invalid operand types for this operator\");".to_string(),
+                }
+            }
+            IrInst::UnOp { dest, ty: _, op, val } => {
+                match self.unary(*op, val) {
+                    Some(expr) => format!("{} = {};", self.value(dest), expr),
+                    None => "chao_panic(\"invalid operand type for this operator\");".to_string(),
+                }
+            }
+            IrInst::Label { id } => format!("L{}:;", id),
+            IrInst::Jump { label } => format!("goto L{};", label),
+            IrInst::Branch { cond, then, otherwise } => {
+                format!("if ({}) goto L{}; else goto L{};", self.value(cond), then, otherwise)
+            }
+            IrInst::Call { dest, ty: _, func: callee, args } => {
+                let call = self.call(callee, args);
+                match dest {
+                    Some(dest) => format!("{} = {};", self.value(dest), call),
+                    None => format!("{};", call),
+                }
+            }
+            IrInst::Return { val: _ } if script => "return;".to_string(),
+            IrInst::Return { val: Some(val) } if func.ret != IrType::Void => {
+                format!("chao_leave(); return {};", self.convert(val, &func.ret))
+            }
+            // the resolver makes sure functions returning a value never get here
+            IrInst::Return { val: None } if func.ret != IrType::Void => {
+                "chao_panic(\"reached the end of a function without a return value\");".to_string()
+            }
+            IrInst::Return { val: _ } => "chao_leave(); return;".to_string(),
+            // phis are gone once the program is out of SSA form
+            IrInst::Phi { dest: _, ty: _, args: _ } => String::new(),
+        };
+    }
+
+    fn call(&self, callee: &String, args: &[IrValue]) -> String {
+        if callee == "print" {
+            if args.is_empty() {
+                return "chao_print(0, NULL)".to_string();
+            }
+            let args: Vec<String> = args.iter().map(|a| self.boxed(a)).collect();
+            return format!("chao_print({}, (chao_value[]){{ {} }})", args.len(), args.join(", "));
+        }
+
+        let params = self.signatures.get(callee).map(|(p, _)| p.clone()).unwrap_or_default();
+        let args: Vec<String> = args
+            .iter()
+            .enumerate()
+            .map(|(i, a)| match params.get(i) {
+                Some(ty) => self.convert(a, ty),
+                None => self.value(a),
+            })
+            .collect();
+        return format!("{}({})", mangle("f_", callee), args.join(", "));
+    }
+
+    /// The C expression for a binary operation, or `None` when the operands can't be used
+    /// with the operator.
+    fn binary(&self, lhs: &IrValue, op: TokenKind, rhs: &IrValue) -> Option<String> {
+        let (l, r) = (self.value(lhs), self.value(rhs));
+        let (lt, rt) = (self.types.of(lhs), self.types.of(rhs));
+
+        if matches!(op, TokenKind::EqualEqual | TokenKind::BangEqual) {
+            let not = if op == TokenKind::BangEqual { "!" } else { "" };
+            let expr = match (&lt, &rt) {
+                (IrType::Str, IrType::Str) => format!("strcmp({}, {}) {} 0", l, r, op.symbol()?),
+                _ if lt == rt && is_scalar(&lt) => format!("{} {} {}", l, op.symbol()?, r),
+                // optionals and nil are compared as tagged values, so different types are unequal
+                _ => format!("{}chao_value_eq({}, {})", not, self.boxed(lhs), self.boxed(rhs)),
+            };
+            return Some(expr);
+        }
+
+        let expr = match (&lt, op, &rt) {
+            (IrType::Str, TokenKind::Plus, IrType::Str) => format!("chao_concat({}, {})", l, r),
+            (IrType::Str, _, IrType::Str) if is_comparison(op) => {
+                format!("strcmp({}, {}) {} 0", l, r, op.symbol()?)
+            }
+            (IrType::Int | IrType::Float | IrType::Char, _, _) if is_comparison(op) && lt == rt => {
+                format!("{} {} {}", l, op.symbol()?, r)
+            }
+            (IrType::Int, _, IrType::Int) => {
+                let helper = match op {
+                    TokenKind::Plus => "chao_add",
+                    TokenKind::Minus => "chao_sub",
+                    TokenKind::Star => "chao_mul",
+                    TokenKind::Slash => "chao_div",
+                    TokenKind::Percent => "chao_rem",
+                    TokenKind::StarStar => "chao_pow",
+                    TokenKind::LessLess => "chao_shl",
+                    TokenKind::GreaterGreater => "chao_shr",
+                    TokenKind::Amp | TokenKind::Pipe | TokenKind::Caret => {
+                        return Some(format!("{} {} {}", l, op.symbol()?, r));
+                    }
+                    _ => return None,
+                };
+                format!("{}({}, {})", helper, l, r)
+            }
+            (IrType::Float, _, IrType::Float) => {
+                match op {
+                    TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash => {
+                        format!("{} {} {}", l, op.symbol()?, r)
+                    }
+                    TokenKind::Percent => format!("fmodf({}, {})", l, r),
+                    TokenKind::StarStar => format!("powf({}, {})", l, r),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        return Some(expr);
+    }
+
+    fn unary(&self, op: TokenKind, val: &IrValue) -> Option<String> {
+        let v = self.value(val);
+        let expr = match (op, self.types.of(val)) {
+            (TokenKind::Minus, IrType::Int) => format!("chao_neg({})", v),
+            (TokenKind::Minus, IrType::Float) => format!("-{}", v),
+            (TokenKind::Tilde, IrType::Int) => format!("~{}", v),
+            (TokenKind::Bang, IrType::Bool) => format!("!{}", v),
+            _ => return None,
+        };
+        return Some(expr);
+    }
+
+    fn value(&self, val: &IrValue) -> String {
+        return match val {
+            IrValue::Temp(t) => format!("t{}", t),
+            IrValue::Identifier(id) => mangle("v_", id),
+            // the literal for the smallest int would overflow before being negated
+            IrValue::ConstInt(i32::MIN) => "INT32_MIN".to_string(),
+            IrValue::ConstInt(n) => n.to_string(),
+            IrValue::ConstFloat(n) if n.is_nan() => "NAN".to_string(),
+            IrValue::ConstFloat(n) if n.is_infinite() => {
+                if *n < 0.0 { "-INFINITY".to_string() } else { "INFINITY".to_string() }
+            }
+            IrValue::ConstFloat(n) => format!("{:?}f", n),
+            IrValue::ConstStr(s) => string_literal(s),
+            IrValue::ConstChar(c) => format!("(chao_char){}", *c as u32),
+            IrValue::ConstBool(b) => b.to_string(),
+            IrValue::Nil => "CHAO_NIL".to_string(),
+        };
+    }
+
+    /// The value as it should be stored in a place of type `to`, wrapping it up when an
+    /// optional is expected.
+    fn convert(&self, val: &IrValue, to: &IrType) -> String {
+        if let IrType::Optional(_) = to {
+            return self.boxed(val);
+        }
+        return self.value(val);
+    }
+
+    /// The value as a `chao_value`.
+    fn boxed(&self, val: &IrValue) -> String {
+        let ctor = match self.types.of(val) {
+            IrType::Optional(_) => return self.value(val),
+            IrType::Int => "chao_box_int",
+            IrType::Float => "chao_box_float",
+            IrType::Bool => "chao_box_bool",
+            IrType::Char => "chao_box_char",
+            IrType::Str => "chao_box_str",
+            IrType::Nil | IrType::Void => "chao_box_nil",
+        };
+        return format!("{}({})", ctor, self.value(val));
+    }
+}
+
+fn c_type(ty: &IrType) -> &'static str {
+    return match ty {
+        IrType::Int => "int32_t",
+        IrType::Float => "float",
+        IrType::Bool => "bool",
+        IrType::Char => "chao_char",
+        IrType::Str => "chao_str",
+        IrType::Nil => "chao_nil",
+        IrType::Void => "void",
+        IrType::Optional(_) => "chao_value",
+    };
+}
+
+/// Whether values of this type can be compared with the C operators directly.
+fn is_scalar(ty: &IrType) -> bool {
+    return matches!(ty, IrType::Int | IrType::Float | IrType::Bool | IrType::Char);
+}
+
+fn is_comparison(op: TokenKind) -> bool {
+    return matches!(op, TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual);
+}
+
+/// Writes a string as a C literal. Anything outside printable ASCII is written as an octal
+/// escape, which unlike a hex escape can't run into the next character.
+fn string_literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            // `??` could start a trigraph
+            b'?' => out.push_str("\\?"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out.push('"');
+    return out;
+}
@@ -0,0 +1,438 @@
+/*
+ * Runtime support for Chao programs compiled to C. Every generated file starts with a copy
+ * of this header so it can be compiled on its own, link with `-lm` for the float operators.
+ *
+ * Integer arithmetic is checked the same way as on the VM, and anything that would fail there
+ * stops the program with a runtime error on stderr and exit code 1.
+ */
+#ifndef CHAO_H
+#define CHAO_H
+
+#include <math.h>
+#include <setjmp.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef const char *chao_str;
+/* a unicode scalar value */
+typedef uint32_t chao_char;
+/* nil carries no information, so any byte will do */
+typedef unsigned char chao_nil;
+#define CHAO_NIL ((chao_nil)0)
+
+/* the most calls that can be in progress at once, the same as on the VM and interpreter */
+#define CHAO_MAX_DEPTH 1024
+/* bytes of strings allocated before the first collection */
+#define CHAO_HEAP_MIN (1 << 20)
+
+typedef enum {
+    CHAO_TAG_NIL,
+    CHAO_TAG_INT,
+    CHAO_TAG_FLOAT,
+    CHAO_TAG_BOOL,
+    CHAO_TAG_CHAR,
+    CHAO_TAG_STR,
+} chao_tag;
+
+/* a value of any type, used for optionals and for printing */
+typedef struct {
+    chao_tag tag;
+    union {
+        int32_t i;
+        float f;
+        bool b;
+        chao_char c;
+        chao_str s;
+    } as;
+} chao_value;
+
+#if defined(__STDC_VERSION__) && __STDC_VERSION__ >= 201112L
+#define CHAO_NORETURN _Noreturn
+#else
+#define CHAO_NORETURN
+#endif
+
+#if defined(__GNUC__)
+#define CHAO_NOINLINE __attribute__((noinline))
+#else
+#define CHAO_NOINLINE
+#endif
+
+/* a global that can hold a string, which keeps the string alive */
+typedef struct {
+    const void *at;
+    size_t size;
+} chao_root;
+
+/* a string made at runtime, linked to all the others so they can be swept */
+typedef struct chao_object {
+    struct chao_object *next;
+    size_t size;
+    bool marked;
+    char bytes[];
+} chao_object;
+
+static int chao_depth = 0;
+
+static inline CHAO_NORETURN void chao_panic(const char *msg) {
+    fflush(stdout);
+    fprintf(stderr, "runtime error: %s\n", msg);
+    exit(1);
+}
+
+static inline void chao_enter(void) {
+    if (++chao_depth > CHAO_MAX_DEPTH) chao_panic("stack overflow");
+}
+
+static inline void chao_leave(void) {
+    chao_depth--;
+}
+
+/* values */
+
+static inline chao_value chao_box_nil(chao_nil v) {
+    chao_value r;
+    (void)v;
+    r.tag = CHAO_TAG_NIL;
+    r.as.i = 0;
+    return r;
+}
+
+static inline chao_value chao_box_int(int32_t v) {
+    chao_value r;
+    r.tag = CHAO_TAG_INT;
+    r.as.i = v;
+    return r;
+}
+
+static inline chao_value chao_box_float(float v) {
+    chao_value r;
+    r.tag = CHAO_TAG_FLOAT;
+    r.as.f = v;
+    return r;
+}
+
+static inline chao_value chao_box_bool(bool v) {
+    chao_value r;
+    r.tag = CHAO_TAG_BOOL;
+    r.as.b = v;
+    return r;
+}
+
+static inline chao_value chao_box_char(chao_char v) {
+    chao_value r;
+    r.tag = CHAO_TAG_CHAR;
+    r.as.c = v;
+    return r;
+}
+
+static inline chao_value chao_box_str(chao_str v) {
+    chao_value r;
+    r.tag = CHAO_TAG_STR;
+    r.as.s = v;
+    return r;
+}
+
+/* values of different types are never equal */
+static inline bool chao_value_eq(chao_value a, chao_value b) {
+    if (a.tag != b.tag) return false;
+    switch (a.tag) {
+    case CHAO_TAG_NIL: return true;
+    case CHAO_TAG_INT: return a.as.i == b.as.i;
+    case CHAO_TAG_FLOAT: return a.as.f == b.as.f;
+    case CHAO_TAG_BOOL: return a.as.b == b.as.b;
+    case CHAO_TAG_CHAR: return a.as.c == b.as.c;
+    case CHAO_TAG_STR: return strcmp(a.as.s, b.as.s) == 0;
+    }
+    return false;
+}
+
+/* integers */
+
+static inline int32_t chao_narrow(int64_t v, const char *msg) {
+    if (v < INT32_MIN || v > INT32_MAX) chao_panic(msg);
+    return (int32_t)v;
+}
+
+static inline int32_t chao_add(int32_t a, int32_t b) {
+    return chao_narrow((int64_t)a + b, "integer overflow in addition");
+}
+
+static inline int32_t chao_sub(int32_t a, int32_t b) {
+    return chao_narrow((int64_t)a - b, "integer overflow in subtraction");
+}
+
+static inline int32_t chao_mul(int32_t a, int32_t b) {
+    return chao_narrow((int64_t)a * b, "integer overflow in multiplication");
+}
+
+static inline int32_t chao_div(int32_t a, int32_t b) {
+    if (b == 0) chao_panic("division by zero");
+    if (a == INT32_MIN && b == -1) chao_panic("integer overflow in division");
+    return a / b;
+}
+
+static inline int32_t chao_rem(int32_t a, int32_t b) {
+    if (b == 0) chao_panic("division by zero");
+    if (a == INT32_MIN && b == -1) chao_panic("integer overflow in remainder");
+    return a % b;
+}
+
+/* exponentiation by squaring, failing on the same inputs as the VM */
+static inline int32_t chao_pow(int32_t base, int32_t exp) {
+    const char *msg = "integer overflow in power";
+    int32_t acc = 1;
+    if (exp < 0) chao_panic("negative exponent in integer power");
+    if (exp == 0) return 1;
+    while (exp > 1) {
+        if (exp & 1) acc = chao_narrow((int64_t)acc * base, msg);
+        exp /= 2;
+        base = chao_narrow((int64_t)base * base, msg);
+    }
+    return chao_narrow((int64_t)acc * base, msg);
+}
+
+static inline int32_t chao_shl(int32_t a, int32_t b) {
+    if (b < 0 || b >= 32) chao_panic("shift amount out of range");
+    return (int32_t)((uint32_t)a << b);
+}
+
+static inline int32_t chao_shr(int32_t a, int32_t b) {
+    if (b < 0 || b >= 32) chao_panic("shift amount out of range");
+    return a >> b;
+}
+
+static inline int32_t chao_neg(int32_t a) {
+    if (a == INT32_MIN) chao_panic("integer overflow in negation");
+    return -a;
+}
+
+/*
+ * strings, which are garbage collected
+ *
+ * Once enough bytes of strings were made since the last collection, the stack and the globals
+ * are scanned for words pointing at one and every string nothing points at is freed. The scan
+ * is conservative, anything that looks like a pointer to a string keeps it alive.
+ */
+
+static const char *chao_stack_base;
+static const chao_root *chao_roots;
+static size_t chao_root_count;
+static chao_object *chao_objects;
+static uintptr_t chao_heap_low = UINTPTR_MAX;
+static uintptr_t chao_heap_high = 0;
+/* bytes in strings that haven't been freed */
+static size_t chao_heap_size = 0;
+static size_t chao_heap_limit = CHAO_HEAP_MIN;
+
+/* `base` has to live in the frame of `main`, every frame the program makes is past it */
+static inline void chao_start(const void *base, const chao_root *roots, size_t count) {
+    chao_stack_base = base;
+    chao_roots = roots;
+    chao_root_count = count;
+}
+
+static inline void chao_mark_words(const void *from, const void *to) {
+    uintptr_t at = (uintptr_t)from < (uintptr_t)to ? (uintptr_t)from : (uintptr_t)to;
+    uintptr_t end = (uintptr_t)from < (uintptr_t)to ? (uintptr_t)to : (uintptr_t)from;
+    chao_object *o;
+
+    at = (at + sizeof(void *) - 1) & ~(uintptr_t)(sizeof(void *) - 1);
+    for (; at + sizeof(void *) <= end; at += sizeof(void *)) {
+        uintptr_t word;
+        memcpy(&word, (const void *)at, sizeof word);
+        if (word < chao_heap_low || word > chao_heap_high) continue;
+        for (o = chao_objects; o != NULL; o = o->next) {
+            if ((uintptr_t)o->bytes == word) {
+                o->marked = true;
+                break;
+            }
+        }
+    }
+}
+
+/* kept out of line so its frame is past the registers `chao_collect` saved */
+static CHAO_NOINLINE void chao_mark_stack(void) {
+    volatile char top = 0;
+    chao_mark_words((const void *)&top, chao_stack_base);
+}
+
+static inline void chao_collect(void) {
+    chao_object **link = &chao_objects;
+    size_t i;
+#if defined(__GNUC__)
+    /* every callee-saved register goes on the stack, where the scan finds what they hold */
+    __builtin_unwind_init();
+#else
+    jmp_buf registers;
+    setjmp(registers);
+#endif
+
+    chao_mark_stack();
+    for (i = 0; i < chao_root_count; i++) {
+        chao_mark_words(chao_roots[i].at, (const char *)chao_roots[i].at + chao_roots[i].size);
+    }
+
+    chao_heap_size = 0;
+    while (*link != NULL) {
+        chao_object *o = *link;
+        if (o->marked) {
+            o->marked = false;
+            chao_heap_size += o->size;
+            link = &o->next;
+        } else {
+            *link = o->next;
+            free(o);
+        }
+    }
+    chao_heap_limit = chao_heap_size * 2 > CHAO_HEAP_MIN ? chao_heap_size * 2 : CHAO_HEAP_MIN;
+}
+
+static inline char *chao_alloc(size_t size) {
+    chao_object *o;
+    if (chao_heap_size + size > chao_heap_limit) chao_collect();
+
+    o = malloc(sizeof *o + size);
+    if (o == NULL) chao_panic("out of memory");
+    o->next = chao_objects;
+    o->size = size;
+    o->marked = false;
+    chao_objects = o;
+    chao_heap_size += size;
+    if ((uintptr_t)o->bytes < chao_heap_low) chao_heap_low = (uintptr_t)o->bytes;
+    if ((uintptr_t)o->bytes > chao_heap_high) chao_heap_high = (uintptr_t)o->bytes;
+    return o->bytes;
+}
+
+static inline chao_str chao_concat(chao_str a, chao_str b) {
+    size_t la = strlen(a);
+    size_t lb = strlen(b);
+    char *s = chao_alloc(la + lb + 1);
+    memcpy(s, a, la);
+    memcpy(s + la, b, lb + 1);
+    return s;
+}
+
+/* printing */
+
+static inline void chao_write_char(chao_char c) {
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xc0 | (c >> 6)));
+        putchar((int)(0x80 | (c & 0x3f)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xe0 | (c >> 12)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3f)));
+        putchar((int)(0x80 | (c & 0x3f)));
+    } else {
+        putchar((int)(0xf0 | (c >> 18)));
+        putchar((int)(0x80 | ((c >> 12) & 0x3f)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3f)));
+        putchar((int)(0x80 | (c & 0x3f)));
+    }
+}
+
+/*
+ * Rounds the first `count` digits of `exact` down or up and checks whether they read back as
+ * `v`. On success the digits and their exponent are left in `digits` and `exp`.
+ */
+static inline bool chao_float_digits(float v, const char *exact, int count, bool up, char *digits, int *exp) {
+    char buf[32];
+    int e = *exp;
+    int j;
+
+    memcpy(digits, exact, (size_t)count);
+    if (up) {
+        for (j = count - 1; j >= 0 && digits[j] == '9'; j--) digits[j] = '0';
+        if (j >= 0) {
+            digits[j]++;
+        } else {
+            digits[0] = '1';
+            e++;
+        }
+    }
+    snprintf(buf, sizeof buf, "%c.%.*se%d", digits[0], count - 1, digits + 1, e);
+    if (strtof(buf, NULL) != v) return false;
+    *exp = e;
+    return true;
+}
+
+/*
+ * The fewest digits that read back as the same float, written out without an exponent. The
+ * digits nearest the value win, with ties rounding up like they do on the VM, so they come
+ * from the exact value rather than printf, which rounds ties to even. No float has more than
+ * 112 significant digits.
+ */
+static inline void chao_write_float(float v) {
+    char exact[128];
+    char digits[16];
+    int count;
+    int exp;
+    int i;
+
+    if (isnan(v)) {
+        fputs("NaN", stdout);
+        return;
+    }
+    if (isinf(v)) {
+        fputs(v < 0 ? "-inf" : "inf", stdout);
+        return;
+    }
+    if (signbit(v)) {
+        putchar('-');
+        v = -v;
+    }
+    snprintf(exact, sizeof exact, "%.111e", (double)v);
+    exp = atoi(strchr(exact, 'e') + 1);
+    /* drop the point so the digits are all in a row */
+    exact[1] = exact[0];
+
+    for (count = 1; count < 9; count++) {
+        bool up = exact[count + 1] >= '5';
+        if (chao_float_digits(v, exact + 1, count, up, digits, &exp)) break;
+        if (chao_float_digits(v, exact + 1, count, !up, digits, &exp)) break;
+    }
+    /* nine digits always read back, nearest first */
+    if (count == 9) chao_float_digits(v, exact + 1, count, exact[count + 1] >= '5', digits, &exp);
+    while (count > 1 && digits[count - 1] == '0') count--;
+
+    if (exp < 0) {
+        fputs("0.", stdout);
+        for (i = -1; i > exp; i--) putchar('0');
+        fwrite(digits, 1, (size_t)count, stdout);
+    } else if (exp >= count - 1) {
+        fwrite(digits, 1, (size_t)count, stdout);
+        for (i = count - 1; i < exp; i++) putchar('0');
+    } else {
+        fwrite(digits, 1, (size_t)exp + 1, stdout);
+        putchar('.');
+        fwrite(digits + exp + 1, 1, (size_t)(count - exp - 1), stdout);
+    }
+}
+
+static inline void chao_write(chao_value v) {
+    switch (v.tag) {
+    case CHAO_TAG_NIL: fputs("nil", stdout); break;
+    case CHAO_TAG_INT: printf("%ld", (long)v.as.i); break;
+    case CHAO_TAG_FLOAT: chao_write_float(v.as.f); break;
+    case CHAO_TAG_BOOL: fputs(v.as.b ? "true" : "false", stdout); break;
+    case CHAO_TAG_CHAR: chao_write_char(v.as.c); break;
+    case CHAO_TAG_STR: fputs(v.as.s, stdout); break;
+    }
+}
+
+/* the `print` builtin, arguments are separated by spaces */
+static inline void chao_print(size_t count, const chao_value *args) {
+    size_t i;
+    for (i = 0; i < count; i++) {
+        if (i > 0) putchar(' ');
+        chao_write(args[i]);
+    }
+    putchar('\n');
+}
+
+#endif
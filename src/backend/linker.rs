@@ -0,0 +1,34 @@
+use std::{ env, fs, path::Path, process::Command };
+
+/// Turns generated assembly into an executable at `output` with the system assembler and
+/// linker. The intermediate files go in a scratch directory that is removed afterwards.
+pub(crate) fn link(asm: &str, output: &str) -> Result<(), String> {
+    let dir = env::temp_dir().join(format!("chao-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|e| format!("could not create '{}': {}", dir.display(), e))?;
+
+    let result = assemble_and_link(&dir, asm, output);
+    let _ = fs::remove_dir_all(&dir);
+    return result;
+}
+
+fn assemble_and_link(dir: &Path, asm: &str, output: &str) -> Result<(), String> {
+    let source = dir.join("out.s");
+    let object = dir.join("out.o");
+    fs::write(&source, asm).map_err(|e| format!("could not write '{}': {}", source.display(), e))?;
+
+    let mut assemble = Command::new("as");
+    assemble.arg("--64").arg("-o").arg(&object).arg(&source);
+    run(assemble, "as")?;
+
+    let mut link = Command::new("ld");
+    link.arg("-o").arg(output).arg(&object);
+    return run(link, "ld");
+}
+
+fn run(mut command: Command, name: &str) -> Result<(), String> {
+    let out = command.output().map_err(|e| format!("could not run '{}': {}", name, e))?;
+    if !out.status.success() {
+        return Err(format!("'{}' failed:\n{}", name, String::from_utf8_lossy(&out.stderr).trim_end()));
+    }
+    return Ok(());
+}
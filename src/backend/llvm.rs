@@ -0,0 +1,654 @@
+use std::collections::{ HashMap, HashSet };
+use crate::{
+    analysis::{ cfg::Cfg, irgen::{ IrInst, IrProgram, IrType, IrValue }, ssa::{ identifiers, Var } },
+    backend::{ holds_string, mangle, TypeMap },
+    common::token::TokenKind,
+};
+
+/// The declarations and helpers every generated module starts with.
+const RUNTIME: &str = include_str!("runtime.ll");
+
+/// Lowers a program in SSA form to a textual LLVM module. Temporaries and renamed bindings
+/// become SSA registers and phis become LLVM phis, so nothing goes through memory except the
+/// globals several functions share. The top level statements become a function that `main`
+/// calls.
+///
+/// The module uses the typed pointers of LLVM 14 and only links against the C library, so
+/// `clang out.ll -lm` is enough to build it.
+pub(crate) fn generate(cfgs: &[Cfg]) -> String {
+    let mut generator = LlvmGenerator::new(cfgs);
+    return generator.module(cfgs);
+}
+
+struct LlvmGenerator {
+    types: TypeMap,
+    /// Parameter and return types of every function by IR name
+    signatures: HashMap<String, (Vec<IrType>, IrType)>,
+    /// Bindings that stay in memory, since several functions use them or they're written
+    /// more than once
+    globals: HashSet<String>,
+    /// String literals, numbered by where they are in here
+    strings: Vec<String>,
+    /// Bindings of the function being generated that are registers
+    locals: HashSet<String>,
+    /// Values of the function being generated that only copy another value and the type
+    /// they copy it as. LLVM has no instruction for a copy, so uses read the original.
+    copies: HashMap<Var, (IrValue, IrType)>,
+    /// The block every label of the function being generated starts
+    blocks: HashMap<usize, usize>,
+    /// Registers made up while generating the function so far
+    next: usize,
+    out: String,
+}
+
+impl LlvmGenerator {
+    fn new(cfgs: &[Cfg]) -> LlvmGenerator {
+        let program = IrProgram { functions: cfgs.iter().map(Cfg::to_function).collect() };
+
+        let mut signatures = HashMap::new();
+        for func in &program.functions {
+            let params = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+            signatures.insert(func.id.clone(), (params, func.ret.clone()));
+        }
+
+        // SSA form keeps the names of bindings it didn't rename, so every other binding is
+        // written exactly once, in the one function that mentions it
+        let mut globals: HashSet<String> = HashSet::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut bound: HashSet<String> = HashSet::new();
+        for (f, func) in program.functions.iter().enumerate() {
+            for id in identifiers(&func.body) {
+                if seen.insert(id.clone(), f).is_some_and(|other| other != f) {
+                    globals.insert(id);
+                }
+            }
+            for inst in &func.body {
+                match inst {
+                    IrInst::Store { id: IrValue::Identifier(id), val: _ } => {
+                        globals.insert(id.clone());
+                    }
+                    IrInst::Bind { id, ty: _, val: _ } if !bound.insert(id.clone()) => {
+                        globals.insert(id.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        return LlvmGenerator {
+            types: TypeMap::new(&program),
+            signatures,
+            globals,
+            strings: vec![],
+            locals: HashSet::new(),
+            copies: HashMap::new(),
+            blocks: HashMap::new(),
+            next: 0,
+            out: String::new(),
+        };
+    }
+
+    fn module(&mut self, cfgs: &[Cfg]) -> String {
+        self.out.push_str(RUNTIME);
+        self.out.push('\n');
+
+        // the globals holding strings keep them alive
+        let mut globals: Vec<&String> = self.globals.iter().collect();
+        globals.sort();
+        let mut roots = vec![];
+        for id in &globals {
+            let ty = self.types.of(&IrValue::Identifier(id.to_string()));
+            self.out.push_str(&format!("{} = internal global {} zeroinitializer\n", mangle("@v_", id), llvm_type(&ty)));
+            if holds_string(&ty) {
+                let (ty, global) = (llvm_type(&ty), mangle("@v_", id));
+                roots.push(format!(
+                    "%chao.root {{ i8* bitcast ({}* {} to i8*), i64 ptrtoint ({}* getelementptr ({}, {}* null, i32 1) to i64) }}",
+                    ty, global, ty, ty, ty
+                ));
+            }
+        }
+        if !roots.is_empty() {
+            self.out.push_str(&format!(
+                "@chao.globals = internal constant [{} x %chao.root] [\n  {}\n]\n",
+                roots.len(),
+                roots.join(",\n  ")
+            ));
+        }
+        if !globals.is_empty() {
+            self.out.push('\n');
+        }
+
+        for (i, cfg) in cfgs.iter().enumerate() {
+            self.function(cfg, i == 0);
+        }
+
+        for (n, s) in self.strings.iter().enumerate() {
+            self.out.push_str(&format!(
+                "@str{} = private unnamed_addr constant [{} x i8] {}\n",
+                n,
+                s.len() + 1,
+                string_literal(s),
+            ));
+        }
+        if !self.strings.is_empty() {
+            self.out.push('\n');
+        }
+
+        let roots = match roots.len() {
+            0 => "%chao.root* null, i64 0".to_string(),
+            n => format!(
+                "%chao.root* getelementptr ([{} x %chao.root], [{} x %chao.root]* @chao.globals, i64 0, i64 0), i64 {}",
+                n, n, n
+            ),
+        };
+        self.out.push_str("define i32 @main() {\n  %base = alloca i8\n");
+        self.out.push_str(&format!("  call void @chao_start(i8* %base, {})\n", roots));
+        self.out.push_str("  call void @chao_script()\n  ret i32 0\n}\n");
+        return std::mem::take(&mut self.out);
+    }
+
+    fn function(&mut self, cfg: &Cfg, script: bool) {
+        let func = cfg.to_function();
+        self.types.enter(&func);
+        self.next = 0;
+        self.blocks = cfg.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(b, block)| block.label.map(|label| (label, b)))
+            .collect();
+        self.locals = identifiers(&func.body)
+            .into_iter()
+            .chain(cfg.params.iter().map(|(id, _)| id.clone()))
+            .filter(|id| !self.globals.contains(id))
+            .collect();
+
+        self.copies = HashMap::new();
+        for inst in &func.body {
+            let (dest, ty, val) = match inst {
+                IrInst::Bind { id, ty, val } => (IrValue::Identifier(id.clone()), ty, val),
+                IrInst::Copy { dest, ty, val } => (dest.clone(), ty, val),
+                _ => continue,
+            };
+            if self.is_global(&dest) || self.is_global(val) || (needs_box(ty, &self.types.of(val)) && !is_constant(val)) {
+                continue;
+            }
+            if let Some(var) = Var::of(&dest) {
+                self.copies.insert(var, (val.clone(), ty.clone()));
+            }
+        }
+
+        if script {
+            self.out.push_str("define internal void @chao_script() {\n");
+        } else {
+            let params: Vec<String> = cfg.params
+                .iter()
+                .map(|(id, ty)| format!("{} {}", llvm_type(ty), mangle("%v_", id)))
+                .collect();
+            self.out.push_str(&format!(
+                "define internal {} {}({}) {{\n",
+                llvm_type(&cfg.ret),
+                mangle("@f_", &cfg.id),
+                params.join(", "),
+            ));
+        }
+
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            self.out.push_str(&format!("b{}:\n", b));
+            if b == 0 && !script {
+                self.emit("call void @chao_enter()");
+            }
+            for inst in &block.body {
+                self.inst(inst, cfg, script);
+            }
+            // blocks that fall through need a branch, falling off the end returns
+            if !block.body.last().is_some_and(|inst| inst.is_terminator()) {
+                match block.succs.first() {
+                    Some(next) => self.emit(format!("br label %b{}", next)),
+                    None => self.inst(&IrInst::Return { val: None }, cfg, script),
+                }
+            }
+        }
+        self.out.push_str("}\n\n");
+    }
+
+    fn inst(&mut self, inst: &IrInst, cfg: &Cfg, script: bool) {
+        match inst {
+            IrInst::Bind { id, ty, val } => self.assign(&IrValue::Identifier(id.clone()), ty, val),
+            IrInst::Store { id, val } => {
+                let ty = self.types.of(id);
+                self.assign(id, &ty, val);
+            }
+            IrInst::Copy { dest, ty, val } => self.assign(dest, ty, val),
+            IrInst::BinOp { dest, ty, lhs, op, rhs } => {
+                match self.binary(lhs, *op, rhs) {
+                    Some(expr) => self.emit(format!("{} = {}", register(dest), expr)),
+                    None => self.fail(dest, ty, "invalid operand types for this operator"),
+                }
+            }
+            IrInst::UnOp { dest, ty, op, val } => {
+                match self.unary(*op, val) {
+                    Some(expr) => self.emit(format!("{} = {}", register(dest), expr)),
+                    None => self.fail(dest, ty, "invalid operand type for this operator"),
+                }
+            }
+            IrInst::Label { id: _ } => {}
+            IrInst::Jump { label } => {
+                let target = self.block(*label);
+                self.emit(format!("br label {}", target));
+            }
+            IrInst::Branch { cond, then, otherwise } => {
+                let cond = self.operand(cond);
+                let (then, otherwise) = (self.block(*then), self.block(*otherwise));
+                self.emit(format!("br i1 {}, label {}, label {}", cond, then, otherwise));
+            }
+            IrInst::Call { dest, ty, func: callee, args } => self.call(dest.as_ref(), ty, callee, args),
+            IrInst::Return { val: _ } if script => self.emit("ret void"),
+            IrInst::Return { val: Some(val) } if cfg.ret != IrType::Void => {
+                let val = self.convert(val, &cfg.ret);
+                self.emit("call void @chao_leave()");
+                self.emit(format!("ret {} {}", llvm_type(&cfg.ret), val));
+            }
+            // the resolver makes sure functions returning a value never get here
+            IrInst::Return { val: None } if cfg.ret != IrType::Void => {
+                let msg = self.string("reached the end of a function without a return value");
+                self.emit(format!("call void @chao_panic(i8* {})", msg));
+                self.emit("unreachable");
+            }
+            IrInst::Return { val: _ } => {
+                self.emit("call void @chao_leave()");
+                self.emit("ret void");
+            }
+            IrInst::Phi { dest, ty, args } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|(pred, val)| format!("[ {}, %b{} ]", self.incoming(val, ty), pred))
+                    .collect();
+                self.emit(format!("{} = phi {} {}", register(dest), llvm_type(ty), args.join(", ")));
+            }
+        }
+    }
+
+    /// Writes `val` to `dest` as a `ty`. Copies into registers were taken care of up front,
+    /// apart from the ones that have to box their value or load it from a global.
+    fn assign(&mut self, dest: &IrValue, ty: &IrType, val: &IrValue) {
+        if let IrValue::Identifier(id) = dest
+            && self.globals.contains(id)
+        {
+            let val = self.convert(val, ty);
+            let ty = llvm_type(ty);
+            self.emit(format!("store {} {}, {}* {}", ty, val, ty, mangle("@v_", id)));
+            return;
+        }
+        if Var::of(dest).is_some_and(|var| self.copies.contains_key(&var)) {
+            return;
+        }
+
+        let expr = if needs_box(ty, &self.types.of(val)) {
+            self.box_call(val)
+        } else {
+            let IrValue::Identifier(id) = val else {
+                return;
+            };
+            let ty = llvm_type(ty);
+            format!("load {}, {}* {}", ty, ty, mangle("@v_", id))
+        };
+        self.emit(format!("{} = {}", register(dest), expr));
+    }
+
+    fn call(&mut self, dest: Option<&IrValue>, ty: &IrType, callee: &String, args: &[IrValue]) {
+        if callee == "print" {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    self.emit("call void @chao_write_byte(i32 32)");
+                }
+                let val = self.as_value(arg);
+                self.emit(format!("call void @chao_write(%chao.value {})", val));
+            }
+            self.emit("call void @chao_write_byte(i32 10)");
+            return;
+        }
+
+        let params = self.signatures.get(callee).map(|(p, _)| p.clone()).unwrap_or_default();
+        let mut typed = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            let ty = params.get(i).cloned().unwrap_or_else(|| self.types.of(arg));
+            let val = self.convert(arg, &ty);
+            typed.push(format!("{} {}", llvm_type(&ty), val));
+        }
+
+        let call = format!("call {} {}({})", llvm_type(ty), mangle("@f_", callee), typed.join(", "));
+        match dest {
+            Some(dest) if *ty != IrType::Void => self.emit(format!("{} = {}", register(dest), call)),
+            _ => self.emit(call),
+        }
+    }
+
+    /// The right hand side of a binary operation, or `None` when the operands can't be used
+    /// with the operator. Anything it needs first is emitted along the way.
+    fn binary(&mut self, lhs: &IrValue, op: TokenKind, rhs: &IrValue) -> Option<String> {
+        let (lt, rt) = (self.types.of(lhs), self.types.of(rhs));
+
+        if matches!(op, TokenKind::EqualEqual | TokenKind::BangEqual) {
+            let equal = op == TokenKind::EqualEqual;
+            let expr = match (&lt, &rt) {
+                (IrType::Str, IrType::Str) => {
+                    let order = self.strcmp(lhs, rhs);
+                    format!("icmp {} i32 {}, 0", if equal { "eq" } else { "ne" }, order)
+                }
+                (IrType::Float, IrType::Float) => {
+                    let (l, r) = (self.operand(lhs), self.operand(rhs));
+                    format!("fcmp {} float {}, {}", if equal { "oeq" } else { "une" }, l, r)
+                }
+                _ if lt == rt && is_scalar(&lt) => {
+                    let (l, r) = (self.operand(lhs), self.operand(rhs));
+                    format!("icmp {} {} {}, {}", if equal { "eq" } else { "ne" }, llvm_type(&lt), l, r)
+                }
+                // optionals and nil are compared as tagged values, so different types are unequal
+                _ => {
+                    let (l, r) = (self.as_value(lhs), self.as_value(rhs));
+                    let call = format!("call i1 @chao_value_eq(%chao.value {}, %chao.value {})", l, r);
+                    if equal {
+                        call
+                    } else {
+                        let eq = self.fresh();
+                        self.emit(format!("{} = {}", eq, call));
+                        format!("xor i1 {}, true", eq)
+                    }
+                }
+            };
+            return Some(expr);
+        }
+
+        let expr = match (&lt, op, &rt) {
+            (IrType::Str, TokenKind::Plus, IrType::Str) => {
+                let (l, r) = (self.operand(lhs), self.operand(rhs));
+                format!("call i8* @chao_concat(i8* {}, i8* {})", l, r)
+            }
+            (IrType::Str, _, IrType::Str) if is_comparison(op) => {
+                let order = self.strcmp(lhs, rhs);
+                format!("icmp {} i32 {}, 0", condition(op, true)?, order)
+            }
+            (IrType::Int | IrType::Char, _, _) if is_comparison(op) && lt == rt => {
+                let (l, r) = (self.operand(lhs), self.operand(rhs));
+                // chars are unicode scalar values, which are never negative
+                let cond = condition(op, lt == IrType::Int)?;
+                format!("icmp {} i32 {}, {}", cond, l, r)
+            }
+            (IrType::Float, _, IrType::Float) if is_comparison(op) => {
+                let (l, r) = (self.operand(lhs), self.operand(rhs));
+                format!("fcmp o{} float {}, {}", condition(op, false)?.trim_start_matches('u'), l, r)
+            }
+            (IrType::Int, _, IrType::Int) => {
+                let (l, r) = (self.operand(lhs), self.operand(rhs));
+                match op {
+                    TokenKind::Amp => format!("and i32 {}, {}", l, r),
+                    TokenKind::Pipe => format!("or i32 {}, {}", l, r),
+                    TokenKind::Caret => format!("xor i32 {}, {}", l, r),
+                    _ => format!("call i32 @{}(i32 {}, i32 {})", checked(op)?, l, r),
+                }
+            }
+            (IrType::Float, _, IrType::Float) => {
+                let (l, r) = (self.operand(lhs), self.operand(rhs));
+                match op {
+                    TokenKind::Plus => format!("fadd float {}, {}", l, r),
+                    TokenKind::Minus => format!("fsub float {}, {}", l, r),
+                    TokenKind::Star => format!("fmul float {}, {}", l, r),
+                    TokenKind::Slash => format!("fdiv float {}, {}", l, r),
+                    TokenKind::Percent => format!("call float @fmodf(float {}, float {})", l, r),
+                    TokenKind::StarStar => format!("call float @powf(float {}, float {})", l, r),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        return Some(expr);
+    }
+
+    fn unary(&mut self, op: TokenKind, val: &IrValue) -> Option<String> {
+        let ty = self.types.of(val);
+        let expr = match (op, ty) {
+            (TokenKind::Minus, IrType::Int) => format!("call i32 @chao_neg(i32 {})", self.operand(val)),
+            (TokenKind::Minus, IrType::Float) => format!("fneg float {}", self.operand(val)),
+            (TokenKind::Tilde, IrType::Int) => format!("xor i32 {}, -1", self.operand(val)),
+            (TokenKind::Bang, IrType::Bool) => format!("xor i1 {}, true", self.operand(val)),
+            _ => return None,
+        };
+        return Some(expr);
+    }
+
+    /// Compares two strings, leaving the result of `strcmp` in a register.
+    fn strcmp(&mut self, lhs: &IrValue, rhs: &IrValue) -> String {
+        let (l, r) = (self.operand(lhs), self.operand(rhs));
+        let order = self.fresh();
+        self.emit(format!("{} = call i32 @strcmp(i8* {}, i8* {})", order, l, r));
+        return order;
+    }
+
+    /// Stops the program with a runtime error. Whatever `dest` would have held still needs
+    /// a definition for the instructions after it.
+    fn fail(&mut self, dest: &IrValue, ty: &IrType, msg: &str) {
+        let msg = self.string(msg);
+        self.emit(format!("call void @chao_panic(i8* {})", msg));
+        if *ty != IrType::Void {
+            self.emit(format!("{} = freeze {} undef", register(dest), llvm_type(ty)));
+        }
+    }
+
+    /// The operand for a value, loading it first if it's a global.
+    fn operand(&mut self, val: &IrValue) -> String {
+        if let Some((original, ty)) = Var::of(val).and_then(|var| self.copies.get(&var)).cloned() {
+            return self.convert(&original, &ty);
+        }
+        return match val {
+            IrValue::Temp(t) => format!("%t{}", t),
+            IrValue::Identifier(id) if self.globals.contains(id) => {
+                let ty = llvm_type(&self.types.of(val));
+                let loaded = self.fresh();
+                self.emit(format!("{} = load {}, {}* {}", loaded, ty, ty, mangle("@v_", id)));
+                loaded
+            }
+            IrValue::Identifier(id) if self.locals.contains(id) => mangle("%v_", id),
+            // SSA form only reads a binding nothing wrote on paths that can't happen
+            IrValue::Identifier(_) => "undef".to_string(),
+            IrValue::ConstInt(n) => n.to_string(),
+            // float constants are written as the bits of the same value as a double
+            IrValue::ConstFloat(n) => format!("0x{:016X}", (*n as f64).to_bits()),
+            IrValue::ConstStr(s) => self.string(s),
+            IrValue::ConstChar(c) => (*c as u32).to_string(),
+            IrValue::ConstBool(b) => b.to_string(),
+            IrValue::Nil => "0".to_string(),
+        };
+    }
+
+    /// The operand for a value as it should be passed to a place of type `to`, wrapping it
+    /// up when an optional is expected.
+    fn convert(&mut self, val: &IrValue, to: &IrType) -> String {
+        if needs_box(to, &self.types.of(val)) {
+            return self.as_value(val);
+        }
+        return self.operand(val);
+    }
+
+    /// The operand for a value as a `%chao.value`. Constants are boxed as constants.
+    fn as_value(&mut self, val: &IrValue) -> String {
+        let ty = self.types.of(val);
+        if let IrType::Optional(_) = ty {
+            return self.operand(val);
+        }
+        if is_constant(val) {
+            let bits = match val {
+                IrValue::ConstInt(n) => (*n as u32).to_string(),
+                IrValue::ConstFloat(n) => n.to_bits().to_string(),
+                IrValue::ConstChar(c) => (*c as u32).to_string(),
+                IrValue::ConstBool(b) => (*b as u32).to_string(),
+                IrValue::ConstStr(s) => format!("ptrtoint (i8* {} to i64)", self.string(s)),
+                _ => "0".to_string(),
+            };
+            return format!("{{ i32 {}, i64 {} }}", tag(&ty), bits);
+        }
+        let boxed = self.box_call(val);
+        let reg = self.fresh();
+        self.emit(format!("{} = {}", reg, boxed));
+        return reg;
+    }
+
+    /// A call to the runtime function that boxes values of this one's type.
+    fn box_call(&mut self, val: &IrValue) -> String {
+        let ty = self.types.of(val);
+        let name = match ty {
+            IrType::Int => "int",
+            IrType::Float => "float",
+            IrType::Bool => "bool",
+            IrType::Char => "char",
+            IrType::Str => "str",
+            _ => return "call %chao.value @chao_box_nil()".to_string(),
+        };
+        let val = self.operand(val);
+        return format!("call %chao.value @chao_box_{}({} {})", name, llvm_type(&ty), val);
+    }
+
+    /// A phi argument of type `ty`. These can only be constants or registers, which are
+    /// already of the right type.
+    fn incoming(&mut self, val: &IrValue, ty: &IrType) -> String {
+        if *val == IrValue::Nil && !matches!(ty, IrType::Optional(_) | IrType::Nil) {
+            return "undef".to_string();
+        }
+        return self.convert(val, ty);
+    }
+
+    /// A pointer to the first byte of a string literal, adding it to the module the first
+    /// time.
+    fn string(&mut self, s: &str) -> String {
+        let n = match self.strings.iter().position(|other| other == s) {
+            Some(n) => n,
+            None => {
+                self.strings.push(s.to_string());
+                self.strings.len() - 1
+            }
+        };
+        let array = format!("[{} x i8]", s.len() + 1);
+        return format!("getelementptr inbounds ({}, {}* @str{}, i64 0, i64 0)", array, array, n);
+    }
+
+    fn block(&self, label: usize) -> String {
+        return match self.blocks.get(&label) {
+            Some(b) => format!("%b{}", b),
+            None => format!("%L{}", label),
+        };
+    }
+
+    fn is_global(&self, val: &IrValue) -> bool {
+        return matches!(val, IrValue::Identifier(id) if self.globals.contains(id));
+    }
+
+    fn fresh(&mut self) -> String {
+        self.next += 1;
+        return format!("%r{}", self.next - 1);
+    }
+
+    fn emit(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("  ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+}
+
+/// The register a temporary or renamed binding is kept in.
+fn register(val: &IrValue) -> String {
+    return match val {
+        IrValue::Temp(t) => format!("%t{}", t),
+        IrValue::Identifier(id) => mangle("%v_", id),
+        _ => "%r.none".to_string(),
+    };
+}
+
+fn llvm_type(ty: &IrType) -> &'static str {
+    return match ty {
+        IrType::Int | IrType::Char => "i32",
+        IrType::Float => "float",
+        IrType::Bool => "i1",
+        IrType::Str => "i8*",
+        IrType::Nil => "i8",
+        IrType::Void => "void",
+        IrType::Optional(_) => "%chao.value",
+    };
+}
+
+/// The type tag the runtime uses for printing and comparing optionals.
+fn tag(ty: &IrType) -> u32 {
+    return match ty {
+        IrType::Nil | IrType::Void => 0,
+        IrType::Int => 1,
+        IrType::Float => 2,
+        IrType::Bool => 3,
+        IrType::Char => 4,
+        IrType::Str => 5,
+        IrType::Optional(inner) => tag(inner),
+    };
+}
+
+/// The runtime function for an int operator that can fail.
+fn checked(op: TokenKind) -> Option<&'static str> {
+    let helper = match op {
+        TokenKind::Plus => "chao_add",
+        TokenKind::Minus => "chao_sub",
+        TokenKind::Star => "chao_mul",
+        TokenKind::Slash => "chao_div",
+        TokenKind::Percent => "chao_rem",
+        TokenKind::StarStar => "chao_pow",
+        TokenKind::LessLess => "chao_shl",
+        TokenKind::GreaterGreater => "chao_shr",
+        _ => return None,
+    };
+    return Some(helper);
+}
+
+/// Whether a value of type `from` has to be boxed to go somewhere of type `to`.
+fn needs_box(to: &IrType, from: &IrType) -> bool {
+    return matches!(to, IrType::Optional(_)) && !matches!(from, IrType::Optional(_));
+}
+
+fn is_constant(val: &IrValue) -> bool {
+    return !matches!(val, IrValue::Temp(_) | IrValue::Identifier(_));
+}
+
+/// Whether values of this type can be compared with `icmp` directly.
+fn is_scalar(ty: &IrType) -> bool {
+    return matches!(ty, IrType::Int | IrType::Bool | IrType::Char);
+}
+
+/// The `icmp` condition for a comparison operator, `fcmp` takes the unsigned one with an
+/// `o` in front of it instead of the `u`.
+fn condition(op: TokenKind, signed: bool) -> Option<&'static str> {
+    let cond = match (op, signed) {
+        (TokenKind::Less, true) => "slt",
+        (TokenKind::Less, false) => "ult",
+        (TokenKind::LessEqual, true) => "sle",
+        (TokenKind::LessEqual, false) => "ule",
+        (TokenKind::Greater, true) => "sgt",
+        (TokenKind::Greater, false) => "ugt",
+        (TokenKind::GreaterEqual, true) => "sge",
+        (TokenKind::GreaterEqual, false) => "uge",
+        _ => return None,
+    };
+    return Some(cond);
+}
+
+fn is_comparison(op: TokenKind) -> bool {
+    return matches!(op, TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual);
+}
+
+/// Writes a string as an LLVM constant with its terminating null, anything outside
+/// printable ASCII as a hex escape.
+fn string_literal(s: &str) -> String {
+    let mut out = String::from("c\"");
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' => out.push_str(&format!("\\{:02X}", b)),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:02X}", b)),
+        }
+    }
+    out.push_str("\\00\"");
+    return out;
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use crate::analysis::irgen::{ IrFunction, IrInst, IrProgram, IrType, IrValue };
+
+pub(crate) mod c;
+pub(crate) mod linker;
+pub(crate) mod llvm;
+pub(crate) mod regalloc;
+pub(crate) mod wasm;
+pub(crate) mod x86_64;
+
+/// The type of every value in a program, since the IR only records types where values are
+/// defined.
+pub(crate) struct TypeMap {
+    /// Every named binding by IR name, which is unique across the whole program
+    names: HashMap<String, IrType>,
+    /// Temporaries of the function being generated
+    temps: HashMap<usize, IrType>,
+}
+
+impl TypeMap {
+    pub(crate) fn new(program: &IrProgram) -> TypeMap {
+        let mut names = HashMap::new();
+        for func in &program.functions {
+            for (id, ty) in &func.params {
+                names.insert(id.clone(), ty.clone());
+            }
+            for (id, ty) in func.body.iter().filter_map(named_dest) {
+                names.insert(id.clone(), ty.clone());
+            }
+        }
+        return TypeMap { names, temps: HashMap::new() };
+    }
+
+    /// Switches over to the temporaries of `func`.
+    pub(crate) fn enter(&mut self, func: &IrFunction) {
+        self.temps = func.body
+            .iter()
+            .filter_map(|inst| match (inst.dest(), dest_type(inst)) {
+                (Some(IrValue::Temp(t)), Some(ty)) => Some((t, ty.clone())),
+                _ => None,
+            })
+            .collect();
+    }
+
+    pub(crate) fn temps(&self) -> &HashMap<usize, IrType> {
+        return &self.temps;
+    }
+
+    pub(crate) fn of(&self, val: &IrValue) -> IrType {
+        return match val {
+            IrValue::Temp(t) => self.temps.get(t).cloned().unwrap_or(IrType::Void),
+            IrValue::Identifier(id) => self.names.get(id).cloned().unwrap_or(IrType::Void),
+            IrValue::ConstInt(_) => IrType::Int,
+            IrValue::ConstFloat(_) => IrType::Float,
+            IrValue::ConstStr(_) => IrType::Str,
+            IrValue::ConstChar(_) => IrType::Char,
+            IrValue::ConstBool(_) => IrType::Bool,
+            IrValue::Nil => IrType::Nil,
+        };
+    }
+}
+
+/// The binding an instruction declares or writes by name, along with its type.
+pub(crate) fn named_dest(inst: &IrInst) -> Option<(&String, &IrType)> {
+    return match inst {
+        IrInst::Bind { id, ty, val: _ } => Some((id, ty)),
+        IrInst::Copy { dest: IrValue::Identifier(id), ty, val: _ } |
+        IrInst::Phi { dest: IrValue::Identifier(id), ty, args: _ } => Some((id, ty)),
+        _ => None,
+    };
+}
+
+fn dest_type(inst: &IrInst) -> Option<&IrType> {
+    return match inst {
+        IrInst::Bind { id: _, ty, val: _ } |
+        IrInst::Copy { dest: _, ty, val: _ } |
+        IrInst::BinOp { dest: _, ty, lhs: _, op: _, rhs: _ } |
+        IrInst::UnOp { dest: _, ty, op: _, val: _ } |
+        IrInst::Call { dest: _, ty, func: _, args: _ } |
+        IrInst::Phi { dest: _, ty, args: _ } => Some(ty),
+        _ => None,
+    };
+}
+
+/// Whether a value of this type can point at a string made at runtime, which the native
+/// runtimes have to know about before freeing it.
+pub(crate) fn holds_string(ty: &IrType) -> bool {
+    return match ty {
+        IrType::Str => true,
+        IrType::Optional(inner) => holds_string(inner),
+        _ => false,
+    };
+}
+
+/// Every distinct binding in order of first appearance.
+pub(crate) fn declarations<'i>(
+    bindings: impl Iterator<Item = (&'i String, &'i IrType)>
+) -> Vec<(String, IrType)> {
+    let mut seen: Vec<(String, IrType)> = vec![];
+    for (id, ty) in bindings {
+        if !seen.iter().any(|(s, _)| s == id) {
+            seen.push((id.clone(), ty.clone()));
+        }
+    }
+    return seen;
+}
+
+/// Turns an IR name into a symbol both C and the assembler accept. Underscores are doubled
+/// so the characters IR names add, `.` for shadowing and `#` for SSA versions, can't collide
+/// with a name from the source.
+pub(crate) fn mangle(prefix: &str, id: &str) -> String {
+    let mut out = String::from(prefix);
+    for c in id.chars() {
+        match c {
+            '_' => out.push_str("__"),
+            '.' => out.push_str("_d"),
+            '#' => out.push_str("_h"),
+            _ if c.is_ascii_alphanumeric() => out.push(c),
+            _ => out.push_str(&format!("_u{:x}_", c as u32)),
+        }
+    }
+    return out;
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use crate::analysis::{ cfg::Cfg, irgen::{ IrFunction, IrValue }, liveness::Liveness, ssa::Var };
+
+/// The stretch of a function where a temporary holds a value, from its first definition to
+/// its last use. Positions count instructions over the blocks in order.
+#[derive(Debug, Clone)]
+pub(crate) struct Interval {
+    pub temp: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Location {
+    /// An index into the registers the allocator was given
+    Register(usize),
+    /// A stack slot, numbered from zero within the function
+    Spill(usize),
+}
+
+pub(crate) struct Allocation {
+    pub locations: HashMap<usize, Location>,
+    /// How many stack slots the spilled temporaries need
+    pub spills: usize,
+}
+
+/// Assigns every temporary of `func` one of `registers` registers or a stack slot.
+pub(crate) fn allocate(func: &IrFunction, registers: usize) -> Allocation {
+    return linear_scan(intervals(&Cfg::build(func)), registers);
+}
+
+/// The live interval of every temporary. A temporary that is live into or out of a block
+/// covers the whole block on that side, so loops keep their values alive all the way round.
+pub(crate) fn intervals(cfg: &Cfg) -> Vec<Interval> {
+    let liveness = Liveness::compute(cfg);
+    let mut ranges: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut cover = |temp: usize, pos: usize| {
+        let range = ranges.entry(temp).or_insert((pos, pos));
+        range.0 = range.0.min(pos);
+        range.1 = range.1.max(pos);
+    };
+
+    let mut pos = 0;
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        let start = pos;
+        for inst in &block.body {
+            let dest = inst.dest();
+            for val in inst.uses().into_iter().chain(dest.as_ref()) {
+                if let IrValue::Temp(t) = val {
+                    cover(*t, pos);
+                }
+            }
+            pos += 1;
+        }
+        // empty blocks still take up a position so their boundaries stay apart
+        let end = if pos == start { pos } else { pos - 1 };
+        pos = end + 1;
+
+        for var in &liveness.live_in[b] {
+            if let Var::Temp(t) = var {
+                cover(*t, start);
+            }
+        }
+        for var in &liveness.live_out[b] {
+            if let Var::Temp(t) = var {
+                cover(*t, end);
+            }
+        }
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(temp, (start, end))| Interval { temp, start, end })
+        .collect();
+    intervals.sort_by_key(|i| (i.start, i.temp));
+    return intervals;
+}
+
+/// Linear scan allocation over intervals sorted by start. When every register is taken, the
+/// interval that ends last gives up its register and lives on the stack instead.
+pub(crate) fn linear_scan(intervals: Vec<Interval>, registers: usize) -> Allocation {
+    let mut locations: HashMap<usize, Location> = HashMap::new();
+    let mut spills = 0;
+    // intervals currently holding a register, sorted by end
+    let mut active: Vec<(Interval, usize)> = vec![];
+    let mut free: Vec<usize> = (0..registers).rev().collect();
+
+    for interval in intervals {
+        // anything that ended before this starts gives its register back
+        while let Some((first, reg)) = active.first()
+            && first.end < interval.start
+        {
+            free.push(*reg);
+            active.remove(0);
+        }
+
+        let reg = match free.pop() {
+            Some(reg) => reg,
+            None => {
+                match active.last() {
+                    Some((last, reg)) if last.end > interval.end => {
+                        let reg = *reg;
+                        locations.insert(last.temp, Location::Spill(spills));
+                        spills += 1;
+                        active.pop();
+                        reg
+                    }
+                    _ => {
+                        locations.insert(interval.temp, Location::Spill(spills));
+                        spills += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        locations.insert(interval.temp, Location::Register(reg));
+        let at = active.partition_point(|(a, _)| a.end <= interval.end);
+        active.insert(at, (interval, reg));
+    }
+    return Allocation { locations, spills };
+}
@@ -0,0 +1,785 @@
+; Runtime for Chao programs compiled to LLVM IR, copied into every generated module. It only
+; needs the C library, so `clang out.ll -lm` or `llc` and a C compiler turn a module into an
+; executable.
+;
+; Integer arithmetic is checked the same way as on the VM, and anything that would fail there
+; stops the program with a runtime error on stderr and exit code 1.
+
+; a value of any type, used for optionals and for printing, as a tag and the bits of the value
+%chao.value = type { i32, i64 }
+; a global that can hold a string, which keeps the string alive, as its address and size
+%chao.root = type { i8*, i64 }
+; a string made at runtime, followed by its bytes: the next string, how many bytes follow and
+; whether the string is reachable
+%chao.object = type { %chao.object*, i64, i1 }
+
+declare i32 @printf(i8*, ...)
+declare i32 @dprintf(i32, i8*, ...)
+declare i32 @snprintf(i8*, i64, i8*, ...)
+declare i32 @putchar(i32)
+declare i32 @fflush(i8*)
+declare void @exit(i32) noreturn
+declare i8* @malloc(i64)
+declare void @free(i8*)
+declare i64 @strlen(i8*)
+declare i32 @strcmp(i8*, i8*)
+declare i8* @strchr(i8*, i32)
+declare i32 @atoi(i8*)
+declare float @strtof(i8*, i8**)
+declare float @fmodf(float, float)
+declare float @powf(float, float)
+declare void @llvm.memcpy.p0i8.p0i8.i64(i8*, i8*, i64, i1)
+declare void @llvm.eh.unwind.init()
+declare float @llvm.fabs.f32(float)
+declare { i32, i1 } @llvm.sadd.with.overflow.i32(i32, i32)
+declare { i32, i1 } @llvm.ssub.with.overflow.i32(i32, i32)
+declare { i32, i1 } @llvm.smul.with.overflow.i32(i32, i32)
+
+@chao.fmt.panic = private unnamed_addr constant [19 x i8] c"runtime error: %s\0A\00"
+@chao.fmt.int = private unnamed_addr constant [3 x i8] c"%d\00"
+@chao.fmt.str = private unnamed_addr constant [3 x i8] c"%s\00"
+@chao.fmt.prefix = private unnamed_addr constant [5 x i8] c"%.*s\00"
+@chao.fmt.exact = private unnamed_addr constant [7 x i8] c"%.111e\00"
+@chao.fmt.digits = private unnamed_addr constant [11 x i8] c"%c.%.*se%d\00"
+@chao.text.nil = private unnamed_addr constant [4 x i8] c"nil\00"
+@chao.text.true = private unnamed_addr constant [5 x i8] c"true\00"
+@chao.text.false = private unnamed_addr constant [6 x i8] c"false\00"
+@chao.text.nan = private unnamed_addr constant [4 x i8] c"NaN\00"
+@chao.text.inf = private unnamed_addr constant [4 x i8] c"inf\00"
+@chao.text.neginf = private unnamed_addr constant [5 x i8] c"-inf\00"
+@chao.text.point = private unnamed_addr constant [3 x i8] c"0.\00"
+@chao.msg.add = private unnamed_addr constant [29 x i8] c"integer overflow in addition\00"
+@chao.msg.sub = private unnamed_addr constant [32 x i8] c"integer overflow in subtraction\00"
+@chao.msg.mul = private unnamed_addr constant [35 x i8] c"integer overflow in multiplication\00"
+@chao.msg.div = private unnamed_addr constant [29 x i8] c"integer overflow in division\00"
+@chao.msg.rem = private unnamed_addr constant [30 x i8] c"integer overflow in remainder\00"
+@chao.msg.pow = private unnamed_addr constant [26 x i8] c"integer overflow in power\00"
+@chao.msg.neg = private unnamed_addr constant [29 x i8] c"integer overflow in negation\00"
+@chao.msg.zero = private unnamed_addr constant [17 x i8] c"division by zero\00"
+@chao.msg.exponent = private unnamed_addr constant [35 x i8] c"negative exponent in integer power\00"
+@chao.msg.shift = private unnamed_addr constant [26 x i8] c"shift amount out of range\00"
+@chao.msg.stack = private unnamed_addr constant [15 x i8] c"stack overflow\00"
+@chao.msg.memory = private unnamed_addr constant [14 x i8] c"out of memory\00"
+
+; calls in progress, the program stops once there are as many as on the VM
+@chao.depth = internal global i32 0
+
+define internal void @chao_panic(i8* %msg) noreturn {
+  %flushed = call i32 @fflush(i8* null)
+  %fmt = getelementptr inbounds [19 x i8], [19 x i8]* @chao.fmt.panic, i64 0, i64 0
+  %written = call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %fmt, i8* %msg)
+  call void @exit(i32 1)
+  unreachable
+}
+
+define internal void @chao_enter() {
+  %depth = load i32, i32* @chao.depth
+  %next = add i32 %depth, 1
+  store i32 %next, i32* @chao.depth
+  %deep = icmp sgt i32 %next, 1024
+  br i1 %deep, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([15 x i8], [15 x i8]* @chao.msg.stack, i64 0, i64 0))
+  unreachable
+ok:
+  ret void
+}
+
+define internal void @chao_leave() {
+  %depth = load i32, i32* @chao.depth
+  %next = sub i32 %depth, 1
+  store i32 %next, i32* @chao.depth
+  ret void
+}
+
+; values
+
+define internal %chao.value @chao_box_nil() {
+  ret %chao.value { i32 0, i64 0 }
+}
+
+define internal %chao.value @chao_box_int(i32 %v) {
+  %bits = zext i32 %v to i64
+  %r = insertvalue %chao.value { i32 1, i64 0 }, i64 %bits, 1
+  ret %chao.value %r
+}
+
+define internal %chao.value @chao_box_float(float %v) {
+  %raw = bitcast float %v to i32
+  %bits = zext i32 %raw to i64
+  %r = insertvalue %chao.value { i32 2, i64 0 }, i64 %bits, 1
+  ret %chao.value %r
+}
+
+define internal %chao.value @chao_box_bool(i1 %v) {
+  %bits = zext i1 %v to i64
+  %r = insertvalue %chao.value { i32 3, i64 0 }, i64 %bits, 1
+  ret %chao.value %r
+}
+
+define internal %chao.value @chao_box_char(i32 %v) {
+  %bits = zext i32 %v to i64
+  %r = insertvalue %chao.value { i32 4, i64 0 }, i64 %bits, 1
+  ret %chao.value %r
+}
+
+define internal %chao.value @chao_box_str(i8* %v) {
+  %bits = ptrtoint i8* %v to i64
+  %r = insertvalue %chao.value { i32 5, i64 0 }, i64 %bits, 1
+  ret %chao.value %r
+}
+
+; values of different types are never equal
+define internal i1 @chao_value_eq(%chao.value %a, %chao.value %b) {
+  %ta = extractvalue %chao.value %a, 0
+  %tb = extractvalue %chao.value %b, 0
+  %va = extractvalue %chao.value %a, 1
+  %vb = extractvalue %chao.value %b, 1
+  %same = icmp eq i32 %ta, %tb
+  br i1 %same, label %compare, label %differ
+differ:
+  ret i1 false
+compare:
+  switch i32 %ta, label %bits [ i32 0, label %nil
+                                i32 2, label %float
+                                i32 5, label %str ]
+nil:
+  ret i1 true
+float:
+  %ra = trunc i64 %va to i32
+  %rb = trunc i64 %vb to i32
+  %fa = bitcast i32 %ra to float
+  %fb = bitcast i32 %rb to float
+  %feq = fcmp oeq float %fa, %fb
+  ret i1 %feq
+str:
+  %sa = inttoptr i64 %va to i8*
+  %sb = inttoptr i64 %vb to i8*
+  %order = call i32 @strcmp(i8* %sa, i8* %sb)
+  %seq = icmp eq i32 %order, 0
+  ret i1 %seq
+bits:
+  %eq = icmp eq i64 %va, %vb
+  ret i1 %eq
+}
+
+; integers
+
+define internal i32 @chao_add(i32 %a, i32 %b) {
+  %r = call { i32, i1 } @llvm.sadd.with.overflow.i32(i32 %a, i32 %b)
+  %over = extractvalue { i32, i1 } %r, 1
+  br i1 %over, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([29 x i8], [29 x i8]* @chao.msg.add, i64 0, i64 0))
+  unreachable
+ok:
+  %v = extractvalue { i32, i1 } %r, 0
+  ret i32 %v
+}
+
+define internal i32 @chao_sub(i32 %a, i32 %b) {
+  %r = call { i32, i1 } @llvm.ssub.with.overflow.i32(i32 %a, i32 %b)
+  %over = extractvalue { i32, i1 } %r, 1
+  br i1 %over, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([32 x i8], [32 x i8]* @chao.msg.sub, i64 0, i64 0))
+  unreachable
+ok:
+  %v = extractvalue { i32, i1 } %r, 0
+  ret i32 %v
+}
+
+define internal i32 @chao_mul(i32 %a, i32 %b) {
+  %r = call { i32, i1 } @llvm.smul.with.overflow.i32(i32 %a, i32 %b)
+  %over = extractvalue { i32, i1 } %r, 1
+  br i1 %over, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([35 x i8], [35 x i8]* @chao.msg.mul, i64 0, i64 0))
+  unreachable
+ok:
+  %v = extractvalue { i32, i1 } %r, 0
+  ret i32 %v
+}
+
+define internal i32 @chao_div(i32 %a, i32 %b) {
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %fail_zero, label %nonzero
+fail_zero:
+  call void @chao_panic(i8* getelementptr inbounds ([17 x i8], [17 x i8]* @chao.msg.zero, i64 0, i64 0))
+  unreachable
+nonzero:
+  %min = icmp eq i32 %a, -2147483648
+  %minus_one = icmp eq i32 %b, -1
+  %over = and i1 %min, %minus_one
+  br i1 %over, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([29 x i8], [29 x i8]* @chao.msg.div, i64 0, i64 0))
+  unreachable
+ok:
+  %v = sdiv i32 %a, %b
+  ret i32 %v
+}
+
+define internal i32 @chao_rem(i32 %a, i32 %b) {
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %fail_zero, label %nonzero
+fail_zero:
+  call void @chao_panic(i8* getelementptr inbounds ([17 x i8], [17 x i8]* @chao.msg.zero, i64 0, i64 0))
+  unreachable
+nonzero:
+  %min = icmp eq i32 %a, -2147483648
+  %minus_one = icmp eq i32 %b, -1
+  %over = and i1 %min, %minus_one
+  br i1 %over, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([30 x i8], [30 x i8]* @chao.msg.rem, i64 0, i64 0))
+  unreachable
+ok:
+  %v = srem i32 %a, %b
+  ret i32 %v
+}
+
+; exponentiation by squaring, failing on the same inputs as the VM
+define internal i32 @chao_pow(i32 %base, i32 %exp) {
+entry:
+  %negative = icmp slt i32 %exp, 0
+  br i1 %negative, label %fail, label %start
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([35 x i8], [35 x i8]* @chao.msg.exponent, i64 0, i64 0))
+  unreachable
+start:
+  %zero = icmp eq i32 %exp, 0
+  br i1 %zero, label %one, label %loop
+one:
+  ret i32 1
+loop:
+  %acc = phi i32 [ 1, %start ], [ %acc.next, %square ]
+  %b = phi i32 [ %base, %start ], [ %b.next, %square ]
+  %e = phi i32 [ %exp, %start ], [ %e.next, %square ]
+  %more = icmp sgt i32 %e, 1
+  br i1 %more, label %step, label %done
+step:
+  %odd = and i32 %e, 1
+  %is_odd = icmp ne i32 %odd, 0
+  br i1 %is_odd, label %multiply, label %square
+multiply:
+  %product = call i32 @chao_pow_mul(i32 %acc, i32 %b)
+  br label %square
+square:
+  %acc.next = phi i32 [ %acc, %step ], [ %product, %multiply ]
+  %e.next = lshr i32 %e, 1
+  %b.next = call i32 @chao_pow_mul(i32 %b, i32 %b)
+  br label %loop
+done:
+  %result = call i32 @chao_pow_mul(i32 %acc, i32 %b)
+  ret i32 %result
+}
+
+define internal i32 @chao_pow_mul(i32 %a, i32 %b) {
+  %r = call { i32, i1 } @llvm.smul.with.overflow.i32(i32 %a, i32 %b)
+  %over = extractvalue { i32, i1 } %r, 1
+  br i1 %over, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([26 x i8], [26 x i8]* @chao.msg.pow, i64 0, i64 0))
+  unreachable
+ok:
+  %v = extractvalue { i32, i1 } %r, 0
+  ret i32 %v
+}
+
+define internal i32 @chao_shl(i32 %a, i32 %b) {
+  %range = icmp uge i32 %b, 32
+  br i1 %range, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([26 x i8], [26 x i8]* @chao.msg.shift, i64 0, i64 0))
+  unreachable
+ok:
+  %v = shl i32 %a, %b
+  ret i32 %v
+}
+
+define internal i32 @chao_shr(i32 %a, i32 %b) {
+  %range = icmp uge i32 %b, 32
+  br i1 %range, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([26 x i8], [26 x i8]* @chao.msg.shift, i64 0, i64 0))
+  unreachable
+ok:
+  %v = ashr i32 %a, %b
+  ret i32 %v
+}
+
+define internal i32 @chao_neg(i32 %a) {
+  %min = icmp eq i32 %a, -2147483648
+  br i1 %min, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([29 x i8], [29 x i8]* @chao.msg.neg, i64 0, i64 0))
+  unreachable
+ok:
+  %v = sub i32 0, %a
+  ret i32 %v
+}
+
+; strings, which are garbage collected
+;
+; Once enough bytes of strings were made since the last collection, the stack and the globals
+; are scanned for words pointing at one and every string nothing points at is freed. The scan
+; is conservative, anything that looks like a pointer to a string keeps it alive.
+
+@chao.stack_base = internal global i8* null
+@chao.roots = internal global %chao.root* null
+@chao.root_count = internal global i64 0
+@chao.objects = internal global %chao.object* null
+@chao.heap_low = internal global i64 -1
+@chao.heap_high = internal global i64 0
+; bytes in strings that haven't been freed, and how many there can be before a collection
+@chao.heap_size = internal global i64 0
+@chao.heap_limit = internal global i64 1048576
+
+; `base` has to live in the frame of `main`, every frame the program makes is past it
+define internal void @chao_start(i8* %base, %chao.root* %roots, i64 %count) {
+  store i8* %base, i8** @chao.stack_base
+  store %chao.root* %roots, %chao.root** @chao.roots
+  store i64 %count, i64* @chao.root_count
+  ret void
+}
+
+; marks every string a word between `from` and `to` points at, they can come in either order
+define internal void @chao_mark_words(i8* %from, i8* %to) {
+entry:
+  %f = ptrtoint i8* %from to i64
+  %t = ptrtoint i8* %to to i64
+  %up = icmp ult i64 %f, %t
+  %lo = select i1 %up, i64 %f, i64 %t
+  %hi = select i1 %up, i64 %t, i64 %f
+  %lo.up = add i64 %lo, 7
+  %start = and i64 %lo.up, -8
+  br label %loop
+loop:
+  %at = phi i64 [ %start, %entry ], [ %next, %word ], [ %next, %search ], [ %next, %found ]
+  %next = add i64 %at, 8
+  %more = icmp ule i64 %next, %hi
+  br i1 %more, label %word, label %done
+word:
+  %p = inttoptr i64 %at to i64*
+  %w = load i64, i64* %p
+  %low = load i64, i64* @chao.heap_low
+  %high = load i64, i64* @chao.heap_high
+  %below = icmp ult i64 %w, %low
+  %above = icmp ugt i64 %w, %high
+  %outside = or i1 %below, %above
+  %first = load %chao.object*, %chao.object** @chao.objects
+  br i1 %outside, label %loop, label %search
+search:
+  %o = phi %chao.object* [ %first, %word ], [ %o.next, %check ]
+  %end = icmp eq %chao.object* %o, null
+  br i1 %end, label %loop, label %check
+check:
+  %bytes = getelementptr %chao.object, %chao.object* %o, i64 1
+  %b = ptrtoint %chao.object* %bytes to i64
+  %link = getelementptr %chao.object, %chao.object* %o, i64 0, i32 0
+  %o.next = load %chao.object*, %chao.object** %link
+  %hit = icmp eq i64 %b, %w
+  br i1 %hit, label %found, label %search
+found:
+  %mark = getelementptr %chao.object, %chao.object* %o, i64 0, i32 2
+  store i1 true, i1* %mark
+  br label %loop
+done:
+  ret void
+}
+
+; kept out of line so its frame is past the registers `chao_collect` saved
+define internal void @chao_mark_stack() noinline {
+  %top = alloca i8
+  %base = load i8*, i8** @chao.stack_base
+  call void @chao_mark_words(i8* %top, i8* %base)
+  ret void
+}
+
+define internal void @chao_collect() {
+entry:
+  ; every callee-saved register goes on the stack, where the scan finds what they hold
+  call void @llvm.eh.unwind.init()
+  call void @chao_mark_stack()
+  %roots = load %chao.root*, %chao.root** @chao.roots
+  %count = load i64, i64* @chao.root_count
+  br label %globals
+globals:
+  %i = phi i64 [ 0, %entry ], [ %i.next, %global ]
+  %more = icmp ult i64 %i, %count
+  br i1 %more, label %global, label %sweep
+global:
+  %at.p = getelementptr %chao.root, %chao.root* %roots, i64 %i, i32 0
+  %at = load i8*, i8** %at.p
+  %size.p = getelementptr %chao.root, %chao.root* %roots, i64 %i, i32 1
+  %size = load i64, i64* %size.p
+  %to = getelementptr i8, i8* %at, i64 %size
+  call void @chao_mark_words(i8* %at, i8* %to)
+  %i.next = add i64 %i, 1
+  br label %globals
+sweep:
+  %link = phi %chao.object** [ @chao.objects, %globals ], [ %o.link, %keep ], [ %link, %drop ]
+  %kept = phi i64 [ 0, %globals ], [ %kept.next, %keep ], [ %kept, %drop ]
+  %o = load %chao.object*, %chao.object** %link
+  %last = icmp eq %chao.object* %o, null
+  br i1 %last, label %done, label %visit
+visit:
+  %o.link = getelementptr %chao.object, %chao.object* %o, i64 0, i32 0
+  %o.mark = getelementptr %chao.object, %chao.object* %o, i64 0, i32 2
+  %marked = load i1, i1* %o.mark
+  br i1 %marked, label %keep, label %drop
+keep:
+  store i1 false, i1* %o.mark
+  %o.size.p = getelementptr %chao.object, %chao.object* %o, i64 0, i32 1
+  %o.size = load i64, i64* %o.size.p
+  %kept.next = add i64 %kept, %o.size
+  br label %sweep
+drop:
+  %o.next = load %chao.object*, %chao.object** %o.link
+  store %chao.object* %o.next, %chao.object** %link
+  %raw = bitcast %chao.object* %o to i8*
+  call void @free(i8* %raw)
+  br label %sweep
+done:
+  ; the next collection waits until the heap doubled
+  store i64 %kept, i64* @chao.heap_size
+  %double = shl i64 %kept, 1
+  %small = icmp ult i64 %double, 1048576
+  %limit = select i1 %small, i64 1048576, i64 %double
+  store i64 %limit, i64* @chao.heap_limit
+  ret void
+}
+
+define internal i8* @chao_alloc(i64 %size) {
+entry:
+  %used = load i64, i64* @chao.heap_size
+  %after = add i64 %used, %size
+  %limit = load i64, i64* @chao.heap_limit
+  %full = icmp ugt i64 %after, %limit
+  br i1 %full, label %collect, label %alloc
+collect:
+  call void @chao_collect()
+  br label %alloc
+alloc:
+  %header.end = getelementptr %chao.object, %chao.object* null, i64 1
+  %header = ptrtoint %chao.object* %header.end to i64
+  %total = add i64 %header, %size
+  %raw = call i8* @malloc(i64 %total)
+  %null = icmp eq i8* %raw, null
+  br i1 %null, label %fail, label %ok
+fail:
+  call void @chao_panic(i8* getelementptr inbounds ([14 x i8], [14 x i8]* @chao.msg.memory, i64 0, i64 0))
+  unreachable
+ok:
+  %o = bitcast i8* %raw to %chao.object*
+  %first = load %chao.object*, %chao.object** @chao.objects
+  %link = getelementptr %chao.object, %chao.object* %o, i64 0, i32 0
+  store %chao.object* %first, %chao.object** %link
+  %size.p = getelementptr %chao.object, %chao.object* %o, i64 0, i32 1
+  store i64 %size, i64* %size.p
+  %mark = getelementptr %chao.object, %chao.object* %o, i64 0, i32 2
+  store i1 false, i1* %mark
+  store %chao.object* %o, %chao.object** @chao.objects
+  %heap = load i64, i64* @chao.heap_size
+  %heap.next = add i64 %heap, %size
+  store i64 %heap.next, i64* @chao.heap_size
+
+  %bytes = getelementptr %chao.object, %chao.object* %o, i64 1
+  %s = bitcast %chao.object* %bytes to i8*
+  %b = ptrtoint i8* %s to i64
+  %low = load i64, i64* @chao.heap_low
+  %lower = icmp ult i64 %b, %low
+  %low.next = select i1 %lower, i64 %b, i64 %low
+  store i64 %low.next, i64* @chao.heap_low
+  %high = load i64, i64* @chao.heap_high
+  %higher = icmp ugt i64 %b, %high
+  %high.next = select i1 %higher, i64 %b, i64 %high
+  store i64 %high.next, i64* @chao.heap_high
+  ret i8* %s
+}
+
+define internal i8* @chao_concat(i8* %a, i8* %b) {
+  %la = call i64 @strlen(i8* %a)
+  %lb = call i64 @strlen(i8* %b)
+  %sum = add i64 %la, %lb
+  %size = add i64 %sum, 1
+  %s = call i8* @chao_alloc(i64 %size)
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %s, i8* %a, i64 %la, i1 false)
+  %tail = getelementptr inbounds i8, i8* %s, i64 %la
+  %lb.nul = add i64 %lb, 1
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %tail, i8* %b, i64 %lb.nul, i1 false)
+  ret i8* %s
+}
+
+; printing
+
+define internal void @chao_write_str(i8* %s) {
+  %fmt = getelementptr inbounds [3 x i8], [3 x i8]* @chao.fmt.str, i64 0, i64 0
+  %written = call i32 (i8*, ...) @printf(i8* %fmt, i8* %s)
+  ret void
+}
+
+; the first `count` bytes of `s`
+define internal void @chao_write_prefix(i8* %s, i32 %count) {
+  %fmt = getelementptr inbounds [5 x i8], [5 x i8]* @chao.fmt.prefix, i64 0, i64 0
+  %written = call i32 (i8*, ...) @printf(i8* %fmt, i32 %count, i8* %s)
+  ret void
+}
+
+define internal void @chao_write_byte(i32 %b) {
+  %written = call i32 @putchar(i32 %b)
+  ret void
+}
+
+; a unicode scalar value as UTF-8
+define internal void @chao_write_char(i32 %c) {
+entry:
+  %one = icmp ult i32 %c, 128
+  br i1 %one, label %ascii, label %wide
+ascii:
+  call void @chao_write_byte(i32 %c)
+  ret void
+wide:
+  %low = and i32 %c, 63
+  %last = or i32 %low, 128
+  %shift6 = lshr i32 %c, 6
+  %two = icmp ult i32 %c, 2048
+  br i1 %two, label %pair, label %wider
+pair:
+  %lead2 = or i32 %shift6, 192
+  call void @chao_write_byte(i32 %lead2)
+  call void @chao_write_byte(i32 %last)
+  ret void
+wider:
+  %mid6 = and i32 %shift6, 63
+  %mid = or i32 %mid6, 128
+  %shift12 = lshr i32 %c, 12
+  %three = icmp ult i32 %c, 65536
+  br i1 %three, label %triple, label %quad
+triple:
+  %lead3 = or i32 %shift12, 224
+  call void @chao_write_byte(i32 %lead3)
+  call void @chao_write_byte(i32 %mid)
+  call void @chao_write_byte(i32 %last)
+  ret void
+quad:
+  %high6 = and i32 %shift12, 63
+  %high = or i32 %high6, 128
+  %shift18 = lshr i32 %c, 18
+  %lead4 = or i32 %shift18, 240
+  call void @chao_write_byte(i32 %lead4)
+  call void @chao_write_byte(i32 %high)
+  call void @chao_write_byte(i32 %mid)
+  call void @chao_write_byte(i32 %last)
+  ret void
+}
+
+; Rounds the first `count` digits of `exact` down or up and checks whether they read back as
+; `v`. On success the digits and their exponent are left in `digits` and `exp`.
+define internal i1 @chao_float_digits(float %v, i8* %exact, i32 %count, i1 %up, i8* %digits, i32* %exp) {
+entry:
+  %buf = alloca [32 x i8]
+  %e = load i32, i32* %exp
+  %size = zext i32 %count to i64
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %digits, i8* %exact, i64 %size, i1 false)
+  %last = sub i32 %count, 1
+  br i1 %up, label %carry, label %check
+carry:
+  %j = phi i32 [ %last, %entry ], [ %j.next, %nine ]
+  %in = icmp sge i32 %j, 0
+  br i1 %in, label %digit, label %overflow
+digit:
+  %at = getelementptr inbounds i8, i8* %digits, i32 %j
+  %d = load i8, i8* %at
+  %is_nine = icmp eq i8 %d, 57
+  br i1 %is_nine, label %nine, label %increment
+nine:
+  store i8 48, i8* %at
+  %j.next = sub i32 %j, 1
+  br label %carry
+increment:
+  %d.next = add i8 %d, 1
+  store i8 %d.next, i8* %at
+  br label %check
+overflow:
+  store i8 49, i8* %digits
+  %e.up = add i32 %e, 1
+  br label %check
+check:
+  %e.final = phi i32 [ %e, %entry ], [ %e, %increment ], [ %e.up, %overflow ]
+  %first = load i8, i8* %digits
+  %first.int = zext i8 %first to i32
+  %rest = getelementptr inbounds i8, i8* %digits, i64 1
+  %b = getelementptr inbounds [32 x i8], [32 x i8]* %buf, i64 0, i64 0
+  %fmt = getelementptr inbounds [11 x i8], [11 x i8]* @chao.fmt.digits, i64 0, i64 0
+  %written = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %b, i64 32, i8* %fmt, i32 %first.int, i32 %last, i8* %rest, i32 %e.final)
+  %back = call float @strtof(i8* %b, i8** null)
+  %same = fcmp oeq float %back, %v
+  br i1 %same, label %found, label %missed
+found:
+  store i32 %e.final, i32* %exp
+  ret i1 true
+missed:
+  ret i1 false
+}
+
+; The fewest digits that read back as the same float, written out without an exponent. The
+; digits nearest the value win, with ties rounding up like they do on the VM, so they come
+; from the exact value rather than printf, which rounds ties to even. No float has more than
+; 112 significant digits.
+define internal void @chao_write_float(float %v) {
+entry:
+  %exact = alloca [128 x i8]
+  %digits = alloca [16 x i8]
+  %exp = alloca i32
+  %nan = fcmp uno float %v, %v
+  br i1 %nan, label %is_nan, label %number
+is_nan:
+  call void @chao_write_str(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @chao.text.nan, i64 0, i64 0))
+  ret void
+number:
+  %abs = call float @llvm.fabs.f32(float %v)
+  %inf = fcmp oeq float %abs, 0x7FF0000000000000
+  br i1 %inf, label %is_inf, label %finite
+is_inf:
+  %below = fcmp olt float %v, 0.0
+  %text = select i1 %below, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @chao.text.neginf, i64 0, i64 0), i8* getelementptr inbounds ([4 x i8], [4 x i8]* @chao.text.inf, i64 0, i64 0)
+  call void @chao_write_str(i8* %text)
+  ret void
+finite:
+  %bits = bitcast float %v to i32
+  %signed = icmp slt i32 %bits, 0
+  br i1 %signed, label %minus, label %expand
+minus:
+  call void @chao_write_byte(i32 45)
+  br label %expand
+expand:
+  %ex = getelementptr inbounds [128 x i8], [128 x i8]* %exact, i64 0, i64 0
+  %dg = getelementptr inbounds [16 x i8], [16 x i8]* %digits, i64 0, i64 0
+  %wide = fpext float %abs to double
+  %fmt = getelementptr inbounds [7 x i8], [7 x i8]* @chao.fmt.exact, i64 0, i64 0
+  %written = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %ex, i64 128, i8* %fmt, double %wide)
+  %marker = call i8* @strchr(i8* %ex, i32 101)
+  %power = getelementptr inbounds i8, i8* %marker, i64 1
+  %e = call i32 @atoi(i8* %power)
+  store i32 %e, i32* %exp
+  ; drop the point so the digits are all in a row
+  %lead = load i8, i8* %ex
+  %row = getelementptr inbounds i8, i8* %ex, i64 1
+  store i8 %lead, i8* %row
+  br label %shortest
+shortest:
+  %count = phi i32 [ 1, %expand ], [ %count.next, %longer ]
+  %after = add i32 %count, 1
+  %next.at = getelementptr inbounds i8, i8* %ex, i32 %after
+  %next = load i8, i8* %next.at
+  %up = icmp uge i8 %next, 53
+  %near = call i1 @chao_float_digits(float %abs, i8* %row, i32 %count, i1 %up, i8* %dg, i32* %exp)
+  br i1 %near, label %trim, label %other
+other:
+  %down = xor i1 %up, true
+  %far = call i1 @chao_float_digits(float %abs, i8* %row, i32 %count, i1 %down, i8* %dg, i32* %exp)
+  br i1 %far, label %trim, label %longer
+longer:
+  %count.next = add i32 %count, 1
+  %more = icmp slt i32 %count.next, 9
+  br i1 %more, label %shortest, label %nine
+nine:
+  ; nine digits always read back, nearest first
+  %ninth.at = getelementptr inbounds i8, i8* %ex, i64 10
+  %ninth = load i8, i8* %ninth.at
+  %up9 = icmp uge i8 %ninth, 53
+  %always = call i1 @chao_float_digits(float %abs, i8* %row, i32 9, i1 %up9, i8* %dg, i32* %exp)
+  br label %trim
+trim:
+  %n = phi i32 [ %count, %shortest ], [ %count, %other ], [ 9, %nine ], [ %n.less, %zero ]
+  %several = icmp sgt i32 %n, 1
+  br i1 %several, label %trailing, label %write
+trailing:
+  %n.less = sub i32 %n, 1
+  %last.at = getelementptr inbounds i8, i8* %dg, i32 %n.less
+  %last = load i8, i8* %last.at
+  %is_zero = icmp eq i8 %last, 48
+  br i1 %is_zero, label %zero, label %write
+zero:
+  br label %trim
+write:
+  %final = load i32, i32* %exp
+  %small = icmp slt i32 %final, 0
+  br i1 %small, label %fraction, label %whole_or_mixed
+fraction:
+  call void @chao_write_str(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @chao.text.point, i64 0, i64 0))
+  br label %fraction.zeros
+fraction.zeros:
+  %i = phi i32 [ -1, %fraction ], [ %i.next, %fraction.zero ]
+  %pad = icmp sgt i32 %i, %final
+  br i1 %pad, label %fraction.zero, label %fraction.digits
+fraction.zero:
+  call void @chao_write_byte(i32 48)
+  %i.next = sub i32 %i, 1
+  br label %fraction.zeros
+fraction.digits:
+  call void @chao_write_prefix(i8* %dg, i32 %n)
+  ret void
+whole_or_mixed:
+  %n.last = sub i32 %n, 1
+  %whole = icmp sge i32 %final, %n.last
+  br i1 %whole, label %integral, label %mixed
+integral:
+  call void @chao_write_prefix(i8* %dg, i32 %n)
+  br label %integral.zeros
+integral.zeros:
+  %k = phi i32 [ %n.last, %integral ], [ %k.next, %integral.zero ]
+  %fill = icmp slt i32 %k, %final
+  br i1 %fill, label %integral.zero, label %done
+integral.zero:
+  call void @chao_write_byte(i32 48)
+  %k.next = add i32 %k, 1
+  br label %integral.zeros
+mixed:
+  %before = add i32 %final, 1
+  call void @chao_write_prefix(i8* %dg, i32 %before)
+  call void @chao_write_byte(i32 46)
+  %tail = getelementptr inbounds i8, i8* %dg, i32 %before
+  %after.point = sub i32 %n, %before
+  call void @chao_write_prefix(i8* %tail, i32 %after.point)
+  br label %done
+done:
+  ret void
+}
+
+define internal void @chao_write(%chao.value %v) {
+entry:
+  %tag = extractvalue %chao.value %v, 0
+  %bits = extractvalue %chao.value %v, 1
+  %low = trunc i64 %bits to i32
+  switch i32 %tag, label %nil [ i32 1, label %int
+                                i32 2, label %float
+                                i32 3, label %bool
+                                i32 4, label %char
+                                i32 5, label %str ]
+nil:
+  call void @chao_write_str(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @chao.text.nil, i64 0, i64 0))
+  ret void
+int:
+  %fmt = getelementptr inbounds [3 x i8], [3 x i8]* @chao.fmt.int, i64 0, i64 0
+  %written = call i32 (i8*, ...) @printf(i8* %fmt, i32 %low)
+  ret void
+float:
+  %f = bitcast i32 %low to float
+  call void @chao_write_float(float %f)
+  ret void
+bool:
+  %b = icmp ne i32 %low, 0
+  %text = select i1 %b, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @chao.text.true, i64 0, i64 0), i8* getelementptr inbounds ([6 x i8], [6 x i8]* @chao.text.false, i64 0, i64 0)
+  call void @chao_write_str(i8* %text)
+  ret void
+char:
+  call void @chao_write_char(i32 %low)
+  ret void
+str:
+  %s = inttoptr i64 %bits to i8*
+  call void @chao_write_str(i8* %s)
+  ret void
+}
@@ -0,0 +1,1066 @@
+# Runtime for Chao programs compiled to x86-64. It is appended to every generated file and
+# only talks to Linux through system calls, so a program links on its own with `ld`.
+#
+# Every function follows the System V calling convention. Values are 64 bit words: ints,
+# floats and chars in the low 32 bits, bools as 0 or 1, strings as pointers to bytes ending
+# in zero and optionals with bit 32 set when there is a value. Optional strings are instead
+# nil when the pointer is null. Type tags passed to the printing functions are 0 for nil,
+# then int, float, bool, char and str.
+#
+# The generated code puts its globals between `chao_globals` and `chao_globals_end`, which
+# the garbage collector scans along with the stack.
+
+    .equ CHAO_OUT_SIZE, 4096
+    .equ CHAO_HEAP_MIN, 1048576
+    .equ CHAO_PAGE, 4096
+    .equ CHAO_MAX_DEPTH, 1024
+
+    .text
+
+    .globl _start
+_start:
+    xorl %ebp, %ebp
+    movq %rsp, chao_stack_base(%rip)
+    call chao_script
+    call chao_flush
+    movl $60, %eax
+    xorl %edi, %edi
+    syscall
+
+# calls and returns of user functions, which stop the program when they nest too deeply
+
+chao_enter:
+    incq chao_depth(%rip)
+    cmpq $CHAO_MAX_DEPTH, chao_depth(%rip)
+    ja chao_fail_stack
+    ret
+
+chao_leave:
+    decq chao_depth(%rip)
+    ret
+
+# output, buffered until the buffer fills up or the program ends
+
+# chao_write_fd(fd, bytes, length)
+chao_write_fd:
+1:
+    testq %rdx, %rdx
+    jz 2f
+    movl $1, %eax
+    syscall
+    testq %rax, %rax
+    js 2f
+    addq %rax, %rsi
+    subq %rax, %rdx
+    jmp 1b
+2:
+    ret
+
+chao_flush:
+    subq $8, %rsp
+    movl $1, %edi
+    leaq chao_out(%rip), %rsi
+    movq chao_out_len(%rip), %rdx
+    call chao_write_fd
+    movq $0, chao_out_len(%rip)
+    addq $8, %rsp
+    ret
+
+# chao_write_bytes(bytes, length)
+chao_write_bytes:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    movq chao_out_len(%rip), %rax
+    addq %r12, %rax
+    cmpq $CHAO_OUT_SIZE, %rax
+    jbe 1f
+    call chao_flush
+    cmpq $CHAO_OUT_SIZE, %r12
+    jbe 1f
+    # too big for the buffer, so it goes straight out
+    movl $1, %edi
+    movq %rbx, %rsi
+    movq %r12, %rdx
+    call chao_write_fd
+    jmp 2f
+1:
+    leaq chao_out(%rip), %rdi
+    addq chao_out_len(%rip), %rdi
+    movq %rbx, %rsi
+    movq %r12, %rcx
+    rep movsb
+    addq %r12, chao_out_len(%rip)
+2:
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# chao_write_byte(byte)
+chao_write_byte:
+    subq $24, %rsp
+    movb %dil, (%rsp)
+    movq %rsp, %rdi
+    movl $1, %esi
+    call chao_write_bytes
+    addq $24, %rsp
+    ret
+
+# chao_strlen(str) -> length
+chao_strlen:
+    movq %rdi, %rax
+1:
+    cmpb $0, (%rax)
+    je 2f
+    incq %rax
+    jmp 1b
+2:
+    subq %rdi, %rax
+    ret
+
+# chao_write_str(str)
+chao_write_str:
+    pushq %rdi
+    call chao_strlen
+    popq %rdi
+    movq %rax, %rsi
+    jmp chao_write_bytes
+
+chao_write_nil:
+    leaq chao_str_nil(%rip), %rdi
+    movl $3, %esi
+    jmp chao_write_bytes
+
+# chao_write_bool(bool)
+chao_write_bool:
+    testl %edi, %edi
+    jz 1f
+    leaq chao_str_true(%rip), %rdi
+    movl $4, %esi
+    jmp chao_write_bytes
+1:
+    leaq chao_str_false(%rip), %rdi
+    movl $5, %esi
+    jmp chao_write_bytes
+
+# chao_write_int(int), digits are written backwards from the end of a buffer on the stack
+chao_write_int:
+    subq $40, %rsp
+    movslq %edi, %rax
+    movq %rax, %r8
+    testq %rax, %rax
+    jns 1f
+    negq %rax
+1:
+    leaq 32(%rsp), %rdi
+    movl $10, %ecx
+2:
+    xorl %edx, %edx
+    divq %rcx
+    addb $48, %dl
+    decq %rdi
+    movb %dl, (%rdi)
+    testq %rax, %rax
+    jnz 2b
+    testq %r8, %r8
+    jns 3f
+    decq %rdi
+    movb $45, (%rdi)
+3:
+    leaq 32(%rsp), %rsi
+    subq %rdi, %rsi
+    call chao_write_bytes
+    addq $40, %rsp
+    ret
+
+# chao_write_char(char), encoded as UTF-8
+chao_write_char:
+    subq $24, %rsp
+    movl %edi, %eax
+    cmpl $0x80, %eax
+    jae 1f
+    movb %al, (%rsp)
+    movl $1, %esi
+    jmp 4f
+1:
+    cmpl $0x800, %eax
+    jae 2f
+    movl %eax, %ecx
+    shrl $6, %ecx
+    orl $0xc0, %ecx
+    movb %cl, (%rsp)
+    andl $0x3f, %eax
+    orl $0x80, %eax
+    movb %al, 1(%rsp)
+    movl $2, %esi
+    jmp 4f
+2:
+    cmpl $0x10000, %eax
+    jae 3f
+    movl %eax, %ecx
+    shrl $12, %ecx
+    orl $0xe0, %ecx
+    movb %cl, (%rsp)
+    movl %eax, %ecx
+    shrl $6, %ecx
+    andl $0x3f, %ecx
+    orl $0x80, %ecx
+    movb %cl, 1(%rsp)
+    andl $0x3f, %eax
+    orl $0x80, %eax
+    movb %al, 2(%rsp)
+    movl $3, %esi
+    jmp 4f
+3:
+    movl %eax, %ecx
+    shrl $18, %ecx
+    orl $0xf0, %ecx
+    movb %cl, (%rsp)
+    movl %eax, %ecx
+    shrl $12, %ecx
+    andl $0x3f, %ecx
+    orl $0x80, %ecx
+    movb %cl, 1(%rsp)
+    movl %eax, %ecx
+    shrl $6, %ecx
+    andl $0x3f, %ecx
+    orl $0x80, %ecx
+    movb %cl, 2(%rsp)
+    andl $0x3f, %eax
+    orl $0x80, %eax
+    movb %al, 3(%rsp)
+    movl $4, %esi
+4:
+    movq %rsp, %rdi
+    call chao_write_bytes
+    addq $24, %rsp
+    ret
+
+# chao_write_float(bits), the fewest digits that read back as the same float, written out
+# without an exponent. For 1 to 9 significant digits the value is rounded in double precision
+# until the rounded value converts back to the float, the same digits the VM prints.
+#
+# rbx holds the float, r12 the decimal exponent of its first digit, r13 the digit count being
+# tried and r14 the digits themselves. The digit text is built at 0(%rsp).
+chao_write_float:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    subq $32, %rsp
+    movl %edi, %ebx
+    movl %ebx, %eax
+    andl $0x7fffffff, %eax
+    cmpl $0x7f800000, %eax
+    ja 90f
+    je 91f
+    testl $0x80000000, %ebx
+    jz 1f
+    movl $45, %edi
+    call chao_write_byte
+    andl $0x7fffffff, %ebx
+1:
+    testl %ebx, %ebx
+    jnz 2f
+    movl $48, %edi
+    call chao_write_byte
+    jmp 99f
+2:
+    # the exponent of the first digit is the largest power of ten that isn't bigger
+    movd %ebx, %xmm0
+    cvtss2sd %xmm0, %xmm0
+    movq $-46, %r12
+    leaq chao_pow10(%rip), %rcx
+3:
+    movsd 8*(46+1)(%rcx,%r12,8), %xmm1
+    ucomisd %xmm1, %xmm0
+    jb 4f
+    incq %r12
+    jmp 3b
+4:
+    movl $1, %r13d
+5:
+    # scale so the digits being tried are left of the point, r8 is the exponent of the last
+    movd %ebx, %xmm0
+    cvtss2sd %xmm0, %xmm0
+    movq %r12, %r8
+    subq %r13, %r8
+    incq %r8
+    leaq chao_pow10(%rip), %rcx
+    movq %r8, %rax
+    testq %rax, %rax
+    js 6f
+    divsd 8*46(%rcx,%rax,8), %xmm0
+    jmp 7f
+6:
+    negq %rax
+    mulsd 8*46(%rcx,%rax,8), %xmm0
+7:
+    # the nearest digits go first, ties rounding up, then the ones on the other side
+    cvttsd2si %xmm0, %r14
+    cvtsi2sd %r14, %xmm1
+    subsd %xmm1, %xmm0
+    movq $1, %r9
+    ucomisd chao_d_half(%rip), %xmm0
+    jb 70f
+    incq %r14
+    movq $-1, %r9
+70:
+    call chao_float_check
+    je 8f
+    # nine digits always read back
+    cmpl $9, %r13d
+    je 8f
+    addq %r9, %r14
+    call chao_float_check
+    je 8f
+    incl %r13d
+    jmp 5b
+8:
+    # write the digits out backwards, dropping zeros from the end
+    movq %r14, %rax
+    movl $10, %ecx
+    xorl %r15d, %r15d
+    leaq 24(%rsp), %rdi
+9:
+    xorl %edx, %edx
+    divq %rcx
+    addb $48, %dl
+    decq %rdi
+    movb %dl, (%rdi)
+    incl %r15d
+    testq %rax, %rax
+    jnz 9b
+    # rounding up can carry into an extra digit, so the exponent follows the digit count
+    addq %r15, %r12
+    subq %r13, %r12
+    movq %rdi, %r14
+10:
+    cmpl $1, %r15d
+    jbe 11f
+    cmpb $48, -1(%r14,%r15)
+    jne 11f
+    decl %r15d
+    jmp 10b
+11:
+    # r14 and r15 are now the digits and their count
+    testq %r12, %r12
+    js 20f
+    leaq -1(%r15), %rax
+    cmpq %rax, %r12
+    jl 30f
+    # a whole number, padded with zeros
+    movq %r14, %rdi
+    movq %r15, %rsi
+    call chao_write_bytes
+    subq %r15, %r12
+    incq %r12
+12:
+    testq %r12, %r12
+    jz 99f
+    movl $48, %edi
+    call chao_write_byte
+    decq %r12
+    jmp 12b
+20:
+    # smaller than one, zeros go between the point and the digits
+    movl $48, %edi
+    call chao_write_byte
+    movl $46, %edi
+    call chao_write_byte
+21:
+    incq %r12
+    jz 22f
+    movl $48, %edi
+    call chao_write_byte
+    jmp 21b
+22:
+    movq %r14, %rdi
+    movq %r15, %rsi
+    call chao_write_bytes
+    jmp 99f
+30:
+    # the point goes between the digits
+    movq %r14, %rdi
+    leaq 1(%r12), %rsi
+    call chao_write_bytes
+    movl $46, %edi
+    call chao_write_byte
+    leaq 1(%r14,%r12), %rdi
+    movq %r15, %rsi
+    subq %r12, %rsi
+    decq %rsi
+    call chao_write_bytes
+    jmp 99f
+90:
+    leaq chao_str_nan(%rip), %rdi
+    movl $3, %esi
+    call chao_write_bytes
+    jmp 99f
+91:
+    testl $0x80000000, %ebx
+    jz 92f
+    movl $45, %edi
+    call chao_write_byte
+92:
+    leaq chao_str_inf(%rip), %rdi
+    movl $3, %esi
+    call chao_write_bytes
+99:
+    addq $32, %rsp
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# Whether the digits in r14 times ten to the r8 read back as the float in ebx, in the zero
+# flag. Only for chao_write_float.
+chao_float_check:
+    cvtsi2sd %r14, %xmm1
+    leaq chao_pow10(%rip), %rcx
+    movq %r8, %rax
+    testq %rax, %rax
+    js 1f
+    mulsd 8*46(%rcx,%rax,8), %xmm1
+    jmp 2f
+1:
+    negq %rax
+    divsd 8*46(%rcx,%rax,8), %xmm1
+2:
+    cvtsd2ss %xmm1, %xmm1
+    movd %xmm1, %eax
+    cmpl %eax, %ebx
+    ret
+
+# chao_write_value(tag, value)
+chao_write_value:
+    cmpl $1, %edi
+    je 1f
+    cmpl $2, %edi
+    je 2f
+    cmpl $3, %edi
+    je 3f
+    cmpl $4, %edi
+    je 4f
+    cmpl $5, %edi
+    je 5f
+    jmp chao_write_nil
+1:
+    movl %esi, %edi
+    jmp chao_write_int
+2:
+    movl %esi, %edi
+    jmp chao_write_float
+3:
+    movl %esi, %edi
+    jmp chao_write_bool
+4:
+    movl %esi, %edi
+    jmp chao_write_char
+5:
+    movq %rsi, %rdi
+    jmp chao_write_str
+
+# chao_write_optional(tag, value), where the tag is the type inside the optional
+chao_write_optional:
+    cmpl $5, %edi
+    jne 1f
+    testq %rsi, %rsi
+    jz chao_write_nil
+    jmp chao_write_value
+1:
+    btq $32, %rsi
+    jnc chao_write_nil
+    jmp chao_write_value
+
+# chao_opt_eq(tag, a, b) -> bool, comparing two optionals holding the type of the tag
+chao_opt_eq:
+    cmpl $5, %edi
+    je 5f
+    btq $32, %rsi
+    setc %al
+    btq $32, %rdx
+    setc %cl
+    cmpb %al, %cl
+    jne 8f
+    testb %al, %al
+    jz 9f
+    cmpl $2, %edi
+    je 2f
+    cmpl %esi, %edx
+    sete %al
+    movzbl %al, %eax
+    ret
+2:
+    movd %esi, %xmm0
+    movd %edx, %xmm1
+    ucomiss %xmm1, %xmm0
+    sete %al
+    setnp %cl
+    andb %cl, %al
+    movzbl %al, %eax
+    ret
+5:
+    testq %rsi, %rsi
+    jz 6f
+    testq %rdx, %rdx
+    jz 8f
+    subq $8, %rsp
+    movq %rsi, %rdi
+    movq %rdx, %rsi
+    call chao_strcmp
+    addq $8, %rsp
+    testl %eax, %eax
+    sete %al
+    movzbl %al, %eax
+    ret
+6:
+    testq %rdx, %rdx
+    sete %al
+    movzbl %al, %eax
+    ret
+8:
+    xorl %eax, %eax
+    ret
+9:
+    movl $1, %eax
+    ret
+
+# strings, which are garbage collected
+#
+# Every string gets a mapping of its own, starting with a header of the next string and the
+# size of the mapping, with bit 0 marking it as reachable. Once enough bytes were mapped since
+# the last collection, the stack and the globals are scanned for words pointing at a string
+# and every string nothing points at is unmapped. The scan is conservative, anything that
+# looks like a pointer to a string keeps it alive. Single pages are kept around for reuse.
+
+# chao_alloc(size) -> pointer
+chao_alloc:
+    pushq %rbx
+    leaq 16+CHAO_PAGE-1(%rdi), %rbx
+    andq $-CHAO_PAGE, %rbx
+    movq chao_heap_size(%rip), %rax
+    addq %rbx, %rax
+    cmpq chao_heap_limit(%rip), %rax
+    jbe 1f
+    call chao_collect
+1:
+    cmpq $CHAO_PAGE, %rbx
+    jne 2f
+    movq chao_free_pages(%rip), %rax
+    testq %rax, %rax
+    jz 2f
+    movq (%rax), %rcx
+    movq %rcx, chao_free_pages(%rip)
+    jmp 3f
+2:
+    xorl %edi, %edi
+    movq %rbx, %rsi
+    movl $3, %edx
+    movl $0x22, %r10d
+    movq $-1, %r8
+    xorl %r9d, %r9d
+    movl $9, %eax
+    syscall
+    cmpq $-4096, %rax
+    ja chao_fail_memory
+3:
+    movq chao_objects(%rip), %rcx
+    movq %rcx, (%rax)
+    movq %rbx, 8(%rax)
+    movq %rax, chao_objects(%rip)
+    addq %rbx, chao_heap_size(%rip)
+    addq $16, %rax
+    cmpq chao_heap_low(%rip), %rax
+    jae 4f
+    movq %rax, chao_heap_low(%rip)
+4:
+    cmpq chao_heap_high(%rip), %rax
+    jbe 5f
+    movq %rax, chao_heap_high(%rip)
+5:
+    popq %rbx
+    ret
+
+# chao_collect, frees the strings nothing points at
+chao_collect:
+    # the callee-saved registers go on the stack, where the scan finds what they hold
+    pushq %rbx
+    pushq %rbp
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    movq %rsp, %rdi
+    movq chao_stack_base(%rip), %rsi
+    call chao_mark
+    leaq chao_globals(%rip), %rdi
+    leaq chao_globals_end(%rip), %rsi
+    call chao_mark
+
+    # %rbx points at the link to the next string, %r12 counts the bytes that are kept
+    leaq chao_objects(%rip), %rbx
+    xorl %r12d, %r12d
+1:
+    movq (%rbx), %rdi
+    testq %rdi, %rdi
+    jz 4f
+    movq 8(%rdi), %rsi
+    btrq $0, %rsi
+    jnc 2f
+    movq %rsi, 8(%rdi)
+    addq %rsi, %r12
+    movq %rdi, %rbx
+    jmp 1b
+2:
+    movq (%rdi), %rax
+    movq %rax, (%rbx)
+    cmpq $CHAO_PAGE, %rsi
+    jne 3f
+    movq chao_free_pages(%rip), %rax
+    movq %rax, (%rdi)
+    movq %rdi, chao_free_pages(%rip)
+    jmp 1b
+3:
+    movl $11, %eax
+    syscall
+    jmp 1b
+4:
+    # the next collection waits until the heap doubled
+    movq %r12, chao_heap_size(%rip)
+    addq %r12, %r12
+    movq $CHAO_HEAP_MIN, %rax
+    cmpq %rax, %r12
+    cmovbq %rax, %r12
+    movq %r12, chao_heap_limit(%rip)
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbp
+    popq %rbx
+    ret
+
+# chao_mark(from, to), marks every string a word in between points at
+chao_mark:
+1:
+    cmpq %rsi, %rdi
+    jae 4f
+    movq (%rdi), %rax
+    addq $8, %rdi
+    cmpq chao_heap_low(%rip), %rax
+    jb 1b
+    cmpq chao_heap_high(%rip), %rax
+    ja 1b
+    subq $16, %rax
+    movq chao_objects(%rip), %rcx
+2:
+    testq %rcx, %rcx
+    jz 1b
+    cmpq %rcx, %rax
+    je 3f
+    movq (%rcx), %rcx
+    jmp 2b
+3:
+    orq $1, 8(%rcx)
+    jmp 1b
+4:
+    ret
+
+# chao_concat(a, b) -> str
+chao_concat:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    call chao_strlen
+    movq %rax, %r13
+    movq %r12, %rdi
+    call chao_strlen
+    movq %rax, %r14
+    leaq 1(%r13,%r14), %rdi
+    call chao_alloc
+    movq %rax, %r15
+    movq %rax, %rdi
+    movq %rbx, %rsi
+    movq %r13, %rcx
+    rep movsb
+    movq %r12, %rsi
+    leaq 1(%r14), %rcx
+    rep movsb
+    movq %r15, %rax
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# chao_strcmp(a, b) -> -1, 0 or 1, comparing bytes as unsigned
+chao_strcmp:
+1:
+    movzbl (%rdi), %eax
+    movzbl (%rsi), %ecx
+    cmpl %ecx, %eax
+    jne 2f
+    testl %eax, %eax
+    jz 3f
+    incq %rdi
+    incq %rsi
+    jmp 1b
+2:
+    sbbl %eax, %eax
+    orl $1, %eax
+    ret
+3:
+    xorl %eax, %eax
+    ret
+
+# checked integer arithmetic that doesn't fit in a few instructions
+
+# chao_div(a, b) -> int
+chao_div:
+    testl %esi, %esi
+    jz chao_fail_div_zero
+    cmpl $-1, %esi
+    jne 1f
+    cmpl $-2147483648, %edi
+    je chao_fail_div
+1:
+    movl %edi, %eax
+    cltd
+    idivl %esi
+    ret
+
+# chao_rem(a, b) -> int
+chao_rem:
+    testl %esi, %esi
+    jz chao_fail_div_zero
+    cmpl $-1, %esi
+    jne 1f
+    cmpl $-2147483648, %edi
+    je chao_fail_rem
+1:
+    movl %edi, %eax
+    cltd
+    idivl %esi
+    movl %edx, %eax
+    ret
+
+# chao_pow(base, exp) -> int, by squaring so it fails on the same inputs as the VM
+chao_pow:
+    testl %esi, %esi
+    js chao_fail_exponent
+    movl $1, %eax
+    jz 3f
+1:
+    cmpl $1, %esi
+    jle 2f
+    testl $1, %esi
+    jz 4f
+    imull %edi, %eax
+    jo chao_fail_pow
+4:
+    shrl $1, %esi
+    imull %edi, %edi
+    jo chao_fail_pow
+    jmp 1b
+2:
+    imull %edi, %eax
+    jo chao_fail_pow
+3:
+    ret
+
+# floats
+
+# chao_fmodf(x, y) -> float, the remainder with the sign of x
+chao_fmodf:
+    subq $24, %rsp
+    movss %xmm1, (%rsp)
+    movss %xmm0, 4(%rsp)
+    flds (%rsp)
+    flds 4(%rsp)
+1:
+    fprem
+    fnstsw %ax
+    testw $0x400, %ax
+    jnz 1b
+    fstp %st(1)
+    fstps (%rsp)
+    movss (%rsp), %xmm0
+    addq $24, %rsp
+    ret
+
+# chao_powf(x, y) -> float, computed as 2 ** (y * log2 |x|) in extended precision. The sign
+# is negative when x is and y is an odd integer, and a negative x with a fractional y is NaN.
+chao_powf:
+    subq $24, %rsp
+    movss %xmm0, (%rsp)
+    movss %xmm1, 4(%rsp)
+    movd %xmm0, %eax
+    movd %xmm1, %ecx
+    # anything to the zero is one, and so is one to anything
+    testl $0x7fffffff, %ecx
+    jz 80f
+    cmpl $0x3f800000, %eax
+    je 80f
+    ucomiss %xmm1, %xmm0
+    jp 81f
+    movl %eax, %edx
+    andl $0x7fffffff, %edx
+    movl %ecx, %r8d
+    andl $0x7fffffff, %r8d
+    cmpl $0x7f800000, %r8d
+    jne 1f
+    # y is infinite, so it only matters whether |x| is above or below one
+    cmpl $0x3f800000, %edx
+    je 80f
+    seta %r9b
+    testl $0x80000000, %ecx
+    sete %r10b
+    cmpb %r9b, %r10b
+    je 83f
+    jmp 82f
+1:
+    # r9 is set when y is an odd integer, r10 when it's an integer at all
+    xorl %r9d, %r9d
+    movl $1, %r10d
+    cmpl $0x4b800000, %r8d
+    jae 2f
+    cvttss2si %xmm1, %r11d
+    cvtsi2ss %r11d, %xmm2
+    ucomiss %xmm1, %xmm2
+    setz %r10b
+    andl %r10d, %r11d
+    andl $1, %r11d
+    movl %r11d, %r9d
+2:
+    testl $0x80000000, %eax
+    jz 3f
+    cmpl $0x7f800000, %edx
+    jae 3f
+    testl %edx, %edx
+    jz 3f
+    testl %r10d, %r10d
+    jz 81f
+3:
+    # |x| ** y, where zero and infinity fall out of the logarithm
+    movl %edx, 8(%rsp)
+    flds 4(%rsp)
+    flds 8(%rsp)
+    fyl2x
+    fstl 16(%rsp)
+    movsd 16(%rsp), %xmm2
+    ucomisd chao_d_overflow(%rip), %xmm2
+    ja 84f
+    ucomisd chao_d_underflow(%rip), %xmm2
+    jb 85f
+    fld %st(0)
+    frndint
+    fxch %st(1)
+    fsub %st(1), %st
+    f2xm1
+    fld1
+    faddp
+    fscale
+    fstp %st(1)
+    fstps 12(%rsp)
+    movss 12(%rsp), %xmm0
+    jmp 86f
+84:
+    fstp %st(0)
+    movl $0x7f800000, %eax
+    movd %eax, %xmm0
+    jmp 86f
+85:
+    fstp %st(0)
+    xorps %xmm0, %xmm0
+86:
+    testl %r9d, %r9d
+    jz 87f
+    testl $0x80000000, (%rsp)
+    jz 87f
+    movd %xmm0, %eax
+    xorl $0x80000000, %eax
+    movd %eax, %xmm0
+87:
+    addq $24, %rsp
+    ret
+80:
+    movl $0x3f800000, %eax
+    movd %eax, %xmm0
+    addq $24, %rsp
+    ret
+81:
+    movl $0x7fc00000, %eax
+    movd %eax, %xmm0
+    addq $24, %rsp
+    ret
+82:
+    xorps %xmm0, %xmm0
+    addq $24, %rsp
+    ret
+83:
+    movl $0x7f800000, %eax
+    movd %eax, %xmm0
+    addq $24, %rsp
+    ret
+
+# runtime errors
+
+# chao_panic(message), prints what went wrong and stops the program
+chao_panic:
+    andq $-16, %rsp
+    movq %rdi, %rbx
+    call chao_flush
+    movl $2, %edi
+    leaq chao_str_error(%rip), %rsi
+    movl $15, %edx
+    call chao_write_fd
+    movq %rbx, %rdi
+    call chao_strlen
+    movq %rax, %rdx
+    movl $2, %edi
+    movq %rbx, %rsi
+    call chao_write_fd
+    movl $2, %edi
+    leaq chao_str_newline(%rip), %rsi
+    movl $1, %edx
+    call chao_write_fd
+    movl $60, %eax
+    movl $1, %edi
+    syscall
+
+chao_fail_add:
+    leaq chao_msg_add(%rip), %rdi
+    jmp chao_panic
+chao_fail_sub:
+    leaq chao_msg_sub(%rip), %rdi
+    jmp chao_panic
+chao_fail_mul:
+    leaq chao_msg_mul(%rip), %rdi
+    jmp chao_panic
+chao_fail_div:
+    leaq chao_msg_div(%rip), %rdi
+    jmp chao_panic
+chao_fail_rem:
+    leaq chao_msg_rem(%rip), %rdi
+    jmp chao_panic
+chao_fail_pow:
+    leaq chao_msg_pow(%rip), %rdi
+    jmp chao_panic
+chao_fail_neg:
+    leaq chao_msg_neg(%rip), %rdi
+    jmp chao_panic
+chao_fail_div_zero:
+    leaq chao_msg_div_zero(%rip), %rdi
+    jmp chao_panic
+chao_fail_exponent:
+    leaq chao_msg_exponent(%rip), %rdi
+    jmp chao_panic
+chao_fail_shift:
+    leaq chao_msg_shift(%rip), %rdi
+    jmp chao_panic
+chao_fail_stack:
+    leaq chao_msg_stack(%rip), %rdi
+    jmp chao_panic
+chao_fail_memory:
+    leaq chao_msg_memory(%rip), %rdi
+    jmp chao_panic
+chao_fail_operands:
+    leaq chao_msg_operands(%rip), %rdi
+    jmp chao_panic
+chao_fail_operand:
+    leaq chao_msg_operand(%rip), %rdi
+    jmp chao_panic
+chao_fail_return:
+    leaq chao_msg_return(%rip), %rdi
+    jmp chao_panic
+
+    .section .rodata
+    .balign 8
+chao_d_half:
+    .double 0.5
+chao_d_overflow:
+    .double 200.0
+chao_d_underflow:
+    .double -200.0
+chao_str_nil:
+    .ascii "nil"
+chao_str_true:
+    .ascii "true"
+chao_str_false:
+    .ascii "false"
+chao_str_nan:
+    .ascii "NaN"
+chao_str_inf:
+    .ascii "inf"
+chao_str_error:
+    .ascii "runtime error: "
+chao_str_newline:
+    .ascii "\n"
+chao_msg_add:
+    .asciz "integer overflow in addition"
+chao_msg_sub:
+    .asciz "integer overflow in subtraction"
+chao_msg_mul:
+    .asciz "integer overflow in multiplication"
+chao_msg_div:
+    .asciz "integer overflow in division"
+chao_msg_rem:
+    .asciz "integer overflow in remainder"
+chao_msg_pow:
+    .asciz "integer overflow in power"
+chao_msg_neg:
+    .asciz "integer overflow in negation"
+chao_msg_div_zero:
+    .asciz "division by zero"
+chao_msg_exponent:
+    .asciz "negative exponent in integer power"
+chao_msg_shift:
+    .asciz "shift amount out of range"
+chao_msg_stack:
+    .asciz "stack overflow"
+chao_msg_memory:
+    .asciz "out of memory"
+chao_msg_operands:
+    .asciz "invalid operand types for this operator"
+chao_msg_operand:
+    .asciz "invalid operand type for this operator"
+chao_msg_return:
+    .asciz "reached the end of a function without a return value"
+
+    .data
+    .balign 8
+chao_heap_low:
+    .quad -1
+chao_heap_limit:
+    .quad CHAO_HEAP_MIN
+
+    .bss
+    .balign 8
+chao_depth:
+    .skip 8
+chao_out_len:
+    .skip 8
+chao_stack_base:
+    .skip 8
+chao_objects:
+    .skip 8
+chao_free_pages:
+    .skip 8
+chao_heap_size:
+    .skip 8
+chao_heap_high:
+    .skip 8
+chao_out:
+    .skip CHAO_OUT_SIZE
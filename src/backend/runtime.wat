@@ -0,0 +1,96 @@
+  ;; Runtime for Chao programs compiled to WebAssembly, copied into every generated module.
+  ;;
+  ;; The host provides printing, the float operators wasm has no instruction for and a way
+  ;; to stop the program. `panic` gets the message of a runtime error as a pointer into the
+  ;; exported memory and a length in bytes, and must not return. `print` is made up of calls
+  ;; to the `write_` functions, followed by `write_char` with a newline.
+  (import "chao" "write_int" (func $chao_write_int (param i32)))
+  (import "chao" "write_float" (func $chao_write_float (param f32)))
+  (import "chao" "write_bool" (func $chao_write_bool (param i32)))
+  (import "chao" "write_char" (func $chao_write_char (param i32)))
+  (import "chao" "fmod" (func $chao_fmod (param f32 f32) (result f32)))
+  (import "chao" "pow" (func $chao_powf (param f32 f32) (result f32)))
+  (import "chao" "panic" (func $chao_panic (param i32 i32)))
+
+  (memory (export "memory") 1)
+
+  ;; calls in progress, the program stops once there are as many as on the VM
+  (global $chao_depth (mut i32) (i32.const 0))
+
+  (func $chao_enter
+    (global.set $chao_depth (i32.add (global.get $chao_depth) (i32.const 1)))
+    (if (i32.gt_s (global.get $chao_depth) (i32.const 1024))
+      (then (call $chao_fail_stack))))
+
+  (func $chao_leave
+    (global.set $chao_depth (i32.sub (global.get $chao_depth) (i32.const 1))))
+
+  ;; integer arithmetic, checked the same way as on the VM
+
+  (func $chao_add (param $a i32) (param $b i32) (result i32)
+    (local $r i64)
+    (local.set $r (i64.add (i64.extend_i32_s (local.get $a)) (i64.extend_i32_s (local.get $b))))
+    (if (call $chao_wide (local.get $r)) (then (call $chao_fail_add)))
+    (i32.wrap_i64 (local.get $r)))
+
+  (func $chao_sub (param $a i32) (param $b i32) (result i32)
+    (local $r i64)
+    (local.set $r (i64.sub (i64.extend_i32_s (local.get $a)) (i64.extend_i32_s (local.get $b))))
+    (if (call $chao_wide (local.get $r)) (then (call $chao_fail_sub)))
+    (i32.wrap_i64 (local.get $r)))
+
+  (func $chao_mul (param $a i32) (param $b i32) (result i32)
+    (local $r i64)
+    (local.set $r (i64.mul (i64.extend_i32_s (local.get $a)) (i64.extend_i32_s (local.get $b))))
+    (if (call $chao_wide (local.get $r)) (then (call $chao_fail_mul)))
+    (i32.wrap_i64 (local.get $r)))
+
+  ;; whether a result doesn't fit in an int
+  (func $chao_wide (param $r i64) (result i32)
+    (i64.ne (local.get $r) (i64.extend_i32_s (i32.wrap_i64 (local.get $r)))))
+
+  (func $chao_div (param $a i32) (param $b i32) (result i32)
+    (if (i32.eqz (local.get $b)) (then (call $chao_fail_div_zero)))
+    (if (i32.and (i32.eq (local.get $a) (i32.const -2147483648)) (i32.eq (local.get $b) (i32.const -1)))
+      (then (call $chao_fail_div)))
+    (i32.div_s (local.get $a) (local.get $b)))
+
+  (func $chao_rem (param $a i32) (param $b i32) (result i32)
+    (if (i32.eqz (local.get $b)) (then (call $chao_fail_div_zero)))
+    (if (i32.and (i32.eq (local.get $a) (i32.const -2147483648)) (i32.eq (local.get $b) (i32.const -1)))
+      (then (call $chao_fail_rem)))
+    (i32.rem_s (local.get $a) (local.get $b)))
+
+  ;; exponentiation by squaring, failing on the same inputs as the VM
+  (func $chao_pow (param $base i32) (param $exp i32) (result i32)
+    (local $acc i32)
+    (if (i32.lt_s (local.get $exp) (i32.const 0)) (then (call $chao_fail_exponent)))
+    (if (i32.eqz (local.get $exp)) (then (return (i32.const 1))))
+    (local.set $acc (i32.const 1))
+    (block $done
+      (loop $next
+        (br_if $done (i32.le_s (local.get $exp) (i32.const 1)))
+        (if (i32.and (local.get $exp) (i32.const 1))
+          (then (local.set $acc (call $chao_pow_mul (local.get $acc) (local.get $base)))))
+        (local.set $exp (i32.shr_u (local.get $exp) (i32.const 1)))
+        (local.set $base (call $chao_pow_mul (local.get $base) (local.get $base)))
+        (br $next)))
+    (call $chao_pow_mul (local.get $acc) (local.get $base)))
+
+  (func $chao_pow_mul (param $a i32) (param $b i32) (result i32)
+    (local $r i64)
+    (local.set $r (i64.mul (i64.extend_i32_s (local.get $a)) (i64.extend_i32_s (local.get $b))))
+    (if (call $chao_wide (local.get $r)) (then (call $chao_fail_pow)))
+    (i32.wrap_i64 (local.get $r)))
+
+  (func $chao_shl (param $a i32) (param $b i32) (result i32)
+    (if (i32.ge_u (local.get $b) (i32.const 32)) (then (call $chao_fail_shift)))
+    (i32.shl (local.get $a) (local.get $b)))
+
+  (func $chao_shr (param $a i32) (param $b i32) (result i32)
+    (if (i32.ge_u (local.get $b) (i32.const 32)) (then (call $chao_fail_shift)))
+    (i32.shr_s (local.get $a) (local.get $b)))
+
+  (func $chao_neg (param $a i32) (result i32)
+    (if (i32.eq (local.get $a) (i32.const -2147483648)) (then (call $chao_fail_neg)))
+    (i32.sub (i32.const 0) (local.get $a)))
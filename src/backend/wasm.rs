@@ -0,0 +1,445 @@
+use std::collections::{ HashMap, HashSet };
+use crate::{
+    analysis::{ cfg::Cfg, irgen::{ IrFunction, IrInst, IrProgram, IrType, IrValue } },
+    backend::{ declarations, mangle, named_dest, TypeMap },
+    common::token::TokenKind,
+};
+
+/// The imports and helpers every generated module starts with.
+const RUNTIME: &str = include_str!("runtime.wat");
+
+/// Runtime errors by the name of the function that raises them. The messages are laid out
+/// one after another at the start of memory.
+const FAILURES: [(&str, &str); 14] = [
+    ("add", "integer overflow in addition"),
+    ("sub", "integer overflow in subtraction"),
+    ("mul", "integer overflow in multiplication"),
+    ("div", "integer overflow in division"),
+    ("rem", "integer overflow in remainder"),
+    ("pow", "integer overflow in power"),
+    ("neg", "integer overflow in negation"),
+    ("div_zero", "division by zero"),
+    ("exponent", "negative exponent in integer power"),
+    ("shift", "shift amount out of range"),
+    ("stack", "stack overflow"),
+    ("operands", "invalid operand types for this operator"),
+    ("operand", "invalid operand type for this operator"),
+    ("return", "reached the end of a function without a return value"),
+];
+
+/// Lowers a program that is out of SSA form to a WebAssembly module in the text format. The
+/// top level statements are exported as `main` and the bindings they declare become globals.
+/// Only ints, floats, bools and chars have a wasm type so far, anything else is reported.
+///
+/// Wasm only has structured control flow, so the blocks of every function sit inside a loop
+/// that jumps to the block numbered by `$pc`. Jumps forward leave the enclosing blocks
+/// directly and only jumps backward go round the loop.
+pub(crate) fn generate(program: &IrProgram) -> Result<String, String> {
+    let mut generator = WasmGenerator::new(program);
+    return generator.program(program);
+}
+
+struct WasmGenerator {
+    types: TypeMap,
+    /// Parameter and return types of every function by IR name
+    signatures: HashMap<String, (Vec<IrType>, IrType)>,
+    /// Bindings of the function being generated that are wasm locals rather than globals
+    locals: HashSet<String>,
+    out: String,
+}
+
+impl WasmGenerator {
+    fn new(program: &IrProgram) -> WasmGenerator {
+        let mut signatures = HashMap::new();
+        for func in &program.functions {
+            let params = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+            signatures.insert(func.id.clone(), (params, func.ret.clone()));
+        }
+
+        return WasmGenerator {
+            types: TypeMap::new(program),
+            signatures,
+            locals: HashSet::new(),
+            out: String::new(),
+        };
+    }
+
+    fn program(&mut self, program: &IrProgram) -> Result<String, String> {
+        self.out.push_str("(module\n");
+        self.out.push_str(RUNTIME);
+        self.out.push('\n');
+
+        let mut offset = 0;
+        let mut messages = String::new();
+        for (name, msg) in FAILURES {
+            self.out.push_str(&format!(
+                "  (func $chao_fail_{} (call $chao_panic (i32.const {}) (i32.const {})) (unreachable))\n",
+                name,
+                offset,
+                msg.len()
+            ));
+            messages.push_str(msg);
+            offset += msg.len();
+        }
+        self.out.push_str(&format!("  (data (i32.const 0) \"{}\")\n\n", messages));
+
+        // bindings at the top level are globals
+        if let Some(script) = program.functions.first() {
+            let globals = declarations(script.body.iter().filter_map(named_dest));
+            for (id, ty) in &globals {
+                let ty = wasm_type(ty)?;
+                self.out.push_str(&format!("  (global ${} (mut {}) ({}.const 0))\n", mangle("v_", id), ty, ty));
+            }
+            if !globals.is_empty() {
+                self.out.push('\n');
+            }
+            self.function(script, true)?;
+        }
+        for func in program.functions.iter().skip(1) {
+            self.function(func, false)?;
+        }
+
+        self.out.push_str("  (export \"main\" (func $chao_script))\n)\n");
+        return Ok(std::mem::take(&mut self.out));
+    }
+
+    fn function(&mut self, func: &IrFunction, script: bool) -> Result<(), String> {
+        self.types.enter(func);
+        let cfg = Cfg::build(func);
+
+        let name = if script { "chao_script".to_string() } else { mangle("f_", &func.id) };
+        let mut header = format!("  (func ${}", name);
+        for (id, ty) in &func.params {
+            header.push_str(&format!(" (param ${} {})", mangle("v_", id), wasm_type(ty)?));
+        }
+        if func.ret != IrType::Void {
+            header.push_str(&format!(" (result {})", wasm_type(&func.ret)?));
+        }
+        self.out.push_str(&header);
+        self.out.push('\n');
+
+        self.locals = func.params.iter().map(|(id, _)| id.clone()).collect();
+        if !script {
+            for (id, ty) in declarations(func.body.iter().filter_map(named_dest)) {
+                if self.locals.insert(id.clone()) {
+                    self.out.push_str(&format!("    (local ${} {})\n", mangle("v_", &id), wasm_type(&ty)?));
+                }
+            }
+        }
+        let mut temps: Vec<(usize, IrType)> = self.types.temps().iter().map(|(t, ty)| (*t, ty.clone())).collect();
+        temps.sort_by_key(|(t, _)| *t);
+        for (t, ty) in temps {
+            self.out.push_str(&format!("    (local $t{} {})\n", t, wasm_type(&ty)?));
+        }
+        self.out.push_str("    (local $pc i32)\n");
+        if !script {
+            self.emit("call $chao_enter");
+        }
+
+        // the block each label starts
+        let blocks: HashMap<usize, usize> = cfg.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(b, block)| block.label.map(|l| (l, b)))
+            .collect();
+        let count = cfg.blocks.len();
+
+        self.emit("loop $dispatch");
+        for b in (0..count).rev() {
+            self.emit(format!("block $B{}", b));
+        }
+        self.emit("local.get $pc");
+        let table: Vec<String> = (0..count).map(|b| format!("$B{}", b)).collect();
+        self.emit(format!("br_table {}", table.join(" ")));
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            self.emit("end");
+            for inst in &block.body {
+                self.inst(inst, func, script, b, &blocks)?;
+            }
+        }
+        self.emit("end");
+
+        // falling off the end
+        if func.ret != IrType::Void {
+            self.emit("call $chao_fail_return");
+            self.emit("unreachable");
+        } else if !script {
+            self.emit("call $chao_leave");
+        }
+        self.out.push_str("  )\n\n");
+        return Ok(());
+    }
+
+    fn inst(
+        &mut self,
+        inst: &IrInst,
+        func: &IrFunction,
+        script: bool,
+        block: usize,
+        blocks: &HashMap<usize, usize>,
+    ) -> Result<(), String> {
+        match inst {
+            IrInst::Bind { id, ty: _, val } => {
+                self.push(val)?;
+                self.set(&IrValue::Identifier(id.clone()));
+            }
+            IrInst::Store { id: dest, val } | IrInst::Copy { dest, ty: _, val } => {
+                self.push(val)?;
+                self.set(dest);
+            }
+            IrInst::BinOp { dest, ty: _, lhs, op, rhs } => {
+                self.binary(lhs, *op, rhs)?;
+                self.set(dest);
+            }
+            IrInst::UnOp { dest, ty: _, op, val } => {
+                self.unary(*op, val)?;
+                self.set(dest);
+            }
+            IrInst::Jump { label } => self.jump(block, blocks, *label),
+            IrInst::Branch { cond, then, otherwise } => {
+                self.push(cond)?;
+                self.emit("if");
+                self.jump(block, blocks, *then);
+                self.emit("else");
+                self.jump(block, blocks, *otherwise);
+                self.emit("end");
+            }
+            IrInst::Call { dest, ty: _, func: callee, args } => self.call(callee, args, dest)?,
+            IrInst::Return { val: _ } if script => self.emit("return"),
+            IrInst::Return { val: Some(val) } if func.ret != IrType::Void => {
+                self.push(val)?;
+                self.emit("call $chao_leave");
+                self.emit("return");
+            }
+            // the resolver makes sure functions returning a value never get here
+            IrInst::Return { val: None } if func.ret != IrType::Void => {
+                self.emit("call $chao_fail_return");
+                self.emit("unreachable");
+            }
+            IrInst::Return { val: _ } => {
+                self.emit("call $chao_leave");
+                self.emit("return");
+            }
+            // labels start blocks, and phis are gone once the program is out of SSA form
+            IrInst::Label { id: _ } | IrInst::Phi { dest: _, ty: _, args: _ } => {}
+        }
+        return Ok(());
+    }
+
+    /// Leaves the current block for the one `label` starts. Blocks further down are reached by
+    /// leaving the wasm blocks around them, anything else goes back through the dispatch loop.
+    fn jump(&mut self, from: usize, blocks: &HashMap<usize, usize>, label: usize) {
+        let Some(to) = blocks.get(&label).copied() else {
+            self.emit("unreachable");
+            return;
+        };
+        if to > from {
+            self.emit(format!("br $B{}", to));
+        } else {
+            self.emit(format!("i32.const {}", to));
+            self.emit("local.set $pc");
+            self.emit("br $dispatch");
+        }
+    }
+
+    fn call(&mut self, callee: &String, args: &[IrValue], dest: &Option<IrValue>) -> Result<(), String> {
+        if callee == "print" {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    self.emit("i32.const 32");
+                    self.emit("call $chao_write_char");
+                }
+                let writer = match self.types.of(arg) {
+                    IrType::Int => "int",
+                    IrType::Float => "float",
+                    IrType::Bool => "bool",
+                    IrType::Char => "char",
+                    ty => return Err(unsupported(&ty)),
+                };
+                self.push(arg)?;
+                self.emit(format!("call $chao_write_{}", writer));
+            }
+            self.emit("i32.const 10");
+            self.emit("call $chao_write_char");
+            return Ok(());
+        }
+
+        for arg in args {
+            self.push(arg)?;
+        }
+        self.emit(format!("call ${}", mangle("f_", callee)));
+        let returns = self.signatures.get(callee).is_some_and(|(_, ret)| *ret != IrType::Void);
+        match dest {
+            Some(dest) => self.set(dest),
+            None if returns => self.emit("drop"),
+            None => {}
+        }
+        return Ok(());
+    }
+
+    /// Pushes the result of a binary operation.
+    fn binary(&mut self, lhs: &IrValue, op: TokenKind, rhs: &IrValue) -> Result<(), String> {
+        let (lt, rt) = (self.types.of(lhs), self.types.of(rhs));
+        for ty in [&lt, &rt] {
+            wasm_type(ty)?;
+        }
+
+        let line = match (&lt, op, &rt) {
+            // values of different types are never equal
+            _ if lt != rt && matches!(op, TokenKind::EqualEqual | TokenKind::BangEqual) => {
+                let equal = if op == TokenKind::BangEqual { 1 } else { 0 };
+                self.emit(format!("i32.const {}", equal));
+                return Ok(());
+            }
+            (IrType::Int | IrType::Bool | IrType::Char, TokenKind::EqualEqual, _) => "i32.eq",
+            (IrType::Int | IrType::Bool | IrType::Char, TokenKind::BangEqual, _) => "i32.ne",
+            (IrType::Int | IrType::Char, _, _) if is_comparison(op) && lt == rt => {
+                // chars are unsigned
+                match (op, lt == IrType::Int) {
+                    (TokenKind::Less, true) => "i32.lt_s",
+                    (TokenKind::Less, false) => "i32.lt_u",
+                    (TokenKind::LessEqual, true) => "i32.le_s",
+                    (TokenKind::LessEqual, false) => "i32.le_u",
+                    (TokenKind::Greater, true) => "i32.gt_s",
+                    (TokenKind::Greater, false) => "i32.gt_u",
+                    (_, true) => "i32.ge_s",
+                    (_, false) => "i32.ge_u",
+                }
+            }
+            (IrType::Int, _, IrType::Int) => {
+                match op {
+                    TokenKind::Plus => "call $chao_add",
+                    TokenKind::Minus => "call $chao_sub",
+                    TokenKind::Star => "call $chao_mul",
+                    TokenKind::Slash => "call $chao_div",
+                    TokenKind::Percent => "call $chao_rem",
+                    TokenKind::StarStar => "call $chao_pow",
+                    TokenKind::LessLess => "call $chao_shl",
+                    TokenKind::GreaterGreater => "call $chao_shr",
+                    TokenKind::Amp => "i32.and",
+                    TokenKind::Pipe => "i32.or",
+                    TokenKind::Caret => "i32.xor",
+                    _ => {
+                        self.fail("operands");
+                        return Ok(());
+                    }
+                }
+            }
+            (IrType::Float, _, IrType::Float) => {
+                match op {
+                    TokenKind::Plus => "f32.add",
+                    TokenKind::Minus => "f32.sub",
+                    TokenKind::Star => "f32.mul",
+                    TokenKind::Slash => "f32.div",
+                    TokenKind::Percent => "call $chao_fmod",
+                    TokenKind::StarStar => "call $chao_powf",
+                    TokenKind::EqualEqual => "f32.eq",
+                    TokenKind::BangEqual => "f32.ne",
+                    TokenKind::Less => "f32.lt",
+                    TokenKind::LessEqual => "f32.le",
+                    TokenKind::Greater => "f32.gt",
+                    TokenKind::GreaterEqual => "f32.ge",
+                    _ => {
+                        self.fail("operands");
+                        return Ok(());
+                    }
+                }
+            }
+            _ => {
+                        self.fail("operands");
+                        return Ok(());
+                    }
+        };
+
+        self.push(lhs)?;
+        self.push(rhs)?;
+        self.emit(line);
+        return Ok(());
+    }
+
+    /// Pushes the result of a unary operation.
+    fn unary(&mut self, op: TokenKind, val: &IrValue) -> Result<(), String> {
+        let ty = self.types.of(val);
+        wasm_type(&ty)?;
+        let lines: &[&str] = match (op, ty) {
+            (TokenKind::Minus, IrType::Int) => &["call $chao_neg"],
+            (TokenKind::Minus, IrType::Float) => &["f32.neg"],
+            (TokenKind::Tilde, IrType::Int) => &["i32.const -1", "i32.xor"],
+            (TokenKind::Bang, IrType::Bool) => &["i32.eqz"],
+            _ => {
+                self.fail("operand");
+                return Ok(());
+            }
+        };
+        self.push(val)?;
+        for line in lines {
+            self.emit(*line);
+        }
+        return Ok(());
+    }
+
+    /// Stops the program with a runtime error where a value was expected.
+    fn fail(&mut self, name: &str) {
+        self.emit(format!("call $chao_fail_{}", name));
+        self.emit("unreachable");
+    }
+
+    fn push(&mut self, val: &IrValue) -> Result<(), String> {
+        let line = match val {
+            IrValue::Temp(t) => format!("local.get $t{}", t),
+            IrValue::Identifier(id) if self.locals.contains(id) => format!("local.get ${}", mangle("v_", id)),
+            IrValue::Identifier(id) => format!("global.get ${}", mangle("v_", id)),
+            IrValue::ConstInt(n) => format!("i32.const {}", n),
+            IrValue::ConstFloat(n) if n.is_nan() => "f32.const nan".to_string(),
+            IrValue::ConstFloat(n) if n.is_infinite() => {
+                if *n < 0.0 { "f32.const -inf".to_string() } else { "f32.const inf".to_string() }
+            }
+            IrValue::ConstFloat(n) => format!("f32.const {:?}", n),
+            IrValue::ConstChar(c) => format!("i32.const {}", *c as u32),
+            IrValue::ConstBool(b) => format!("i32.const {}", *b as u32),
+            IrValue::ConstStr(_) | IrValue::Nil => return Err(unsupported(&self.types.of(val))),
+        };
+        self.emit(line);
+        return Ok(());
+    }
+
+    fn set(&mut self, dest: &IrValue) {
+        match dest {
+            IrValue::Temp(t) => self.emit(format!("local.set $t{}", t)),
+            IrValue::Identifier(id) if self.locals.contains(id) => {
+                self.emit(format!("local.set ${}", mangle("v_", id)));
+            }
+            IrValue::Identifier(id) => self.emit(format!("global.set ${}", mangle("v_", id))),
+            _ => self.emit("drop"),
+        }
+    }
+
+    fn emit(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+}
+
+/// The wasm type values of `ty` are kept in.
+fn wasm_type(ty: &IrType) -> Result<&'static str, String> {
+    return match ty {
+        IrType::Int | IrType::Bool | IrType::Char => Ok("i32"),
+        IrType::Float => Ok("f32"),
+        _ => Err(unsupported(ty)),
+    };
+}
+
+fn unsupported(ty: &IrType) -> String {
+    let what = match ty {
+        IrType::Str => "strings",
+        IrType::Optional(_) => "optionals",
+        IrType::Nil => "nil",
+        _ => "values without a type",
+    };
+    return format!("{} are not supported by the wasm target yet", what);
+}
+
+fn is_comparison(op: TokenKind) -> bool {
+    return matches!(op, TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual);
+}
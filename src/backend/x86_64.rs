@@ -0,0 +1,633 @@
+use std::collections::{ hash_map::Entry, HashMap };
+use crate::{
+    analysis::irgen::{ IrFunction, IrInst, IrProgram, IrType, IrValue },
+    backend::{ declarations, mangle, named_dest, regalloc::{ self, Location }, TypeMap },
+    common::token::TokenKind,
+};
+
+/// The runtime every generated file ends with, so the output assembles and links on its own.
+const RUNTIME: &str = include_str!("runtime.s");
+
+/// Registers handed out to temporaries. They're all callee-saved, so their values survive
+/// calls and a function only has to save the ones it uses.
+const REGISTERS: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+/// Registers for the first arguments that aren't floats, in order
+const INT_ARGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+/// How many float arguments go in `%xmm0` and up
+const FLOAT_ARGS: usize = 8;
+
+/// Lowers a program that is out of SSA form to GNU assembler x86-64 for Linux, calling
+/// functions the System V way. The top level statements become `chao_script`, which the
+/// runtime's `_start` calls, and every binding they declare becomes a global.
+///
+/// Every value takes up a 64 bit word. Ints, floats and chars are kept in the low half with
+/// the high half zero, bools are 0 or 1 and strings are pointers. An optional has bit 32 set
+/// when it holds a value, except for optional strings, which are nil when the pointer is null.
+/// Nil is zero either way.
+pub(crate) fn generate(program: &IrProgram) -> String {
+    let mut generator = AsmGenerator::new(program);
+    return generator.program(program);
+}
+
+struct AsmGenerator {
+    types: TypeMap,
+    /// Parameter and return types of every function by IR name
+    signatures: HashMap<String, (Vec<IrType>, IrType)>,
+    /// Contents of the string literals, `.Lstr{n}` is the nth one
+    strings: Vec<String>,
+    /// Index of the function being generated, which keeps its labels apart from the others
+    function: usize,
+    /// Where the temporaries and local bindings of the function being generated live
+    temps: HashMap<usize, String>,
+    locals: HashMap<String, String>,
+    out: String,
+}
+
+impl AsmGenerator {
+    fn new(program: &IrProgram) -> AsmGenerator {
+        let mut signatures = HashMap::new();
+        for func in &program.functions {
+            let params = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+            signatures.insert(func.id.clone(), (params, func.ret.clone()));
+        }
+
+        return AsmGenerator {
+            types: TypeMap::new(program),
+            signatures,
+            strings: vec![],
+            function: 0,
+            temps: HashMap::new(),
+            locals: HashMap::new(),
+            out: String::new(),
+        };
+    }
+
+    fn program(&mut self, program: &IrProgram) -> String {
+        self.out.push_str("    .text\n\n");
+        match program.functions.first() {
+            Some(script) => self.function(script, 0, true),
+            None => self.out.push_str("chao_script:\n    ret\n\n"),
+        }
+        for (i, func) in program.functions.iter().enumerate().skip(1) {
+            self.function(func, i, false);
+        }
+
+        // powers of ten for printing floats, from 1e-46 up to 1e54
+        self.out.push_str("    .section .rodata\n    .balign 8\nchao_pow10:\n");
+        for n in -46..=54 {
+            let bits = format!("1e{}", n).parse::<f64>().map(f64::to_bits).unwrap_or_default();
+            self.out.push_str(&format!("    .quad 0x{:016x}\n", bits));
+        }
+        for (n, s) in self.strings.iter().enumerate() {
+            self.out.push_str(&format!(".Lstr{}:\n    .asciz {}\n", n, string_literal(s)));
+        }
+
+        // bindings at the top level are globals
+        let globals = match program.functions.first() {
+            Some(script) => declarations(script.body.iter().filter_map(named_dest)),
+            None => vec![],
+        };
+        // the runtime scans them for strings that are still in use
+        self.out.push_str("\n    .bss\n    .balign 8\nchao_globals:\n");
+        for (id, _) in &globals {
+            self.out.push_str(&format!("{}:\n    .skip 8\n", mangle("v_", id)));
+        }
+        self.out.push_str("chao_globals_end:\n");
+
+        self.out.push('\n');
+        self.out.push_str(RUNTIME);
+        return std::mem::take(&mut self.out);
+    }
+
+    fn function(&mut self, func: &IrFunction, index: usize, script: bool) {
+        self.types.enter(func);
+        self.function = index;
+
+        let allocation = regalloc::allocate(func, REGISTERS.len());
+        let mut saved: Vec<usize> = allocation.locations
+            .values()
+            .filter_map(|loc| match loc {
+                Location::Register(r) => Some(*r),
+                Location::Spill(_) => None,
+            })
+            .collect();
+        saved.sort();
+        saved.dedup();
+
+        // stack slots sit below the saved registers, locals first and then spilled temporaries
+        let slot = |i: usize| format!("-{}(%rbp)", 8 * saved.len() + 8 * (i + 1));
+        let mut slots = 0;
+        let mut arrivals: Vec<(String, String)> = vec![];
+        self.locals.clear();
+        if !script {
+            let (mut ints, mut floats, mut stack) = (0, 0, 0);
+            for (id, ty) in &func.params {
+                let incoming = if *ty == IrType::Float && floats < FLOAT_ARGS {
+                    floats += 1;
+                    format!("%xmm{}", floats - 1)
+                } else if *ty != IrType::Float && ints < INT_ARGS.len() {
+                    ints += 1;
+                    INT_ARGS[ints - 1].to_string()
+                } else {
+                    // arguments past the registers are already on the stack above the return address
+                    stack += 1;
+                    self.locals.insert(id.clone(), format!("{}(%rbp)", 8 + 8 * stack));
+                    continue;
+                };
+                self.locals.insert(id.clone(), slot(slots));
+                arrivals.push((incoming, slot(slots)));
+                slots += 1;
+            }
+            for (id, _) in declarations(func.body.iter().filter_map(named_dest)) {
+                if let Entry::Vacant(entry) = self.locals.entry(id) {
+                    entry.insert(slot(slots));
+                    slots += 1;
+                }
+            }
+        }
+        self.temps = allocation.locations
+            .iter()
+            .map(|(t, loc)| {
+                let place = match loc {
+                    Location::Register(r) => REGISTERS[*r].to_string(),
+                    Location::Spill(s) => slot(slots + s),
+                };
+                (*t, place)
+            })
+            .collect();
+        slots += allocation.spills;
+        // keeps the stack 16 byte aligned at calls, the return address and `%rbp` make 16
+        let frame = 8 * (slots + (saved.len() + slots) % 2);
+
+        let name = if script { "chao_script".to_string() } else { mangle("f_", &func.id) };
+        self.out.push_str(&format!("{}:\n", name));
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        for r in &saved {
+            self.emit(format!("pushq {}", REGISTERS[*r]));
+        }
+        if frame > 0 {
+            self.emit(format!("subq ${}, %rsp", frame));
+        }
+        if !script {
+            self.emit("call chao_enter");
+        }
+        for (incoming, place) in arrivals {
+            if incoming.starts_with("%xmm") {
+                self.emit(format!("movd {}, %eax", incoming));
+                self.emit(format!("movq %rax, {}", place));
+            } else {
+                self.emit(format!("movq {}, {}", incoming, place));
+            }
+        }
+
+        for inst in &func.body {
+            self.inst(inst, func, script);
+        }
+
+        self.out.push_str(&format!(".L{}_ret:\n", index));
+        if !script {
+            self.emit("call chao_leave");
+        }
+        self.emit(format!("leaq -{}(%rbp), %rsp", 8 * saved.len()));
+        for r in saved.iter().rev() {
+            self.emit(format!("popq {}", REGISTERS[*r]));
+        }
+        self.emit("popq %rbp");
+        self.emit("ret");
+        self.out.push('\n');
+    }
+
+    fn inst(&mut self, inst: &IrInst, func: &IrFunction, script: bool) {
+        match inst {
+            IrInst::Bind { id, ty, val } => {
+                self.load_as(val, ty, "%rax");
+                self.store("%rax", &IrValue::Identifier(id.clone()));
+            }
+            IrInst::Store { id, val } => {
+                self.load_as(val, &self.types.of(id), "%rax");
+                self.store("%rax", id);
+            }
+            IrInst::Copy { dest, ty, val } => {
+                self.load_as(val, ty, "%rax");
+                self.store("%rax", dest);
+            }
+            IrInst::BinOp { dest, ty: _, lhs, op, rhs } => {
+                if self.binary(lhs, *op, rhs) {
+                    self.store("%rax", dest);
+                } else {
+                    self.emit("jmp chao_fail_operands");
+                }
+            }
+            IrInst::UnOp { dest, ty: _, op, val } => {
+                if self.unary(*op, val) {
+                    self.store("%rax", dest);
+                } else {
+                    self.emit("jmp chao_fail_operand");
+                }
+            }
+            IrInst::Label { id } => self.out.push_str(&format!(".L{}_{}:\n", self.function, id)),
+            IrInst::Jump { label } => self.emit(format!("jmp .L{}_{}", self.function, label)),
+            IrInst::Branch { cond, then, otherwise } => {
+                self.load(cond, "%rax");
+                self.emit("testl %eax, %eax");
+                self.emit(format!("jnz .L{}_{}", self.function, then));
+                self.emit(format!("jmp .L{}_{}", self.function, otherwise));
+            }
+            IrInst::Call { dest, ty: _, func: callee, args } => self.call(callee, args, dest),
+            IrInst::Return { val: _ } if script => self.emit(format!("jmp .L{}_ret", self.function)),
+            IrInst::Return { val: Some(val) } if func.ret != IrType::Void => {
+                self.load_as(val, &func.ret, "%rax");
+                if func.ret == IrType::Float {
+                    self.emit("movd %eax, %xmm0");
+                }
+                self.emit(format!("jmp .L{}_ret", self.function));
+            }
+            // the resolver makes sure functions returning a value never get here
+            IrInst::Return { val: None } if func.ret != IrType::Void => self.emit("jmp chao_fail_return"),
+            IrInst::Return { val: _ } => self.emit(format!("jmp .L{}_ret", self.function)),
+            // phis are gone once the program is out of SSA form
+            IrInst::Phi { dest: _, ty: _, args: _ } => {}
+        }
+    }
+
+    fn call(&mut self, callee: &String, args: &[IrValue], dest: &Option<IrValue>) {
+        if callee == "print" {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    self.emit("movl $32, %edi");
+                    self.emit("call chao_write_byte");
+                }
+                self.load(arg, "%rsi");
+                match self.types.of(arg) {
+                    IrType::Optional(inner) => {
+                        self.emit(format!("movl ${}, %edi", tag(&inner)));
+                        self.emit("call chao_write_optional");
+                    }
+                    ty => {
+                        self.emit(format!("movl ${}, %edi", tag(&ty)));
+                        self.emit("call chao_write_value");
+                    }
+                }
+            }
+            self.emit("movl $10, %edi");
+            self.emit("call chao_write_byte");
+            return;
+        }
+
+        let (params, ret) = self.signatures.get(callee).cloned().unwrap_or((vec![], IrType::Void));
+        let mut registers: Vec<(&IrValue, IrType, String)> = vec![];
+        let mut stack: Vec<(&IrValue, IrType)> = vec![];
+        let (mut ints, mut floats) = (0, 0);
+        for (i, arg) in args.iter().enumerate() {
+            let ty = params.get(i).cloned().unwrap_or_else(|| self.types.of(arg));
+            if ty == IrType::Float && floats < FLOAT_ARGS {
+                registers.push((arg, ty, format!("%xmm{}", floats)));
+                floats += 1;
+            } else if ty != IrType::Float && ints < INT_ARGS.len() {
+                registers.push((arg, ty, INT_ARGS[ints].to_string()));
+                ints += 1;
+            } else {
+                stack.push((arg, ty));
+            }
+        }
+
+        // the rest go on the stack last to first, padded so the call stays aligned
+        let pushed = stack.len() + stack.len() % 2;
+        if stack.len() % 2 == 1 {
+            self.emit("subq $8, %rsp");
+        }
+        for (arg, ty) in stack.iter().rev() {
+            self.load_as(arg, ty, "%rax");
+            self.emit("pushq %rax");
+        }
+        // loading a value only touches the register it goes into, so earlier arguments stay put
+        for (arg, ty, reg) in &registers {
+            if reg.starts_with("%xmm") {
+                self.load_as(arg, ty, "%rax");
+                self.emit(format!("movd %eax, {}", reg));
+            } else {
+                self.load_as(arg, ty, reg);
+            }
+        }
+        self.emit(format!("call {}", mangle("f_", callee)));
+        if pushed > 0 {
+            self.emit(format!("addq ${}, %rsp", 8 * pushed));
+        }
+
+        if let Some(dest) = dest {
+            if ret == IrType::Float {
+                self.emit("movd %xmm0, %eax");
+            }
+            self.store("%rax", dest);
+        }
+    }
+
+    /// Computes a binary operation into `%rax`. Returns false when the operands can't be used
+    /// with the operator.
+    fn binary(&mut self, lhs: &IrValue, op: TokenKind, rhs: &IrValue) -> bool {
+        let (lt, rt) = (self.types.of(lhs), self.types.of(rhs));
+        if matches!(op, TokenKind::EqualEqual | TokenKind::BangEqual) {
+            self.equality(lhs, &lt, op, rhs, &rt);
+            return true;
+        }
+
+        let lines: Vec<&str> = match (&lt, op, &rt) {
+            (IrType::Str, TokenKind::Plus, IrType::Str) => {
+                self.load(lhs, "%rdi");
+                self.load(rhs, "%rsi");
+                self.emit("call chao_concat");
+                return true;
+            }
+            (IrType::Str, _, IrType::Str) if is_comparison(op) => {
+                self.load(lhs, "%rdi");
+                self.load(rhs, "%rsi");
+                self.emit("call chao_strcmp");
+                self.emit("cmpl $0, %eax");
+                self.set(condition(op, true));
+                return true;
+            }
+            (IrType::Int | IrType::Char, _, _) if is_comparison(op) && lt == rt => {
+                self.load(lhs, "%rax");
+                self.load(rhs, "%rcx");
+                self.emit("cmpl %ecx, %eax");
+                // chars are unsigned
+                self.set(condition(op, lt == IrType::Int));
+                return true;
+            }
+            (IrType::Float, _, _) if is_comparison(op) && lt == rt => {
+                self.float_compare(lhs, op, rhs);
+                return true;
+            }
+            (IrType::Int, _, IrType::Int) => {
+                match op {
+                    TokenKind::Plus => vec!["addl %ecx, %eax", "jo chao_fail_add"],
+                    TokenKind::Minus => vec!["subl %ecx, %eax", "jo chao_fail_sub"],
+                    TokenKind::Star => vec!["imull %ecx, %eax", "jo chao_fail_mul"],
+                    TokenKind::Slash => vec!["movl %eax, %edi", "movl %ecx, %esi", "call chao_div"],
+                    TokenKind::Percent => vec!["movl %eax, %edi", "movl %ecx, %esi", "call chao_rem"],
+                    TokenKind::StarStar => vec!["movl %eax, %edi", "movl %ecx, %esi", "call chao_pow"],
+                    TokenKind::LessLess => vec!["cmpl $32, %ecx", "jae chao_fail_shift", "shll %cl, %eax"],
+                    TokenKind::GreaterGreater => vec!["cmpl $32, %ecx", "jae chao_fail_shift", "sarl %cl, %eax"],
+                    TokenKind::Amp => vec!["andl %ecx, %eax"],
+                    TokenKind::Pipe => vec!["orl %ecx, %eax"],
+                    TokenKind::Caret => vec!["xorl %ecx, %eax"],
+                    _ => return false,
+                }
+            }
+            (IrType::Float, _, IrType::Float) => {
+                let line = match op {
+                    TokenKind::Plus => "addss %xmm1, %xmm0",
+                    TokenKind::Minus => "subss %xmm1, %xmm0",
+                    TokenKind::Star => "mulss %xmm1, %xmm0",
+                    TokenKind::Slash => "divss %xmm1, %xmm0",
+                    TokenKind::Percent => "call chao_fmodf",
+                    TokenKind::StarStar => "call chao_powf",
+                    _ => return false,
+                };
+                vec!["movd %eax, %xmm0", "movd %ecx, %xmm1", line, "movd %xmm0, %eax"]
+            }
+            _ => return false,
+        };
+
+        self.load(lhs, "%rax");
+        self.load(rhs, "%rcx");
+        for line in lines {
+            self.emit(line);
+        }
+        return true;
+    }
+
+    /// `==` and `!=`, where values of different types are never equal.
+    fn equality(&mut self, lhs: &IrValue, lt: &IrType, op: TokenKind, rhs: &IrValue, rt: &IrType) {
+        let negate = op == TokenKind::BangEqual;
+        match (lt, rt) {
+            (IrType::Int | IrType::Bool | IrType::Char, _) if lt == rt => {
+                self.load(lhs, "%rax");
+                self.load(rhs, "%rcx");
+                self.emit("cmpl %ecx, %eax");
+                self.set(condition(op, true));
+            }
+            (IrType::Float, IrType::Float) => self.float_compare(lhs, op, rhs),
+            (IrType::Str, IrType::Str) => {
+                self.load(lhs, "%rdi");
+                self.load(rhs, "%rsi");
+                self.emit("call chao_strcmp");
+                self.emit("cmpl $0, %eax");
+                self.set(condition(op, true));
+            }
+            _ if !is_nullable(lt) && !is_nullable(rt) => {
+                self.emit(format!("movl ${}, %eax", negate as u32));
+            }
+            // at least one side is an optional or nil, so both are compared as optionals
+            _ => {
+                let (li, ri) = (inner(lt), inner(rt));
+                self.load_as(lhs, &IrType::Optional(Box::new(li.clone())), "%rax");
+                self.load_as(rhs, &IrType::Optional(Box::new(ri.clone())), "%rcx");
+                if li == ri && matches!(li, IrType::Float | IrType::Str) {
+                    self.emit(format!("movl ${}, %edi", tag(&li)));
+                    self.emit("movq %rax, %rsi");
+                    self.emit("movq %rcx, %rdx");
+                    self.emit("call chao_opt_eq");
+                    if negate {
+                        self.emit("xorl $1, %eax");
+                    }
+                } else if li == ri && li != IrType::Nil {
+                    self.emit("cmpq %rcx, %rax");
+                    self.set(condition(op, true));
+                } else {
+                    // optionals of different types are only equal when both are nil
+                    self.emit("orq %rcx, %rax");
+                    self.set(condition(op, true));
+                }
+            }
+        }
+    }
+
+    /// Compares two floats into `%rax`, anything compared with NaN is false except `!=`.
+    fn float_compare(&mut self, lhs: &IrValue, op: TokenKind, rhs: &IrValue) {
+        self.load(lhs, "%rax");
+        self.load(rhs, "%rcx");
+        self.emit("movd %eax, %xmm0");
+        self.emit("movd %ecx, %xmm1");
+        let lines: &[&str] = match op {
+            TokenKind::Less => &["ucomiss %xmm0, %xmm1", "seta %al"],
+            TokenKind::LessEqual => &["ucomiss %xmm0, %xmm1", "setae %al"],
+            TokenKind::Greater => &["ucomiss %xmm1, %xmm0", "seta %al"],
+            TokenKind::GreaterEqual => &["ucomiss %xmm1, %xmm0", "setae %al"],
+            TokenKind::BangEqual => &["ucomiss %xmm1, %xmm0", "setne %al", "setp %cl", "orb %cl, %al"],
+            _ => &["ucomiss %xmm1, %xmm0", "sete %al", "setnp %cl", "andb %cl, %al"],
+        };
+        for line in lines {
+            self.emit(*line);
+        }
+        self.emit("movzbl %al, %eax");
+    }
+
+    /// Computes a unary operation into `%rax`, or returns false when the operand can't be used
+    /// with the operator.
+    fn unary(&mut self, op: TokenKind, val: &IrValue) -> bool {
+        let lines: &[&str] = match (op, self.types.of(val)) {
+            (TokenKind::Minus, IrType::Int) => &["negl %eax", "jo chao_fail_neg"],
+            (TokenKind::Minus, IrType::Float) => &["xorl $0x80000000, %eax"],
+            (TokenKind::Tilde, IrType::Int) => &["notl %eax"],
+            (TokenKind::Bang, IrType::Bool) => &["xorl $1, %eax"],
+            _ => return false,
+        };
+        self.load(val, "%rax");
+        for line in lines {
+            self.emit(*line);
+        }
+        return true;
+    }
+
+    /// Turns the flags into 0 or 1 in `%rax`.
+    fn set(&mut self, cond: &str) {
+        self.emit(format!("set{} %al", cond));
+        self.emit("movzbl %al, %eax");
+    }
+
+    /// Where a temporary or binding lives, as an operand.
+    fn place(&self, val: &IrValue) -> Option<String> {
+        return match val {
+            IrValue::Temp(t) => self.temps.get(t).cloned(),
+            IrValue::Identifier(id) => match self.locals.get(id) {
+                Some(place) => Some(place.clone()),
+                None => Some(format!("{}(%rip)", mangle("v_", id))),
+            },
+            _ => None,
+        };
+    }
+
+    /// Puts a value into `reg`, without touching any other register.
+    fn load(&mut self, val: &IrValue, reg: &str) {
+        let line = match val {
+            IrValue::Temp(_) | IrValue::Identifier(_) => match self.place(val) {
+                Some(place) if place == reg => return,
+                Some(place) => format!("movq {}, {}", place, reg),
+                None => format!("xorl {0}, {0}", dword(reg)),
+            },
+            IrValue::ConstInt(n) => format!("movl ${}, {}", n, dword(reg)),
+            IrValue::ConstFloat(n) => format!("movl $0x{:x}, {}", n.to_bits(), dword(reg)),
+            IrValue::ConstChar(c) => format!("movl ${}, {}", *c as u32, dword(reg)),
+            IrValue::ConstBool(b) => format!("movl ${}, {}", *b as u32, dword(reg)),
+            IrValue::ConstStr(s) => format!("leaq {}(%rip), {}", self.string(s), reg),
+            IrValue::Nil => format!("xorl {0}, {0}", dword(reg)),
+        };
+        self.emit(line);
+    }
+
+    /// Puts a value into `reg` as it should be stored in a place of type `to`, wrapping it up
+    /// when an optional is expected.
+    fn load_as(&mut self, val: &IrValue, to: &IrType, reg: &str) {
+        let from = self.types.of(val);
+        match (to, &from) {
+            (IrType::Optional(_), IrType::Nil | IrType::Void) => {
+                self.emit(format!("xorl {0}, {0}", dword(reg)));
+            }
+            (IrType::Optional(_), IrType::Optional(_) | IrType::Str) => self.load(val, reg),
+            (IrType::Optional(_), _) => {
+                self.load(val, reg);
+                self.emit(format!("btsq $32, {}", reg));
+            }
+            _ => self.load(val, reg),
+        }
+    }
+
+    fn store(&mut self, reg: &str, dest: &IrValue) {
+        if let Some(place) = self.place(dest)
+            && place != reg
+        {
+            self.emit(format!("movq {}, {}", reg, place));
+        }
+    }
+
+    /// The label of a string literal, adding it to the data section the first time.
+    fn string(&mut self, s: &String) -> String {
+        let n = match self.strings.iter().position(|other| other == s) {
+            Some(n) => n,
+            None => {
+                self.strings.push(s.clone());
+                self.strings.len() - 1
+            }
+        };
+        return format!(".Lstr{}", n);
+    }
+
+    fn emit(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+}
+
+/// The type tag the runtime uses for printing and comparing optionals.
+fn tag(ty: &IrType) -> u32 {
+    return match ty {
+        IrType::Nil | IrType::Void => 0,
+        IrType::Int => 1,
+        IrType::Float => 2,
+        IrType::Bool => 3,
+        IrType::Char => 4,
+        IrType::Str => 5,
+        IrType::Optional(inner) => tag(inner),
+    };
+}
+
+/// The condition code suffix for a comparison operator.
+fn condition(op: TokenKind, signed: bool) -> &'static str {
+    return match (op, signed) {
+        (TokenKind::Less, true) => "l",
+        (TokenKind::Less, false) => "b",
+        (TokenKind::LessEqual, true) => "le",
+        (TokenKind::LessEqual, false) => "be",
+        (TokenKind::Greater, true) => "g",
+        (TokenKind::Greater, false) => "a",
+        (TokenKind::GreaterEqual, true) => "ge",
+        (TokenKind::GreaterEqual, false) => "ae",
+        (TokenKind::BangEqual, _) => "ne",
+        _ => "e",
+    };
+}
+
+fn is_comparison(op: TokenKind) -> bool {
+    return matches!(op, TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual);
+}
+
+fn is_nullable(ty: &IrType) -> bool {
+    return matches!(ty, IrType::Optional(_) | IrType::Nil | IrType::Void);
+}
+
+/// The type an optional holds, anything else is its own.
+fn inner(ty: &IrType) -> IrType {
+    return match ty {
+        IrType::Optional(inner) => inner.as_ref().clone(),
+        IrType::Void => IrType::Nil,
+        _ => ty.clone(),
+    };
+}
+
+/// The 32 bit half of a 64 bit register.
+fn dword(reg: &str) -> String {
+    return match reg.strip_prefix("%r") {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => format!("{}d", reg),
+        Some(rest) => format!("%e{}", rest),
+        None => reg.to_string(),
+    };
+}
+
+/// Writes a string as an assembler literal, anything outside printable ASCII as an octal
+/// escape.
+fn string_literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out.push('"');
+    return out;
+}
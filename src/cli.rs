@@ -0,0 +1,253 @@
+use crate::common::error::ErrorFormat;
+
+pub(crate) const USAGE: &str = "\
+Usage: chao <command> [options] <file>
+
+Commands:
+    run <file>                  compile and run a program
+    check <file>                parse and type check a program without running it
+    build <file>                compile a program without running it
+    emit <file> --stage=<stage> print the output of one stage of the compiler
+    fix <file>                  apply the fixes diagnostics suggest to a program in place
+    repl                        start an interactive session
+    explain <code>              describe an error code, like E0003
+    help                        print this message
+
+Options:
+    --engine=<engine>           run: execute with `vm` (default) or `interp`
+    --target=<target>           build: compile for `bytecode` (default), `c`, `x86_64`
+                                or `wasm`
+    -o <path>                   build: write the output to <path>, by default it
+                                goes next to the source
+    --stage=<stage>             emit: one of `tokens`, `ast`, `ir`, `ssa`, `cfg`, `asm`,
+                                `llvm` or `bytecode`
+    --opt                       emit: optimize the ir before printing it, which
+                                builds always do
+    --error-format=<format>     write diagnostics as `human` text (default), or as `json`
+                                lines or a `sarif` log on stderr
+    -h, --help                  print this message
+
+Pass `-` as the file to read the program from stdin.
+";
+
+/// Exit code for programs rejected by the compiler or failing at runtime.
+pub(crate) const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid command lines and unreadable input.
+pub(crate) const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Engine {
+    Vm,
+    Interp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Target {
+    Bytecode,
+    /// A standalone C file that any C compiler can turn into a native binary
+    C,
+    /// A Linux executable, assembled and linked with the system `as` and `ld`
+    X86_64,
+    /// A WebAssembly module in the text format, for hosts that provide printing
+    Wasm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Stage {
+    Tokens,
+    Ast,
+    Ir,
+    /// The IR of every function in SSA form, split into blocks
+    Ssa,
+    /// The control flow graph of every function, as Graphviz DOT
+    Cfg,
+    /// The x86-64 assembly the native target is built from
+    Asm,
+    /// A textual LLVM module built from the SSA form, for `clang` to compile
+    Llvm,
+    Bytecode,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    Run {
+        path: String,
+        engine: Engine,
+    },
+    Check {
+        path: String,
+    },
+    Build {
+        path: String,
+        target: Target,
+        output: Option<String>,
+    },
+    Emit {
+        path: String,
+        stage: Stage,
+        /// Whether the optimization passes run before the IR stages are printed
+        optimize: bool,
+    },
+    /// Apply every suggested fix to the file, then report what's left like `check`
+    Fix {
+        path: String,
+    },
+    Repl,
+    /// Print the long form description of an error code
+    Explain {
+        code: String,
+    },
+    Help,
+}
+
+/// Parses the command line, not including the name of the executable, into the command and
+/// how it should report diagnostics. Returns a message describing the problem when the
+/// arguments don't form a valid command.
+pub(crate) fn parse(args: &[String]) -> Result<(Command, ErrorFormat), String> {
+    let mut args = args.iter();
+    let name = match args.next() {
+        Some(name) => name.as_str(),
+        None => return Err("no command given".to_string()),
+    };
+    if matches!(name, "help" | "-h" | "--help") {
+        return Ok((Command::Help, ErrorFormat::Human));
+    }
+    if name == "repl" {
+        return match args.next().map(|a| a.as_str()) {
+            None => Ok((Command::Repl, ErrorFormat::Human)),
+            Some("-h" | "--help") => Ok((Command::Help, ErrorFormat::Human)),
+            Some(arg) => Err(format!("unexpected argument '{}' for 'repl'", arg)),
+        };
+    }
+    if name == "explain" {
+        let code = match args.next().map(|a| a.as_str()) {
+            Some("-h" | "--help") => return Ok((Command::Help, ErrorFormat::Human)),
+            Some(code) => code.to_string(),
+            None => return Err("'explain' expects an error code".to_string()),
+        };
+        return match args.next() {
+            None => Ok((Command::Explain { code }, ErrorFormat::Human)),
+            Some(arg) => Err(format!("unexpected argument '{}' for 'explain'", arg)),
+        };
+    }
+    if !matches!(name, "run" | "check" | "build" | "emit" | "fix") {
+        return Err(format!("unknown command '{}'", name));
+    }
+
+    let mut path: Option<String> = None;
+    let mut engine: Option<Engine> = None;
+    let mut target: Option<Target> = None;
+    let mut output: Option<String> = None;
+    let mut stage: Option<Stage> = None;
+    let mut opt = false;
+    let mut format: Option<ErrorFormat> = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok((Command::Help, ErrorFormat::Human)),
+            "--opt" => opt = true,
+            "-o" => {
+                match args.next() {
+                    Some(o) => output = Some(o.clone()),
+                    None => return Err("expected a path after '-o'".to_string()),
+                }
+            }
+            // a lone dash is the stdin path, not an option
+            "-" => path = set_once(path, arg.clone(), "file")?,
+            _ if arg.starts_with('-') => {
+                let (flag, val) = arg.split_once('=').unwrap_or((arg.as_str(), ""));
+                match flag {
+                    "--engine" => {
+                        let e = match val {
+                            "vm" => Engine::Vm,
+                            "interp" => Engine::Interp,
+                            _ => return Err(format!("unknown engine '{}'", val)),
+                        };
+                        engine = set_once(engine, e, flag)?;
+                    }
+                    "--target" => {
+                        let t = match val {
+                            "bytecode" => Target::Bytecode,
+                            "c" => Target::C,
+                            "x86_64" => Target::X86_64,
+                            "wasm" => Target::Wasm,
+                            _ => return Err(format!("unknown target '{}'", val)),
+                        };
+                        target = set_once(target, t, flag)?;
+                    }
+                    "--stage" => {
+                        let s = match val {
+                            "tokens" => Stage::Tokens,
+                            "ast" => Stage::Ast,
+                            "ir" => Stage::Ir,
+                            "ssa" => Stage::Ssa,
+                            "cfg" => Stage::Cfg,
+                            "asm" => Stage::Asm,
+                            "llvm" => Stage::Llvm,
+                            "bytecode" => Stage::Bytecode,
+                            _ => return Err(format!("unknown stage '{}'", val)),
+                        };
+                        stage = set_once(stage, s, flag)?;
+                    }
+                    "--error-format" => {
+                        let f = match val {
+                            "human" => ErrorFormat::Human,
+                            "json" => ErrorFormat::Json,
+                            "sarif" => ErrorFormat::Sarif,
+                            _ => return Err(format!("unknown error format '{}'", val)),
+                        };
+                        format = set_once(format, f, flag)?;
+                    }
+                    _ => return Err(format!("unknown option '{}'", arg)),
+                }
+            }
+            _ => path = set_once(path, arg.clone(), "file")?,
+        }
+    }
+
+    let path = match path {
+        Some(p) => p,
+        None => return Err(format!("'{}' expects a file", name)),
+    };
+
+    // reject options that belong to a different command instead of silently ignoring them
+    let misplaced = match name {
+        "run" => target.is_some() || output.is_some() || stage.is_some() || opt,
+        "check" | "fix" => engine.is_some() || target.is_some() || output.is_some() || stage.is_some() || opt,
+        "build" => engine.is_some() || stage.is_some() || opt,
+        _ => engine.is_some() || target.is_some() || output.is_some(),
+    };
+    if misplaced {
+        return Err(format!("option not supported by '{}'", name));
+    }
+    if path == "-" && name == "fix" {
+        return Err("'fix' needs a file to write the fixes to".to_string());
+    }
+    if path == "-" && output.is_none() && name == "build" {
+        return Err("'-o' is needed to build from stdin".to_string());
+    }
+
+    let command = match name {
+        "run" => Command::Run { path, engine: engine.unwrap_or(Engine::Vm) },
+        "check" => Command::Check { path },
+        "fix" => Command::Fix { path },
+        "build" => Command::Build { path, target: target.unwrap_or(Target::Bytecode), output },
+        _ => {
+            match stage {
+                Some(Stage::Tokens | Stage::Ast | Stage::Bytecode) if opt => {
+                    return Err("'--opt' only applies to the ir, ssa, cfg, asm and llvm stages".to_string());
+                }
+                Some(stage) => Command::Emit { path, stage, optimize: opt },
+                None => return Err("'emit' expects a --stage".to_string()),
+            }
+        }
+    };
+    return Ok((command, format.unwrap_or(ErrorFormat::Human)));
+}
+
+fn set_once<T>(slot: Option<T>, val: T, what: &str) -> Result<Option<T>, String> {
+    match slot {
+        Some(_) => Err(format!("{} given more than once", what)),
+        None => Ok(Some(val)),
+    }
+}
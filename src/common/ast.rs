@@ -1,9 +1,10 @@
 use crate::Token;
+use super::span::Span;
 
 use super::token::TokenKind;
 
 #[derive(Debug, PartialEq)]
-pub(crate) enum NodeKind<'a> {
+pub(crate) enum NodeKind {
     LiteralInt {
         val: i32,
     },
@@ -13,6 +14,9 @@ pub(crate) enum NodeKind<'a> {
     LiteralStr {
         val: String,
     },
+    LiteralChar {
+        val: char,
+    },
     LiteralIdent {
         id: String,
     },
@@ -21,103 +25,174 @@ pub(crate) enum NodeKind<'a> {
     LiteralNil,
 
     ExprAssignment {
-        id: Box<Node<'a>>,
+        id: Box<Node>,
         op: TokenKind,
-        val: Box<Node<'a>>,
+        val: Box<Node>,
     },
 
     ExprBinary {
-        lhs: Box<Node<'a>>,
+        lhs: Box<Node>,
         op: TokenKind,
-        rhs: Box<Node<'a>>,
+        rhs: Box<Node>,
     },
 
+    /// Prefix or postfix unary operation, only `++` and `--` can be postfix
     ExprUnary {
         op: TokenKind,
-        operand: Box<Node<'a>>,
+        operand: Box<Node>,
+        postfix: bool,
     },
 
     StmtVariable {
         id: String,
-        val: Box<Node<'a>>,
+        val: Box<Node>,
     },
 
     StmtConstant {
         id: String,
-        val: Box<Node<'a>>,
+        val: Box<Node>,
     },
 
     StmtExpression {
-        expr: Box<Node<'a>>,
+        expr: Box<Node>,
+    },
+
+    /// Braces delimited list of statements with its own scope
+    StmtBlock {
+        body: Vec<Node>,
+    },
+
+    /// `otherwise` is either another `StmtIf` for `else if` chains or a `StmtBlock`
+    StmtIf {
+        cond: Box<Node>,
+        then: Box<Node>,
+        otherwise: Option<Box<Node>>,
+    },
+
+    StmtWhile {
+        cond: Box<Node>,
+        body: Box<Node>,
+    },
+
+    StmtLoop {
+        body: Box<Node>,
+    },
+
+    StmtBreak,
+    StmtContinue,
+
+    /// `ret` is `None` for functions that don't declare a return type
+    StmtFunction {
+        id: String,
+        params: Vec<Param>,
+        ret: Option<TypeName>,
+        body: Box<Node>,
+    },
+
+    StmtReturn {
+        val: Option<Box<Node>>,
     },
 
-    Invalid {
-        tk: Token<'a>,
+    ExprCall {
+        callee: Box<Node>,
+        args: Vec<Node>,
     },
 }
 
+/// A type written out in the source, like the `int` in `fn f(a: int)`. Names are only
+/// checked during resolution.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct TypeName {
+    pub name: String,
+    /// Written with a trailing `?`, the value may also be `nil`
+    pub optional: bool,
+    pub span: Span,
+}
+
 #[derive(Debug, PartialEq)]
-pub(crate) struct Node<'a> {
-    pub kind: NodeKind<'a>,
+pub(crate) struct Param {
+    pub id: String,
+    pub ty: TypeName,
+    /// From the name through to the end of the type
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Node {
+    pub kind: NodeKind,
+    /// Everything the node was parsed from, so an expression covers all of its operands
+    pub span: Span,
+    /// The line of the token the node is named after, like the operator of a binary
+    /// expression, which is what the bytecode's line table shows
     pub line: usize,
-    pub offset: usize,
 }
 
-impl<'a> Node<'a> {
-    pub(crate) fn new(kind: NodeKind<'a>, line: usize, offset: usize) -> Node<'a> {
+impl Node {
+    pub(crate) fn new(kind: NodeKind, span: Span, line: usize) -> Node {
         return Node {
             kind,
+            span,
             line,
-            offset,
-        };
-    }
-
-    pub(crate) fn invalid(tk: Token<'a>) -> Node<'a> {
-        let line = tk.line;
-        let offset = tk.offset;
-        return Node {
-            kind: NodeKind::Invalid { tk },
-            line,
-            offset,
         };
     }
 
     /// Takes a token and attempts to parse it's lexeme into an `i32`.
     /// Will remove all underscores and parse, returning `Err` if anything goes wrong.
-    pub(crate) fn int(token: &Token) -> Result<Node<'a>, ()> {
+    pub(crate) fn int(token: &Token) -> Result<Node, ()> {
         let raw = token.lexeme.replace("_", "");
         match raw.parse::<i32>() {
-            Ok(val) => Ok(Node::new(NodeKind::LiteralInt { val }, token.line, token.offset)),
+            Ok(val) => Ok(Node::new(NodeKind::LiteralInt { val }, token.span, token.line)),
             Err(_) => Err(()),
         }
     }
 
     /// Takes a token and attempts to parse it's lexeme into an `f32`.
     /// Will remove all underscores and parse, returning `Err` if anything goes wrong.
-    pub(crate) fn float(token: &Token) -> Result<Node<'a>, ()> {
+    pub(crate) fn float(token: &Token) -> Result<Node, ()> {
         let raw = token.lexeme.replace("_", "");
         match raw.parse::<f32>() {
-            Ok(val) => Ok(Node::new(NodeKind::LiteralFloat { val }, token.line, token.offset)),
+            Ok(val) => Ok(Node::new(NodeKind::LiteralFloat { val }, token.span, token.line)),
             Err(_) => Err(()),
         }
     }
 
+    /// Takes a token and attempts to turn it's lexeme into a single `char`, handling the
+    /// escapes `\n`, `\r`, `\t`, `\\` and `\0`. Returns `Err` for anything else.
+    pub(crate) fn char(token: &Token) -> Result<Node, ()> {
+        let mut chars = token.lexeme.chars();
+        let val = match (chars.next(), chars.next(), chars.next()) {
+            (Some('\\'), Some(esc), None) => {
+                match esc {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '\\' => '\\',
+                    '0' => '\0',
+                    _ => return Err(()),
+                }
+            }
+            (Some(c), None, None) => c,
+            _ => return Err(()),
+        };
+        return Ok(Node::new(NodeKind::LiteralChar { val }, token.span, token.line));
+    }
+
     /// Takes a token and returns an Identifier node where `id` is the lexeme of the token.
-    pub(crate) fn ident(token: &Token) -> Node<'a> {
+    pub(crate) fn ident(token: &Token) -> Node {
         return Node::new(
             NodeKind::LiteralIdent { id: token.lexeme.to_string() },
-            token.line,
-            token.offset
+            token.span,
+            token.line
         );
     }
 
     /// Takes a token and returns a String where `val` is a copied and dynamiclly allocated string containing
     /// the token's lexeme
-    pub(crate) fn str(token: &Token) -> Node<'a> {
+    pub(crate) fn str(token: &Token) -> Node {
         return Node::new(
             NodeKind::LiteralStr { val: token.lexeme.to_string() },
-            token.line,
-            token.offset
+            token.span,
+            token.line
         );
     }
 }
@@ -1,111 +1,159 @@
 use std::{ fmt::Display, io::{ stdout, Write } };
-use super::token::Token;
+use super::{ span::Span, token::Token };
 
 mod terminal {
-    pub(super) const ESC: &'static str = "\x1b[";
-    pub(super) const RED: &'static str = "91m";
-    pub(super) const GREEN: &'static str = "92m";
-    pub(super) const YELLOW: &'static str = "93m";
-    pub(super) const RESET: &'static str = "\x1b[m";
+    pub(super) const ESC: &str = "\x1b[";
+    pub(super) const RED: &str = "91m";
+    pub(super) const GREEN: &str = "92m";
+    pub(super) const YELLOW: &str = "93m";
+    pub(super) const RESET: &str = "\x1b[m";
 }
 
 mod formatting {
-    use crate::common::token::Token;
+    use crate::common::span::Span;
     use super::{ terminal, ErrorSeverity };
 
-    /// Format line and offset
-    /// Takes in a line and an offset and returns the formatting everything
-    pub(super) fn format_line_offset(
-        line: usize,
-        offset: usize,
-        source: &Vec<String>,
+    /// Spans over more lines than this only show the first and the last of them
+    const MAX_LINES: usize = 4;
+
+    /// Formats the header naming the severity, where the error is and its code and kind.
+    /// Errors that can't be found in the source are only given the path.
+    pub(super) fn format_header(
+        severity: &ErrorSeverity,
         path: &String,
-        kind: &'static str,
-        severity: &ErrorSeverity
-    ) -> Option<(String, String)> {
-        // Format the header
-        let header = format!(
-            "\n[{}] {}:{} {}{}{}{}:",
+        at: Option<(usize, usize)>,
+        code: &'static str,
+        kind: &'static str
+    ) -> String {
+        let place = match at {
+            Some((line, column)) => format!("{}:{}:{}", path, line, column),
+            None => path.clone(),
+        };
+        return format!(
+            "\n[{}] {} {}{}{} {}{}:",
             severity,
-            path,
-            line,
+            place,
             terminal::ESC,
             terminal::YELLOW,
+            code,
             kind,
             terminal::RESET
         );
+    }
 
-        // Get the content of the line from the source.
-        let line_content = &source[line - 1];
-
-        // Create the whitespace to align with the token's position
-        let whitespace = " ".repeat(offset);
-        let underline = "^";
-
-        // Prepare the formatted error message with the highlighted line.
-        let body = format!(
-            "~\n~ {}\n~ {}{}{}{}{}",
-            line_content,
-            terminal::ESC,
-            terminal::YELLOW,
-            whitespace,
-            underline,
-            terminal::RESET
-        );
+    /// Formats every line of source a span covers, underlining the part of each line inside
+    /// the span. An empty span, like the end of the input, still gets a single `^`.
+    pub(super) fn format_snippet(span: Span, source: &[String]) -> Option<String> {
+        let (first, start) = line_of(span.start, source)?;
+        let (last, end) = line_of(span.end.max(span.start), source)?;
+
+        let mut body = String::from("~");
+        for line in first..=last {
+            // the lines in the middle of a long span are left out
+            if last - first >= MAX_LINES && line > first && line < last {
+                if line == first + 1 {
+                    body.push_str("\n~ ...");
+                }
+                continue;
+            }
 
-        // Return the line content as part of the error message.
-        Some((body, header))
+            // lines after the first are underlined from their indentation on
+            let text = line_text(&source[line - 1]);
+            let from = if line == first { start } else { text.len() - text.trim_start().len() };
+            let to = if line == last { end } else { text.len() };
+            let width = text.get(from..to).map_or(0, |t| t.chars().count());
+            let width = if first == last { width.max(1) } else { width };
+
+            body.push_str(&format!("\n~ {}", text));
+            if width > 0 {
+                body.push_str(&format!(
+                    "\n~ {}{}{}{}{}",
+                    terminal::ESC,
+                    terminal::YELLOW,
+                    " ".repeat(column(text, from) - 1),
+                    "^".repeat(width),
+                    terminal::RESET
+                ));
+            }
+        }
+        return Some(body);
     }
 
-    /// Takes in a token and returns the formatted error header
-    /// and it's formatted line content to be printed.
-    pub(super) fn format_token(
-        token: &Token,
-        source: &Vec<String>,
-        path: &String,
-        kind: &'static str,
-        severity: &ErrorSeverity
-    ) -> Option<(String, String)> {
-        // A wee bit of bounds checking
-        if token.line == 0 || token.line > source.len() {
+    /// Formats the line a fix changes as it reads with the fix applied, underlining what the
+    /// fix puts there. Fixes spanning more than one line aren't shown.
+    pub(super) fn format_fix(span: Span, replacement: &str, source: &[String]) -> Option<String> {
+        let (line, start) = line_of(span.start, source)?;
+        let (last, end) = line_of(span.end.max(span.start), source)?;
+        if line != last || replacement.contains('\n') {
             return None;
         }
 
-        // Format the header
-        let header = format!(
-            "\n[{}] {}:{} {}{}{}{}:",
-            severity,
-            path,
-            token.line,
+        let text = line_text(&source[line - 1]);
+        let fixed = format!("{}{}{}", text.get(..start)?, replacement, text.get(end..)?);
+        return Some(format!(
+            "~\n~ {}\n~ {}{}{}{}{}",
+            fixed,
             terminal::ESC,
-            terminal::YELLOW,
-            kind,
+            terminal::GREEN,
+            " ".repeat(column(&fixed, start) - 1),
+            "^".repeat(replacement.chars().count().max(1)),
             terminal::RESET
-        );
+        ));
+    }
+
+    /// Finds the line a byte offset into the source is on, returning the line counting from
+    /// one and the offset into that line. The end of a line counts as part of it.
+    pub(super) fn line_of(offset: usize, source: &[String]) -> Option<(usize, usize)> {
+        let mut start = 0;
+        for (i, line) in source.iter().enumerate() {
+            if offset <= start + line.len() {
+                return Some((i + 1, offset - start));
+            }
+            // lines are split on newlines, which the offsets have to count
+            start += line.len() + 1;
+        }
+        return None;
+    }
 
-        // Get the content of the line from the source.
-        let line_content = &source[token.line - 1];
+    /// A line as it reads, without the `\r` of a CRLF line ending.
+    pub(super) fn line_text(line: &str) -> &str {
+        return line.strip_suffix('\r').unwrap_or(line);
+    }
 
-        // Create the whitespace to align with the token's position
-        let whitespace = " ".repeat(token.offset);
-        let underline = "^".repeat(token.lexeme.len());
+    /// The column of a byte offset into a line, counting characters from one.
+    pub(super) fn column(text: &str, offset: usize) -> usize {
+        return text.get(..offset).map_or(offset, |t| t.chars().count()) + 1;
+    }
+}
 
-        // Prepare the formatted error message with the highlighted line.
-        let body = format!(
-            "~\n~ {}\n~ {}{}{}{}{}",
-            line_content,
-            terminal::ESC,
-            terminal::YELLOW,
-            whitespace,
-            underline,
-            terminal::RESET
-        );
+mod json {
+    /// Writes a string as a JSON string literal.
+    pub(super) fn string(s: &str) -> String {
+        let mut out = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                _ if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                _ => out.push(c),
+            }
+        }
+        out.push('"');
+        return out;
+    }
 
-        // Return the line content as part of the error message.
-        Some((body, header))
+    /// Writes an object from fields whose values are already JSON.
+    pub(super) fn object(fields: &[(&str, String)]) -> String {
+        let fields: Vec<String> = fields.iter().map(|(key, val)| format!("{}:{}", string(key), val)).collect();
+        return format!("{{{}}}", fields.join(","));
     }
 }
 
+/// Every kind of error, each with a stable code that `chao explain` describes. Codes are
+/// never reused or renumbered, new variants take the next free one.
 pub(crate) enum ErrorBase<'a> {
     /// Syntax error in the code.
     SyntaxError {
@@ -119,14 +167,11 @@ pub(crate) enum ErrorBase<'a> {
 
     /// Unterminated literal, this is a lexing error.
     UnterminatedLiteral {
-        line: usize,
-        offset: usize,
+        span: Span,
     },
 
     /// Expected a token but found something else
     ExpectedToken {
-        line: usize,
-        offset: usize,
         offender: Token<'a>,
     },
 
@@ -138,47 +183,162 @@ pub(crate) enum ErrorBase<'a> {
 
     /// Any kind of operation between two incompatible types
     IncompatibleTypes {
-        line: usize,
-        offset: usize,
+        span: Span,
     },
 
     UnknownIdentifier {
-        line: usize,
-        offset: usize,
+        span: Span,
+    },
+
+    /// `break` or `continue` used outside of a loop body
+    OutsideLoop {
+        span: Span,
+    },
+
+    /// Calling a function with the wrong number of arguments
+    ArgumentCount {
+        span: Span,
+    },
+
+    /// Passing an argument whose type doesn't match the parameter
+    ArgumentType {
+        span: Span,
+    },
+
+    /// Calling something that isn't a function
+    NotCallable {
+        span: Span,
+    },
+
+    /// A type name that doesn't name any type
+    UnknownType {
+        span: Span,
+    },
+
+    /// `return` used outside of a function body
+    OutsideFunction {
+        span: Span,
+    },
+
+    /// A function with a return type that can finish without returning
+    MissingReturn {
+        span: Span,
+    },
+
+    /// Writing to a binding declared as a constant
+    ReassignConstant {
+        span: Span,
     },
 
     /// One of few lexer errors, illegal character found while tokenizing
     IllegalCharacter {
-        line: usize,
-        offset: usize,
+        span: Span,
+    },
+
+    /// Code that parses but that a later stage of the compiler has no support for yet
+    UnsupportedConstruct {
+        span: Span,
+    },
+
+    /// A constant or variable that is declared but never read, only ever a warning
+    UnusedBinding {
+        span: Span,
+    },
+
+    /// An operation on constants that is certain to fail, such as integer overflow
+    ArithmeticError {
+        span: Span,
+    },
+
+    /// Failure while lowering a resolved program into bytecode
+    CompileError {
+        span: Span,
+    },
+
+    /// Any failure raised while executing the program, such as overflow
+    RuntimeError {
+        span: Span,
+    },
+
+    /// Declaring a name that the same scope already has
+    DuplicateDeclaration {
+        span: Span,
     },
 }
 
 impl<'a> ErrorBase<'a> {
-    /// Returns a formatted version of header and line content based on the error type
-    pub(crate) fn formatted(
-        &self,
-        source: &Vec<String>,
-        path: &String,
-        severity: &ErrorSeverity
-    ) -> Option<(String, String)> {
+    /// Returns the formatted header along with the lines of source the error points at,
+    /// which are left out when the error isn't in the reporter's source.
+    pub(crate) fn formatted(&self, reporter: &Reporter, severity: &ErrorSeverity) -> (String, String) {
+        let span = self.span();
+        let at = reporter.locate(span);
+        let header = formatting::format_header(
+            severity,
+            reporter.path,
+            at.as_ref().map(|at| (at.line, at.column)),
+            self.code(),
+            self.kind()
+        );
+        let body = match at {
+            Some(_) => formatting::format_snippet(span, reporter.source).unwrap_or_default(),
+            None => String::new(),
+        };
+        return (body, header);
+    }
+
+    /// What the error points at in the source.
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            Self::SyntaxError { token } | Self::InvalidStatement { token } | Self::ParseError { token } => {
+                token.span
+            }
+            Self::ExpectedToken { offender } => offender.span,
+            Self::UnterminatedLiteral { span } |
+            Self::ReassignConstant { span } |
+            Self::IncompatibleTypes { span } |
+            Self::UnknownIdentifier { span } |
+            Self::OutsideLoop { span } |
+            Self::ArgumentCount { span } |
+            Self::ArgumentType { span } |
+            Self::NotCallable { span } |
+            Self::UnknownType { span } |
+            Self::OutsideFunction { span } |
+            Self::MissingReturn { span } |
+            Self::IllegalCharacter { span } |
+            Self::UnsupportedConstruct { span } |
+            Self::UnusedBinding { span } |
+            Self::ArithmeticError { span } |
+            Self::CompileError { span } |
+            Self::RuntimeError { span } |
+            Self::DuplicateDeclaration { span } => *span,
+        }
+    }
+
+    /// The code of the error, which stays the same between versions.
+    pub(crate) fn code(&self) -> &'static str {
         match self {
-            Self::SyntaxError { token } =>
-                formatting::format_token(token, source, path, self.kind(), severity),
-            Self::InvalidStatement { token } =>
-                formatting::format_token(token, source, path, self.kind(), severity),
-            Self::ParseError { token } =>
-                formatting::format_token(token, source, path, self.kind(), severity),
-            Self::IllegalCharacter { line, offset } =>
-                formatting::format_line_offset(*line, *offset, source, path, self.kind(), severity),
-            Self::UnterminatedLiteral { line, offset } =>
-                formatting::format_line_offset(*line, *offset, source, path, self.kind(), severity),
-            Self::ExpectedToken { line, offset, offender: _ } =>
-                formatting::format_line_offset(*line, *offset, source, path, self.kind(), severity),
-            Self::IncompatibleTypes { line, offset } =>
-                formatting::format_line_offset(*line, *offset, source, path, self.kind(), severity),
-            Self::UnknownIdentifier { line, offset } =>
-                formatting::format_line_offset(*line, *offset, source, path, self.kind(), severity),
+            Self::SyntaxError { token: _ } => "E0001",
+            Self::InvalidStatement { token: _ } => "E0002",
+            Self::UnknownIdentifier { span: _ } => "E0003",
+            Self::ExpectedToken { offender: _ } => "E0004",
+            Self::ParseError { token: _ } => "E0005",
+            Self::IncompatibleTypes { span: _ } => "E0006",
+            Self::UnterminatedLiteral { span: _ } => "E0007",
+            Self::OutsideLoop { span: _ } => "E0008",
+            Self::ArgumentCount { span: _ } => "E0009",
+            Self::ArgumentType { span: _ } => "E0010",
+            Self::NotCallable { span: _ } => "E0011",
+            Self::UnknownType { span: _ } => "E0012",
+            Self::OutsideFunction { span: _ } => "E0013",
+            Self::MissingReturn { span: _ } => "E0014",
+            Self::ReassignConstant { span: _ } => "E0015",
+            Self::IllegalCharacter { span: _ } => "E0016",
+            Self::UnsupportedConstruct { span: _ } => "E0017",
+            Self::UnusedBinding { span: _ } => "E0018",
+            Self::ArithmeticError { span: _ } => "E0019",
+            Self::CompileError { span: _ } => "E0020",
+            Self::RuntimeError { span: _ } => "E0021",
+            Self::DuplicateDeclaration { span: _ } => "E0022",
         }
     }
 
@@ -186,12 +346,26 @@ impl<'a> ErrorBase<'a> {
         match self {
             Self::SyntaxError { token: _ } => "Syntax Error",
             Self::ParseError { token: _ } => "Parse Error",
-            Self::IllegalCharacter { line: _, offset: _ } => "Illegal Character",
-            Self::UnterminatedLiteral { line: _, offset: _ } => "Unterminated Literal",
+            Self::IllegalCharacter { span: _ } => "Illegal Character",
+            Self::UnterminatedLiteral { span: _ } => "Unterminated Literal",
             Self::InvalidStatement { token: _ } => "Invalid Statement",
-            Self::ExpectedToken { line: _, offset: _, offender: _ } => "Expected Token",
-            Self::IncompatibleTypes { line: _, offset: _ } => "Incomaptible Types",
-            Self::UnknownIdentifier { line: _, offset: _ } => "Unknown Identifier",
+            Self::ExpectedToken { offender: _ } => "Expected Token",
+            Self::IncompatibleTypes { span: _ } => "Incompatible Types",
+            Self::UnknownIdentifier { span: _ } => "Unknown Identifier",
+            Self::ReassignConstant { span: _ } => "Reassign Constant",
+            Self::ArgumentCount { span: _ } => "Argument Count",
+            Self::ArgumentType { span: _ } => "Argument Type",
+            Self::NotCallable { span: _ } => "Not Callable",
+            Self::UnknownType { span: _ } => "Unknown Type",
+            Self::OutsideFunction { span: _ } => "Outside Function",
+            Self::MissingReturn { span: _ } => "Missing Return",
+            Self::OutsideLoop { span: _ } => "Outside Loop",
+            Self::UnsupportedConstruct { span: _ } => "Unsupported Construct",
+            Self::UnusedBinding { span: _ } => "Unused Binding",
+            Self::ArithmeticError { span: _ } => "Arithmetic Error",
+            Self::CompileError { span: _ } => "Compile Error",
+            Self::RuntimeError { span: _ } => "Runtime Error",
+            Self::DuplicateDeclaration { span: _ } => "Duplicate Declaration",
         }
     }
 }
@@ -213,11 +387,66 @@ impl Display for ErrorSeverity {
     }
 }
 
+impl ErrorSeverity {
+    /// The severity as machine readable output spells it.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Suggestion => "suggestion",
+        }
+    }
+
+    /// The SARIF level for the severity, which calls suggestions notes.
+    fn level(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Suggestion => "note",
+        }
+    }
+}
+
+/// How the reporter writes diagnostics out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ErrorFormat {
+    /// Colored text showing the offending line, on stdout
+    Human,
+    /// One JSON object per diagnostic on its own line, on stderr
+    Json,
+    /// A single SARIF 2.1.0 log on stderr, written once the compiler is done
+    Sarif,
+}
+
+/// Where a diagnostic points in the source. Lines and columns count from one, columns in
+/// characters, and the span is in bytes from the start of the file.
+struct Location {
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    start: usize,
+    end: usize,
+}
+
+/// Something shown along with the message of an error.
+enum Attachment {
+    /// Another place in the source that explains the error, with what's there
+    Label(Span, String),
+    /// Extra context, shown after the message as a `note:` line
+    Note(String),
+    /// A way to fix the error, shown last as a `help:` line
+    Help(String),
+    /// An edit that fixes the error, replacing the span with the text, and what it does
+    Fix(Span, String, String),
+}
+
 pub(crate) struct ChaoError<'a> {
     base: ErrorBase<'a>,
     severity: ErrorSeverity,
     can_compile: bool,
-    msg: &'static str,
+    msg: String,
+    attachments: Vec<Attachment>,
 }
 
 impl<'a> ChaoError<'a> {
@@ -225,54 +454,205 @@ impl<'a> ChaoError<'a> {
         base: ErrorBase<'a>,
         severity: ErrorSeverity,
         can_compile: bool,
-        msg: &'static str
+        msg: impl Into<String>
     ) -> ChaoError<'a> {
         return ChaoError {
             base,
             severity,
             can_compile,
-            msg,
+            msg: msg.into(),
+            attachments: vec![],
+        };
+    }
+
+    /// Points out another span, like where a binding was declared.
+    pub(crate) fn with_label(mut self, span: Span, msg: impl Into<String>) -> ChaoError<'a> {
+        self.attachments.push(Attachment::Label(span, msg.into()));
+        return self;
+    }
+
+    pub(crate) fn with_note(mut self, msg: impl Into<String>) -> ChaoError<'a> {
+        self.attachments.push(Attachment::Note(msg.into()));
+        return self;
+    }
+
+    pub(crate) fn with_help(mut self, msg: impl Into<String>) -> ChaoError<'a> {
+        self.attachments.push(Attachment::Help(msg.into()));
+        return self;
+    }
+
+    /// Suggests replacing `span` with `replacement`, which `chao fix` applies on its own. An
+    /// empty span inserts the replacement.
+    pub(crate) fn with_fix(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        msg: impl Into<String>
+    ) -> ChaoError<'a> {
+        self.attachments.push(Attachment::Fix(span, replacement.into(), msg.into()));
+        return self;
+    }
+
+    fn labels(&self) -> Vec<(Span, &String)> {
+        return self.attachments
+            .iter()
+            .filter_map(|a| if let Attachment::Label(span, msg) = a { Some((*span, msg)) } else { None })
+            .collect();
+    }
+
+    fn notes(&self) -> Vec<&String> {
+        return self.attachments
+            .iter()
+            .filter_map(|a| if let Attachment::Note(msg) = a { Some(msg) } else { None })
+            .collect();
+    }
+
+    fn help(&self) -> Vec<&String> {
+        return self.attachments
+            .iter()
+            .filter_map(|a| if let Attachment::Help(msg) = a { Some(msg) } else { None })
+            .collect();
+    }
+
+    fn fixes(&self) -> Vec<(Span, &String, &String)> {
+        return self.attachments
+            .iter()
+            .filter_map(|a| if let Attachment::Fix(span, text, msg) = a { Some((*span, text, msg)) } else { None })
+            .collect();
+    }
+
+    /// The message along with anything the error adds to it.
+    pub(crate) fn message(&self) -> String {
+        return match &self.base {
+            ErrorBase::ExpectedToken { offender } => {
+                format!("{} found '{}' instead", self.msg, offender.lexeme)
+            }
+            _ => self.msg.clone(),
         };
     }
 
+    /// The notes and then the help, each line starting with which it is.
+    fn trailers(&self) -> Vec<String> {
+        let notes = self.notes().into_iter().map(|n| format!("note: {}", n));
+        return notes.chain(self.help().into_iter().map(|h| format!("help: {}", h))).collect();
+    }
+
+    /// The error as a single line JSON object. Errors that aren't in the reporter's source
+    /// have a null line and column.
+    fn to_json(&self, reporter: &Reporter) -> String {
+        let labels: Vec<String> = self.labels()
+            .into_iter()
+            .map(|(span, msg)| {
+                let mut fields = vec![("message", json::string(msg))];
+                fields.extend(reporter.json_location(span));
+                json::object(&fields)
+            })
+            .collect();
+        let strings = |lines: Vec<&String>| {
+            format!("[{}]", lines.iter().map(|l| json::string(l)).collect::<Vec<String>>().join(","))
+        };
+
+        let mut fields = vec![
+            ("code", json::string(self.base.code())),
+            ("kind", json::string(self.base.kind())),
+            ("severity", json::string(self.severity.name())),
+            ("message", json::string(&self.message())),
+            ("file", json::string(reporter.path)),
+        ];
+        fields.extend(reporter.json_location(self.base.span()));
+        fields.push(("labels", format!("[{}]", labels.join(","))));
+        fields.push(("notes", strings(self.notes())));
+        fields.push(("help", strings(self.help())));
+        let fixes: Vec<String> = self.fixes()
+            .into_iter()
+            .map(|(span, text, msg)| {
+                let mut fields = vec![("message", json::string(msg)), ("replacement", json::string(text))];
+                fields.extend(reporter.json_location(span));
+                json::object(&fields)
+            })
+            .collect();
+        fields.push(("fixes", format!("[{}]", fixes.join(","))));
+        return json::object(&fields);
+    }
+
+    /// The error as a SARIF result, with its code as the rule. Labels become related
+    /// locations, the notes and help are added to the message text and fixes in the
+    /// reporter's source are kept as SARIF fixes.
+    fn to_sarif(&self, reporter: &Reporter) -> String {
+        let mut text = self.message();
+        for line in self.trailers() {
+            text = format!("{}\n{}", text, line);
+        }
+
+        let mut fields = vec![
+            ("ruleId", json::string(self.base.code())),
+            ("level", json::string(self.severity.level())),
+            ("message", json::object(&[("text", json::string(&text))])),
+            ("locations", format!("[{}]", reporter.sarif_location(self.base.span(), &[]))),
+        ];
+        let labels = self.labels();
+        if !labels.is_empty() {
+            let related: Vec<String> = labels
+                .into_iter()
+                .enumerate()
+                .map(|(id, (span, msg))| {
+                    let message = json::object(&[("text", json::string(msg))]);
+                    reporter.sarif_location(span, &[("id", id.to_string()), ("message", message)])
+                })
+                .collect();
+            fields.push(("relatedLocations", format!("[{}]", related.join(","))));
+        }
+        let fixes: Vec<String> = self.fixes()
+            .into_iter()
+            .filter_map(|(span, text, msg)| reporter.sarif_fix(span, text, msg))
+            .collect();
+        if !fixes.is_empty() {
+            fields.push(("fixes", format!("[{}]", fixes.join(","))));
+        }
+        return json::object(&fields);
+    }
+
     pub(crate) fn print(&self, reporter: &'a Reporter) {
-        // Get the line content
-        let (body, header) = self.base
-            .formatted(reporter.source, reporter.path, &self.severity)
-            .unwrap_or(("".to_string(), "".to_string()));
-
-        // Dont let this error silently (for now)
-        if body == "" || header == "" {
-            eprintln!("ERROR BODY AND/OR HEADER ARE EMPTY!:\n  {}\n  {}", body, header);
-            return;
+        // Get the line content, which is missing for errors from another source
+        let (mut body, header) = self.base.formatted(reporter, &self.severity);
+
+        // labels outside of the reporter's source are left out
+        for (span, msg) in self.labels() {
+            if reporter.locate(span).is_some()
+                && let Some(snippet) = formatting::format_snippet(span, reporter.source)
+            {
+                body = format!("{}\n~ {}:\n{}", body, msg, snippet);
+            }
         }
+        let body = if body.is_empty() { body } else { format!("{}\n", body) };
 
-        let message = self.msg;
+        let message = self.message();
+        let mut out = stdout().lock();
         write!(
-            stdout(),
-            "{header}\n{body}\n{}{}{message}{}",
+            out,
+            "{header}\n{body}{}{}{message}{}",
             terminal::ESC,
             terminal::GREEN,
             terminal::RESET
         ).unwrap();
 
-        match &self.base {
-            ErrorBase::ExpectedToken { line: _, offset: _, offender } => {
-                write!(
-                    stdout(),
-                    "{}{} found '{}' instead{}",
-                    terminal::ESC,
-                    terminal::GREEN,
-                    offender.lexeme,
-                    terminal::RESET
-                ).unwrap();
+        for line in self.trailers() {
+            write!(out, "\n{}", line).unwrap();
+        }
+
+        // fixes show the line they change as it would read after
+        for (span, text, msg) in self.fixes() {
+            write!(out, "\n[{}] {}", ErrorSeverity::Suggestion, msg).unwrap();
+            if reporter.locate(span).is_some()
+                && let Some(fixed) = formatting::format_fix(span, text, reporter.source)
+            {
+                write!(out, ":\n{}", fixed).unwrap();
             }
-            _ => {}
         }
 
         // Flush all of this to stdout
-        write!(stdout(), "\n").unwrap();
-        stdout().flush().unwrap()
+        writeln!(out).unwrap();
+        out.flush().unwrap()
     }
 }
 
@@ -280,21 +660,59 @@ pub(crate) struct Reporter<'a> {
     errors: Vec<ChaoError<'a>>,
     source: &'a Vec<String>,
     path: &'a String,
+    /// The file spans in `source` carry, anything from another file can't be shown
+    file: usize,
+    format: ErrorFormat,
+    /// SARIF results printed so far, they all go in the one log written by `finish`
+    results: Vec<String>,
+    /// The code and kind of every rule the SARIF results refer to, in order of first use
+    rules: Vec<(&'static str, &'static str)>,
 }
 
 impl<'a> Reporter<'a> {
-    pub(crate) fn new(source: &'a Vec<String>, path: &'a String) -> Reporter<'a> {
+    pub(crate) fn new(
+        source: &'a Vec<String>,
+        path: &'a String,
+        file: usize,
+        format: ErrorFormat
+    ) -> Reporter<'a> {
         return Reporter {
             errors: vec![],
             source,
             path,
+            file,
+            format,
+            results: vec![],
+            rules: vec![],
         };
     }
 
-    pub(crate) fn error(&mut self, base: ErrorBase<'a>, can_compile: bool, msg: &'static str) {
+    /// The file the source being reported on is, which the lexer puts in every span.
+    pub(crate) fn file(&self) -> usize {
+        return self.file;
+    }
+
+    pub(crate) fn error(&mut self, base: ErrorBase<'a>, can_compile: bool, msg: impl Into<String>) {
         self.errors.push(ChaoError::new(base, ErrorSeverity::Error, can_compile, msg));
     }
 
+    /// The edits suggested by everything reported so far, leaving out the ones for another
+    /// source.
+    pub(crate) fn fixes(&self) -> Vec<(Span, String)> {
+        return self.errors
+            .iter()
+            .flat_map(|e| e.fixes())
+            .filter(|(span, _, _)| span.file == self.file)
+            .map(|(span, text, _)| (span, text.clone()))
+            .collect();
+    }
+
+    /// Whether anything reported so far stops the program from being compiled. Warnings are
+    /// reported with `can_compile` set so they don't count.
+    pub(crate) fn has_errors(&self) -> bool {
+        return self.errors.iter().any(|e| !e.can_compile);
+    }
+
     pub(crate) fn dump(&mut self, mut errs: Vec<ChaoError<'a>>) {
         for e in errs.drain(0..) {
             self.errors.push(e)
@@ -305,6 +723,112 @@ impl<'a> Reporter<'a> {
         // Drain the errors and copy them into a new vec
         // This way we have ownership
         let errors: Vec<ChaoError<'a>> = self.errors.drain(0..).collect();
-        errors.iter().for_each(|e| e.print(self));
+        match self.format {
+            ErrorFormat::Human => errors.iter().for_each(|e| e.print(self)),
+            ErrorFormat::Json => errors.iter().for_each(|e| eprintln!("{}", e.to_json(self))),
+            ErrorFormat::Sarif => {
+                let results: Vec<String> = errors.iter().map(|e| e.to_sarif(self)).collect();
+                self.results.extend(results);
+                for e in errors.iter() {
+                    let rule = (e.base.code(), e.base.kind());
+                    if !self.rules.contains(&rule) {
+                        self.rules.push(rule);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes out anything that has to wait until every diagnostic is in, which is only the
+    /// SARIF log. It is written even when there's nothing to report.
+    pub(crate) fn finish(&mut self) {
+        if self.format != ErrorFormat::Sarif {
+            return;
+        }
+        let rules: Vec<String> = self.rules
+            .iter()
+            .map(|(code, kind)| json::object(&[("id", json::string(code)), ("name", json::string(kind))]))
+            .collect();
+        let driver = json::object(&[
+            ("name", json::string("chao")),
+            ("version", json::string(env!("CARGO_PKG_VERSION"))),
+            ("rules", format!("[{}]", rules.join(","))),
+        ]);
+        let run = json::object(&[
+            ("tool", json::object(&[("driver", driver)])),
+            ("results", format!("[{}]", self.results.join(","))),
+        ]);
+        eprintln!("{}", json::object(&[
+            ("$schema", json::string("https://json.schemastore.org/sarif-2.1.0.json")),
+            ("version", json::string("2.1.0")),
+            ("runs", format!("[{}]", run)),
+        ]));
+    }
+
+    /// The line, column and span fields of a JSON diagnostic.
+    fn json_location(&self, span: Span) -> Vec<(&'static str, String)> {
+        let at = self.locate(span);
+        let null = || "null".to_string();
+        return vec![
+            ("line", at.as_ref().map_or_else(null, |at| at.line.to_string())),
+            ("column", at.as_ref().map_or_else(null, |at| at.column.to_string())),
+            ("span", json::object(&[("start", span.start.to_string()), ("end", span.end.to_string())])),
+        ];
+    }
+
+    /// A SARIF location with `fields` in front of it. Spans that aren't in the reporter's
+    /// source only name the file.
+    fn sarif_location(&self, span: Span, fields: &[(&str, String)]) -> String {
+        let mut physical = vec![("artifactLocation", json::object(&[("uri", json::string(self.path))]))];
+        if let Some(at) = self.locate(span) {
+            physical.push(("region", json::object(&[
+                ("startLine", at.line.to_string()),
+                ("startColumn", at.column.to_string()),
+                ("endLine", at.end_line.to_string()),
+                ("endColumn", at.end_column.to_string()),
+                ("byteOffset", at.start.to_string()),
+                ("byteLength", (at.end - at.start).to_string()),
+            ])));
+        }
+        let mut fields = fields.to_vec();
+        fields.push(("physicalLocation", json::object(&physical)));
+        return json::object(&fields);
+    }
+
+    /// A SARIF fix replacing the bytes of a span, if it's in the reporter's source.
+    fn sarif_fix(&self, span: Span, text: &str, msg: &str) -> Option<String> {
+        let at = self.locate(span)?;
+        let replacement = json::object(&[
+            ("deletedRegion", json::object(&[
+                ("byteOffset", at.start.to_string()),
+                ("byteLength", (at.end - at.start).to_string()),
+            ])),
+            ("insertedContent", json::object(&[("text", json::string(text))])),
+        ]);
+        let change = json::object(&[
+            ("artifactLocation", json::object(&[("uri", json::string(self.path))])),
+            ("replacements", format!("[{}]", replacement)),
+        ]);
+        return Some(json::object(&[
+            ("description", json::object(&[("text", json::string(msg))])),
+            ("artifactChanges", format!("[{}]", change)),
+        ]));
+    }
+
+    /// Works out the lines and columns a span covers, if it's in the reporter's source.
+    fn locate(&self, span: Span) -> Option<Location> {
+        if span.file != self.file {
+            return None;
+        }
+        let (line, offset) = formatting::line_of(span.start, self.source)?;
+        let (end_line, end_offset) = formatting::line_of(span.end.max(span.start), self.source)?;
+        return Some(Location {
+            line,
+            column: formatting::column(&self.source[line - 1], offset),
+            end_line,
+            end_column: formatting::column(&self.source[end_line - 1], end_offset),
+            start: span.start,
+            end: span.end.max(span.start),
+        });
     }
 }
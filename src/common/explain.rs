@@ -0,0 +1,339 @@
+/// The kind and long form description of every error code, printed by `chao explain`. Each
+/// description says what the error means, shows a program that has it and how to fix it.
+const EXPLANATIONS: &[(&str, &str, &str)] = &[
+    ("E0001", "Syntax Error", "\
+The parser found a token that can't start or continue what it was parsing. This is the
+catch all for syntax the parser has no more specific error for.
+
+Erroneous example:
+
+    let = 5;
+
+A declaration needs a name between `let` and `=`:
+
+    let count = 5;
+    print(count);
+"),
+    ("E0002", "Invalid Statement", "\
+An expression was used as a statement but doing so has no effect. Only assignments, calls
+and increments or decrements do something when their value is thrown away.
+
+Erroneous example:
+
+    total = 1;
+    total + 2;
+
+Use the value, for example by printing it or binding it to a name:
+
+    total = 1;
+    print(total + 2);
+"),
+    ("E0003", "Unknown Identifier", "\
+A name was used that isn't declared in this scope or any scope around it. Bindings are only
+visible after their declaration and inside the block they're declared in.
+
+Erroneous example:
+
+    print(count);
+
+Declare the name before using it:
+
+    count = 3;
+    print(count);
+"),
+    ("E0004", "Expected Token", "\
+The parser expected a particular token, like the `;` ending a statement or the `)` closing
+a call, and found something else. The message names both.
+
+Erroneous example:
+
+    print(1)
+
+Add the missing token:
+
+    print(1);
+"),
+    ("E0005", "Parse Error", "\
+A literal is written correctly but its value can't be represented. Integers have to fit in
+32 bits, and character literals hold exactly one character or escape.
+
+Erroneous example:
+
+    letter = 'ab';
+
+Use a string for more than one character:
+
+    letters = \"ab\";
+    print(letters);
+"),
+    ("E0006", "Incompatible Types", "\
+An operation was given values of types it doesn't work on, like adding a `bool` to an
+`int`, a condition that isn't a `bool`, or returning the wrong type from a function. The
+message names the types involved and the whole expression is underlined.
+
+Erroneous example:
+
+    print(1 + true);
+
+Convert or change one side so both have types the operator accepts:
+
+    print(1 + 1);
+"),
+    ("E0007", "Unterminated Literal", "\
+A string or character literal was opened but never closed. Literals can't span more than
+one line, so the closing quote has to be on the same line as the opening one.
+
+Erroneous example:
+
+    print(\"hello);
+
+Close the literal:
+
+    print(\"hello\");
+"),
+    ("E0008", "Outside Loop", "\
+`break` or `continue` was used outside of a `while` or `loop` body, where there's no loop
+for it to leave or restart.
+
+Erroneous example:
+
+    break;
+
+Only use them inside a loop:
+
+    loop {
+        break;
+    }
+"),
+    ("E0009", "Argument Count", "\
+A function was called with more or fewer arguments than it has parameters.
+
+Erroneous example:
+
+    fn add(a: int, b: int): int {
+        return a + b;
+    }
+    print(add(1));
+
+Pass one argument for every parameter:
+
+    fn add(a: int, b: int): int {
+        return a + b;
+    }
+    print(add(1, 2));
+"),
+    ("E0010", "Argument Type", "\
+An argument's type doesn't match the type of the parameter it's passed to. Optional
+parameters, like `int?`, also accept `nil`.
+
+Erroneous example:
+
+    fn twice(n: int): int {
+        return n * 2;
+    }
+    print(twice(\"2\"));
+
+Pass a value of the parameter's type:
+
+    fn twice(n: int): int {
+        return n * 2;
+    }
+    print(twice(2));
+"),
+    ("E0011", "Not Callable", "\
+Something other than a function was called. Only names declared with `fn` can be called.
+
+Erroneous example:
+
+    n = 1;
+    print(n(2));
+
+Call a function instead:
+
+    fn inc(n: int): int {
+        return n + 1;
+    }
+    print(inc(2));
+"),
+    ("E0012", "Unknown Type", "\
+A parameter or return type names a type that doesn't exist. The types are `int`, `float`,
+`str`, `char`, `bool` and, for return types only, `void`. A trailing `?` makes any of them
+but `void` optional.
+
+Erroneous example:
+
+    fn show(n: integer) {
+        print(n);
+    }
+
+Use one of the type names:
+
+    fn show(n: int) {
+        print(n);
+    }
+"),
+    ("E0013", "Outside Function", "\
+`return` was used outside of a function body. The top level of a program has nothing to
+return to.
+
+Erroneous example:
+
+    return 1;
+
+Only return from inside a function:
+
+    fn one(): int {
+        return 1;
+    }
+"),
+    ("E0014", "Missing Return", "\
+A function with a return type can reach the end of its body without returning a value.
+Every path through the function has to end in a `return`.
+
+Erroneous example:
+
+    fn sign(n: int): int {
+        if n < 0 {
+            return -1;
+        }
+    }
+
+Return a value on the remaining paths too:
+
+    fn sign(n: int): int {
+        if n < 0 {
+            return -1;
+        }
+        return 1;
+    }
+"),
+    ("E0015", "Reassign Constant", "\
+A constant was written to. Bindings declared as `name = value;` are constants, only ones
+declared with `let` can be assigned to or incremented. Builtins like `print` can't be
+assigned to either.
+
+Erroneous example:
+
+    limit = 10;
+    limit -> 20;
+
+Declare the binding with `let` to make it mutable:
+
+    let limit = 10;
+    limit -> 20;
+    print(limit);
+"),
+    ("E0016", "Illegal Character", "\
+The lexer found a character that isn't part of any token in Chao.
+
+Erroneous example:
+
+    print(1 @ 2);
+
+Remove the character or use an operator that exists:
+
+    print(1 + 2);
+"),
+    ("E0017", "Unsupported Construct", "\
+The program is valid but uses something a later stage of the compiler, or one of its
+targets, doesn't support yet, like assigning to something that isn't a name.
+
+Erroneous example:
+
+    (1) -> 2;
+
+Only assign to bindings:
+
+    let one = 1;
+    one -> 2;
+    print(one);
+"),
+    ("E0018", "Unused Binding", "\
+A constant or variable is declared but its value is never read. This is only a warning,
+the program still runs. Names starting with an underscore are never warned about.
+
+Erroneous example:
+
+    temp = 1;
+
+Use the binding, remove it, or start its name with an underscore:
+
+    _temp = 1;
+"),
+    ("E0019", "Arithmetic Error", "\
+An operation on constants is certain to fail when the program runs, like dividing by zero
+or an integer overflow. The compiler evaluates constant expressions ahead of time and
+reports these instead of waiting for the program to fail.
+
+Erroneous example:
+
+    print(10 / 0);
+
+Make sure the operation can succeed:
+
+    print(10 / 2);
+"),
+    ("E0020", "Compile Error", "\
+A program that passed every check couldn't be turned into bytecode, because it goes past a
+limit of the bytecode format, like 65,536 constants in one program or a jump over more than
+65,535 bytes.
+
+Erroneous example, with the body repeated tens of thousands of times:
+
+    let i = 0;
+    while i < 10 {
+        i -> i + 1;
+        i -> i + 1;
+    }
+
+Move the repeated work into a function so no single body is that large:
+
+    fn step(i: int): int {
+        return i + 1;
+    }
+    let i = 0;
+    while i < 10 {
+        i -> step(i);
+    }
+"),
+    ("E0021", "Runtime Error", "\
+The program failed while running, for example from an integer overflow, dividing by zero
+or recursing too deeply. The underlined code is what was running when it failed.
+
+Erroneous example:
+
+    let big = 2147483647;
+    big -> big + 1;
+
+Check values before operations that could fail:
+
+    let big = 2147483647;
+    if big < 2147483647 {
+        big -> big + 1;
+    }
+"),
+    ("E0022", "Duplicate Declaration", "\
+A name was declared twice in the same scope. A function reading the name would see a
+different one of the two depending on how the program is run, so a scope can only declare
+each name once. Declaring it again in a block inside that scope is fine.
+
+Erroneous example:
+
+    x = 1;
+    x = 2;
+    print(x);
+
+Declare it with `let` and assign the new value, or give the second one its own name:
+
+    let x = 1;
+    x -> 2;
+    print(x);
+"),
+];
+
+/// The kind and description of an error code like `E0003`, which may be written in lower
+/// case.
+pub(crate) fn explain(code: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    let code = code.to_uppercase();
+    return EXPLANATIONS.iter().find(|(c, _, _)| *c == code).copied();
+}
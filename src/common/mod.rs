@@ -1,3 +1,5 @@
 pub(crate) mod token;
 pub(crate) mod error;
 pub(crate) mod ast;
+pub(crate) mod span;
+pub(crate) mod explain;
@@ -0,0 +1,38 @@
+use std::fmt::Display;
+
+/// A range of bytes in a source file, from `start` up to but not including `end`. Offsets
+/// count from the start of the file, lines and columns are only worked out from the source
+/// when a diagnostic is shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Span {
+    /// Which source the span is in, the program is file 0 and the REPL numbers its inputs
+    pub file: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub(crate) fn new(file: usize, start: usize, end: usize) -> Span {
+        return Span {
+            file,
+            start,
+            end,
+        };
+    }
+
+    /// The smallest span covering both this one and `other`.
+    pub(crate) fn to(&self, other: Span) -> Span {
+        return Span::new(self.file, self.start.min(other.start), self.end.max(other.end));
+    }
+
+    /// The first `len` bytes of the span.
+    pub(crate) fn prefix(&self, len: usize) -> Span {
+        return Span::new(self.file, self.start, (self.start + len).min(self.end));
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
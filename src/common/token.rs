@@ -1,27 +1,29 @@
 use std::fmt::Display;
+use super::span::Span;
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Token<'a> {
     pub kind: TokenKind,
-    pub offset: usize,
+    pub span: Span,
     pub line: usize,
     pub lexeme: &'a str,
 }
 
 impl<'a> Token<'a> {
-    pub(crate) fn new(kind: TokenKind, offset: usize, line: usize, lexeme: &'a str) -> Token<'a> {
+    pub(crate) fn new(kind: TokenKind, span: Span, line: usize, lexeme: &'a str) -> Token<'a> {
         return Token {
             kind,
-            offset,
+            span,
             line,
             lexeme,
         };
     }
 
-    pub(crate) fn eof(offset: usize, line: usize) -> Token<'a> {
+    /// The end of the input, an empty span just after the last token.
+    pub(crate) fn eof(span: Span, line: usize) -> Token<'a> {
         return Token {
             kind: TokenKind::Eof,
-            offset,
+            span,
             line,
             lexeme: "<EOF>",
         };
@@ -30,7 +32,7 @@ impl<'a> Token<'a> {
 
 impl<'a> Display for Token<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{} {} '{}'", self.line, self.offset, self.kind, self.lexeme)
+        write!(f, "{}:{} {} '{}'", self.line, self.span, self.kind, self.lexeme)
     }
 }
 
@@ -40,6 +42,8 @@ pub(crate) enum TokenKind {
 
     LParen,
     RParen,
+    LBrace,
+    RBrace,
 
     Plus,
     PlusEqual,
@@ -48,9 +52,34 @@ pub(crate) enum TokenKind {
     MinusEqual,
     MinusMinus,
 
+    Star,
+    StarStar,
+    Slash,
+    Percent,
+
     Equal,
+    EqualEqual,
+    Bang,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    Amp,
+    AmpAmp,
+    Pipe,
+    PipePipe,
+    Caret,
+    Tilde,
+    LessLess,
+    GreaterGreater,
+
     Arrow,
     Semicolon,
+    Comma,
+    Colon,
+    Question,
 
     LiteralString,
     LiteralInt,
@@ -60,6 +89,15 @@ pub(crate) enum TokenKind {
     True,
     False,
     Nil,
+    Let,
+    If,
+    Else,
+    While,
+    Loop,
+    Break,
+    Continue,
+    Fn,
+    Return,
 }
 
 impl TokenKind {
@@ -68,9 +106,78 @@ impl TokenKind {
             "true" => Self::True,
             "false" => Self::False,
             "nil" => Self::Nil,
+            "let" => Self::Let,
+            "if" => Self::If,
+            "else" => Self::Else,
+            "while" => Self::While,
+            "loop" => Self::Loop,
+            "break" => Self::Break,
+            "continue" => Self::Continue,
+            "fn" => Self::Fn,
+            "return" => Self::Return,
             _ => Self::Identifier,
         }
     }
+
+    /// Returns the binding power of this token when used as a binary operator, or `None` if
+    /// it is not one. Higher numbers bind tighter.
+    pub(crate) fn precedence(&self) -> Option<u8> {
+        let prec = match self {
+            Self::PipePipe => 1,
+            Self::AmpAmp => 2,
+            Self::Pipe => 3,
+            Self::Caret => 4,
+            Self::Amp => 5,
+            Self::EqualEqual | Self::BangEqual => 6,
+            Self::Less | Self::LessEqual | Self::Greater | Self::GreaterEqual => 7,
+            Self::LessLess | Self::GreaterGreater => 8,
+            Self::Plus | Self::Minus => 9,
+            Self::Star | Self::Slash | Self::Percent => 10,
+            Self::StarStar => 11,
+            _ => return None,
+        };
+        return Some(prec);
+    }
+
+    /// Returns the source text of an operator, or `None` if this isn't one.
+    pub(crate) fn symbol(&self) -> Option<&'static str> {
+        let sym = match self {
+            Self::Plus => "+",
+            Self::PlusPlus => "++",
+            Self::PlusEqual => "+=",
+            Self::Minus => "-",
+            Self::MinusMinus => "--",
+            Self::MinusEqual => "-=",
+            Self::Star => "*",
+            Self::StarStar => "**",
+            Self::Slash => "/",
+            Self::Percent => "%",
+            Self::Equal => "=",
+            Self::EqualEqual => "==",
+            Self::Bang => "!",
+            Self::BangEqual => "!=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::Amp => "&",
+            Self::AmpAmp => "&&",
+            Self::Pipe => "|",
+            Self::PipePipe => "||",
+            Self::Caret => "^",
+            Self::Tilde => "~",
+            Self::LessLess => "<<",
+            Self::GreaterGreater => ">>",
+            Self::Arrow => "->",
+            _ => return None,
+        };
+        return Some(sym);
+    }
+
+    /// Whether chains of this binary operator group to the right, `2 ** 3 ** 2` is `2 ** 9`.
+    pub(crate) fn is_right_assoc(&self) -> bool {
+        return *self == Self::StarStar;
+    }
 }
 
 impl Display for TokenKind {
@@ -78,6 +185,8 @@ impl Display for TokenKind {
         write!(f, "{}", match self {
             Self::LParen => "LParen",
             Self::RParen => "RParen",
+            Self::LBrace => "LBrace",
+            Self::RBrace => "RBrace",
 
             Self::Plus => "Plus",
             Self::PlusPlus => "PlusPlus",
@@ -87,9 +196,34 @@ impl Display for TokenKind {
             Self::MinusEqual => "MinusEqual",
             Self::MinusMinus => "MinusMinus",
 
+            Self::Star => "Star",
+            Self::StarStar => "StarStar",
+            Self::Slash => "Slash",
+            Self::Percent => "Percent",
+
             Self::Equal => "Equal",
+            Self::EqualEqual => "EqualEqual",
+            Self::Bang => "Bang",
+            Self::BangEqual => "BangEqual",
+            Self::Less => "Less",
+            Self::LessEqual => "LessEqual",
+            Self::Greater => "Greater",
+            Self::GreaterEqual => "GreaterEqual",
+
+            Self::Amp => "Amp",
+            Self::AmpAmp => "AmpAmp",
+            Self::Pipe => "Pipe",
+            Self::PipePipe => "PipePipe",
+            Self::Caret => "Caret",
+            Self::Tilde => "Tilde",
+            Self::LessLess => "LessLess",
+            Self::GreaterGreater => "GreaterGreater",
+
             Self::Arrow => "Arrow",
             Self::Semicolon => "Semicolin",
+            Self::Comma => "Comma",
+            Self::Colon => "Colon",
+            Self::Question => "Question",
             Self::LiteralString => "String",
             Self::LiteralInt => "Integer",
             Self::LiteralFloat => "Float",
@@ -98,6 +232,15 @@ impl Display for TokenKind {
             Self::True => "True",
             Self::False => "False",
             Self::Nil => "Nil",
+            Self::Let => "Let",
+            Self::If => "If",
+            Self::Else => "Else",
+            Self::While => "While",
+            Self::Loop => "Loop",
+            Self::Break => "Break",
+            Self::Continue => "Continue",
+            Self::Fn => "Fn",
+            Self::Return => "Return",
             Self::Eof => "EOF",
         })
     }
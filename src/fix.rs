@@ -0,0 +1,78 @@
+use std::{ cell::RefCell, rc::Rc };
+use crate::{
+    analysis::resolver::Resolver,
+    common::{ error::{ ErrorFormat, Reporter }, span::Span },
+    frontend::{ lexer::Lexer, parser::Parser },
+    src_by_lines,
+};
+
+/// How many times a program is checked again after fixing it. Parsing stops at the first
+/// error in a statement and resolving only starts once parsing succeeds, so a fix can uncover
+/// errors with fixes of their own.
+const MAX_PASSES: usize = 8;
+
+/// Applies every fix the compiler suggests for `source` until there are none left, returning
+/// the fixed source and how many fixes went into it. Everything the fixes don't touch is kept
+/// byte for byte, line endings included.
+pub(crate) fn fix_all(source: &str, name: &String) -> (String, usize) {
+    let mut source = source.to_string();
+    let mut applied = 0;
+    for _ in 0..MAX_PASSES {
+        let edits = suggested(&src_by_lines(&source), name);
+        if edits.is_empty() {
+            break;
+        }
+        let (fixed, count) = apply(&source, edits);
+        if count == 0 {
+            break;
+        }
+        source = fixed;
+        applied += count;
+    }
+    return (source, applied);
+}
+
+/// Checks the program and collects the fixes its diagnostics suggest, without reporting any
+/// of them.
+fn suggested(lines: &Vec<String>, name: &String) -> Vec<(Span, String)> {
+    let reporter = Rc::new(RefCell::new(Reporter::new(lines, name, 0, ErrorFormat::Human)));
+    let lex = Lexer::new(lines, reporter.clone());
+    if let Ok(mut parser) = Parser::new(lex, reporter.clone()) {
+        parser.parse();
+        if !reporter.borrow().has_errors() {
+            let mut resolver = Resolver::new();
+            if let Err(errs) = resolver.resolve(&parser.tree) {
+                reporter.borrow_mut().dump(errs);
+            }
+        }
+    }
+    let edits = reporter.borrow().fixes();
+    return edits;
+}
+
+/// Makes the edits to the source, from first to last. Edits that overlap one before them or
+/// don't land on character boundaries are left out. Returns the new source and how many
+/// edits were made.
+fn apply(source: &str, edits: Vec<(Span, String)>) -> (String, usize) {
+    let mut edits: Vec<(usize, usize, String)> = edits
+        .into_iter()
+        .map(|(span, text)| (span.start, span.end, text))
+        .collect();
+    edits.sort_by_key(|(start, end, _)| (*start, *end));
+    edits.dedup();
+
+    let mut out = String::new();
+    let mut at = 0;
+    let mut applied = 0;
+    for (start, end, text) in edits {
+        if start < at || source.get(start..end).is_none() {
+            continue;
+        }
+        out.push_str(&source[at..start]);
+        out.push_str(&text);
+        at = end;
+        applied += 1;
+    }
+    out.push_str(&source[at..]);
+    return (out, applied);
+}
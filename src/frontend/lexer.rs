@@ -1,6 +1,6 @@
-use std::{ cell::RefCell, rc::Rc };
+use std::{ cell::RefCell, iter::Peekable, rc::Rc, str::CharIndices };
 
-use crate::common::{ error::{ ErrorBase, Reporter }, token::{ Token, TokenKind } };
+use crate::common::{ error::{ ErrorBase, Reporter }, span::Span, token::{ Token, TokenKind } };
 
 pub(crate) struct Lexer<'a> {
     pub reporter: Rc<RefCell<Reporter<'a>>>,
@@ -18,41 +18,65 @@ impl<'a> Lexer<'a> {
     }
 
     pub(crate) fn scan(&mut self) {
-        let mut lines = self.input.iter().enumerate();
+        let lines = self.input.iter().enumerate();
+        let file = self.reporter.borrow().file();
 
-        // helper crap
-        let mut last_ii = 0;
-        let mut last_i = 0;
-
-        while let Some((i, ln)) = lines.next() {
-            last_i = i + 1;
+        // where the current line starts in the file, lines are split on newlines
+        let mut base = 0;
 
+        for (i, raw) in lines {
             let i = i + 1; // shadow i because lines indicies are n - 1
+            let ln = raw.strip_suffix('\r').unwrap_or(raw);
             let mut chars = ln.char_indices().peekable();
+            let at = |start: usize, end: usize| Span::new(file, base + start, base + end);
+            let tok = |kind, start, end| Token::new(kind, at(start, end), i, &ln[start..end]);
 
             while let Some((ii, ch)) = chars.next() {
-                last_ii = ii;
-
                 match ch {
                     ' ' | '\t' | '\r' => {}
-                    '(' =>
-                        self.tokens.push(
-                            Token::new(TokenKind::LParen, ii, i, &ln[ii..ii + '('.len_utf8()])
-                        ),
-                    ')' =>
-                        self.tokens.push(
-                            Token::new(TokenKind::LParen, ii, i, &ln[ii..ii + ')'.len_utf8()])
-                        ),
+                    '(' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::LParen, &[])),
+                    ')' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::RParen, &[])),
 
-                    ';' =>
-                        self.tokens.push(
-                            Token::new(TokenKind::Semicolon, ii, i, &ln[ii..ii + ';'.len_utf8()])
-                        ),
+                    '{' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::LBrace, &[])),
+                    '}' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::RBrace, &[])),
 
-                    '=' =>
-                        self.tokens.push(
-                            Token::new(TokenKind::Equal, ii, i, &ln[ii..ii + '='.len_utf8()])
-                        ),
+                    ';' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Semicolon, &[])),
+                    ',' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Comma, &[])),
+                    ':' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Colon, &[])),
+                    '?' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Question, &[])),
+
+                    '=' => {
+                        let pairs = [('=', TokenKind::EqualEqual)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Equal, &pairs));
+                    }
+                    '!' => {
+                        let pairs = [('=', TokenKind::BangEqual)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Bang, &pairs));
+                    }
+                    '<' => {
+                        let pairs = [('=', TokenKind::LessEqual), ('<', TokenKind::LessLess)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Less, &pairs));
+                    }
+                    '>' => {
+                        let pairs = [('=', TokenKind::GreaterEqual), ('>', TokenKind::GreaterGreater)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Greater, &pairs));
+                    }
+                    '*' => {
+                        let pairs = [('*', TokenKind::StarStar)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Star, &pairs));
+                    }
+                    '/' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Slash, &[])),
+                    '%' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Percent, &[])),
+                    '&' => {
+                        let pairs = [('&', TokenKind::AmpAmp)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Amp, &pairs));
+                    }
+                    '|' => {
+                        let pairs = [('|', TokenKind::PipePipe)];
+                        self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Pipe, &pairs));
+                    }
+                    '^' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Caret, &[])),
+                    '~' => self.tokens.push(operator(&mut chars, &tok, ii, TokenKind::Tilde, &[])),
 
                     '+' => {
                         let mut token: Option<Token> = None;
@@ -62,7 +86,7 @@ impl<'a> Lexer<'a> {
                                     token = Some(
                                         Token::new(
                                             TokenKind::PlusPlus,
-                                            ii,
+                                            at(ii, ii + "++".len()),
                                             i,
                                             &ln[ii..ii + "++".len()]
                                         )
@@ -72,7 +96,7 @@ impl<'a> Lexer<'a> {
                                     token = Some(
                                         Token::new(
                                             TokenKind::PlusEqual,
-                                            ii,
+                                            at(ii, ii + "+=".len()),
                                             i,
                                             &ln[ii..ii + "+=".len()]
                                         )
@@ -87,7 +111,12 @@ impl<'a> Lexer<'a> {
                             }
                             None => {
                                 token = Some(
-                                    Token::new(TokenKind::Plus, ii, i, &ln[ii..ii + '+'.len_utf8()])
+                                    Token::new(
+                                        TokenKind::Plus,
+                                        at(ii, ii + '+'.len_utf8()),
+                                        i,
+                                        &ln[ii..ii + '+'.len_utf8()]
+                                    )
                                 );
                             }
                         }
@@ -102,7 +131,7 @@ impl<'a> Lexer<'a> {
                                     token = Some(
                                         Token::new(
                                             TokenKind::MinusMinus,
-                                            ii,
+                                            at(ii, ii + "--".len()),
                                             i,
                                             &ln[ii..ii + "--".len()]
                                         )
@@ -112,7 +141,7 @@ impl<'a> Lexer<'a> {
                                     token = Some(
                                         Token::new(
                                             TokenKind::MinusEqual,
-                                            ii,
+                                            at(ii, ii + "-=".len()),
                                             i,
                                             &ln[ii..ii + "-=".len()]
                                         )
@@ -122,7 +151,7 @@ impl<'a> Lexer<'a> {
                                     token = Some(
                                         Token::new(
                                             TokenKind::Arrow,
-                                            ii,
+                                            at(ii, ii + "->".len()),
                                             i,
                                             &ln[ii..ii + "->".len()]
                                         )
@@ -139,7 +168,7 @@ impl<'a> Lexer<'a> {
                                 token = Some(
                                     Token::new(
                                         TokenKind::Minus,
-                                        ii,
+                                        at(ii, ii + '-'.len_utf8()),
                                         i,
                                         &ln[ii..ii + '-'.len_utf8()]
                                     )
@@ -162,10 +191,7 @@ impl<'a> Lexer<'a> {
                                     len += chars.next().unwrap().1.len_utf8();
                                 }
                                 None => {
-                                    let eb = ErrorBase::UnterminatedLiteral {
-                                        line: i,
-                                        offset: ii,
-                                    };
+                                    let eb = ErrorBase::UnterminatedLiteral { span: at(ii, ln.len()) };
                                     let mut r = self.reporter.borrow_mut();
                                     r.error(eb, false, "Unterminated character literal");
                                     break 'char_literal;
@@ -175,7 +201,8 @@ impl<'a> Lexer<'a> {
 
                         // push the token
                         let lexeme = &ln[ii + 1..ii + len];
-                        self.tokens.push(Token::new(TokenKind::LiteralChar, ii, i, lexeme));
+                        let end = chars.peek().map_or(ln.len(), |(end, _)| *end);
+                        self.tokens.push(Token::new(TokenKind::LiteralChar, at(ii, end), i, lexeme));
                     }
                     '"' => {
                         let mut len = ch.len_utf8();
@@ -190,10 +217,7 @@ impl<'a> Lexer<'a> {
                                     len += chars.next().unwrap().1.len_utf8();
                                 }
                                 None => {
-                                    let eb = ErrorBase::UnterminatedLiteral {
-                                        line: i,
-                                        offset: ii,
-                                    };
+                                    let eb = ErrorBase::UnterminatedLiteral { span: at(ii, ln.len()) };
                                     let mut r = self.reporter.borrow_mut();
                                     r.error(eb, false, "Unterminated string literal");
                                     break 'str_literal;
@@ -203,7 +227,8 @@ impl<'a> Lexer<'a> {
 
                         // Push the token
                         let lexeme = &ln[ii + 1..ii + len];
-                        self.tokens.push(Token::new(TokenKind::LiteralString, ii, i, lexeme));
+                        let end = chars.peek().map_or(ln.len(), |(end, _)| *end);
+                        self.tokens.push(Token::new(TokenKind::LiteralString, at(ii, end), i, lexeme));
                     }
                     'a'..='z' | 'A'..='Z' | '_' => {
                         let mut len = ch.len_utf8();
@@ -222,7 +247,7 @@ impl<'a> Lexer<'a> {
 
                         // push the token
                         let lexeme = &ln[ii..ii + len];
-                        self.tokens.push(Token::new(TokenKind::as_keyword(lexeme), ii, i, lexeme));
+                        self.tokens.push(tok(TokenKind::as_keyword(lexeme), ii, ii + len));
                     }
                     '0'..='9' => {
                         let mut len = ch.len_utf8();
@@ -243,23 +268,43 @@ impl<'a> Lexer<'a> {
                         // based on prescence of decimal point
                         let lexeme = &ln[ii..ii + len];
                         if lexeme.contains(".") {
-                            self.tokens.push(Token::new(TokenKind::LiteralFloat, ii, i, lexeme));
+                            self.tokens.push(tok(TokenKind::LiteralFloat, ii, ii + len));
                         } else {
-                            self.tokens.push(Token::new(TokenKind::LiteralInt, ii, i, lexeme));
+                            self.tokens.push(tok(TokenKind::LiteralInt, ii, ii + len));
                         }
                     }
 
                     _ => {
                         // (error) throw illegal character error
-                        let eb = ErrorBase::IllegalCharacter { line: i, offset: ii };
+                        let eb = ErrorBase::IllegalCharacter { span: at(ii, ii + ch.len_utf8()) };
                         let mut r = self.reporter.borrow_mut();
                         r.error(eb, false, "illegal character found");
                     }
                 }
             }
+            base += raw.len() + 1;
         }
 
-        // push eof
-        self.tokens.push(Token::eof(last_ii, last_i));
+        // push eof just after the last token, where whatever is missing would have gone
+        let (end, line) = self.tokens.last().map_or((0, 1), |t| (t.span.end, t.line));
+        self.tokens.push(Token::eof(Span::new(file, end, end), line));
+    }
+}
+
+/// Scans a one or two character token. If the character after the current one matches
+/// one of `pairs`, it is consumed and the paired kind is used, otherwise `single` is used.
+fn operator<'a>(
+    chars: &mut Peekable<CharIndices>,
+    tok: &impl Fn(TokenKind, usize, usize) -> Token<'a>,
+    ii: usize,
+    single: TokenKind,
+    pairs: &[(char, TokenKind)]
+) -> Token<'a> {
+    let paired = chars.peek().and_then(|(_, peeked)| pairs.iter().find(|(c, _)| c == peeked));
+    if let Some((_, kind)) = paired {
+        let kind = *kind;
+        _ = chars.next();
+        return tok(kind, ii, ii + 2);
     }
+    return tok(single, ii, ii + 1);
 }
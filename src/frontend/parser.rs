@@ -1,16 +1,29 @@
 use std::{ cell::RefCell, rc::Rc };
 use crate::{
-    common::{ ast::{ Node, NodeKind }, error::{ ErrorBase, Reporter }, token::TokenKind },
+    common::{
+        ast::{ Node, NodeKind, Param, TypeName },
+        error::{ ChaoError, ErrorBase, ErrorSeverity, Reporter },
+        span::Span,
+        token::TokenKind,
+    },
     Token,
 };
 use super::lexer::Lexer;
 
+/// How deeply expressions and blocks may nest before the parser gives up on them, this keeps
+/// the recursive descent and every later pass from overflowing the stack.
+const MAX_NESTING: usize = 256;
+
 pub(crate) struct Parser<'a> {
-    pub tree: Vec<Node<'a>>,
+    pub tree: Vec<Node>,
     pub reporter: Rc<RefCell<Reporter<'a>>>,
     input: Vec<Token<'a>>,
     current: Token<'a>,
     eof: Token<'a>,
+    /// How many blocks deep the parser currently is
+    depth: usize,
+    /// How many blocks, groupings and prefix operators deep the parser currently is
+    nesting: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -22,9 +35,9 @@ impl<'a> Parser<'a> {
         let mut input = Vec::<Token>::new();
         let _ = std::mem::replace(&mut input, lexer.tokens);
 
-        // (debug) print tokens in debug
-        if cfg!(debug_assertions) {
-            println!("{:#?}", input);
+        // (debug) print tokens
+        if crate::debug_dumps() {
+            eprintln!("{:#?}", input);
         }
 
         // check if the file is empty
@@ -34,7 +47,7 @@ impl<'a> Parser<'a> {
 
         let eof = input.pop().unwrap(); // this will always be EOF
         input.reverse();
-        let current = input.pop().unwrap();
+        let current = input.pop().unwrap_or(eof.clone());
 
         return Ok(Parser {
             tree: vec![],
@@ -42,21 +55,40 @@ impl<'a> Parser<'a> {
             input,
             current,
             eof,
+            depth: 0,
+            nesting: 0,
         });
     }
 
+    /// Parses the whole input as one expression without a semicolon, used by the REPL to
+    /// evaluate and echo values.
+    pub(crate) fn parse_single_expression(&mut self) -> Option<Node> {
+        let expr = self.parse_expression()?;
+        self.expect(TokenKind::Eof, "expected the end of the input")?;
+        return Some(expr);
+    }
+
     pub(crate) fn parse(&mut self) {
-        while !self.input.is_empty() {
-            if let Some(n) = self.parse_statement() {
-                self.tree.push(n);
+        while self.current.kind != TokenKind::Eof {
+            match self.parse_statement() {
+                Some(n) => self.tree.push(n),
+                None => self.synchronize(),
             }
             // println!("after cycle: {:#?}", self.current);
 
             _ = self.next(1);
         }
 
-        if cfg!(debug_assertions) {
-            println!("{:#?}", self.tree);
+        if crate::debug_dumps() {
+            eprintln!("{:#?}", self.tree);
+        }
+    }
+
+    /// Skips ahead to the end of the current statement after an error so that one mistake
+    /// doesn't cascade into errors for every remaining token in the statement.
+    fn synchronize(&mut self) {
+        while self.current.kind != TokenKind::Semicolon && self.current.kind != TokenKind::Eof {
+            self.next(1);
         }
     }
 
@@ -70,56 +102,109 @@ impl<'a> Parser<'a> {
         }
         return &self.current;
     }
+
+    /// Goes to the next token and reports an error if it isn't of the expected kind.
+    fn expect(&mut self, kind: TokenKind, msg: &'static str) -> Option<()> {
+        self.next(1);
+        if self.current.kind != kind {
+            self.error_expected(msg);
+            return None;
+        }
+        return Some(());
+    }
+
+    /// Reports that something else was expected in place of the current token.
+    fn error_expected(&mut self, msg: &'static str) {
+        let eb = ErrorBase::ExpectedToken { offender: self.current.clone() };
+        let mut r = self.reporter.borrow_mut();
+        r.error(eb, false, msg);
+    }
+
+    /// Runs `parse` one level deeper, reporting an error instead once `MAX_NESTING` is hit.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.nesting >= MAX_NESTING {
+            let eb = ErrorBase::SyntaxError { token: self.current.clone() };
+            let mut r = self.reporter.borrow_mut();
+            r.error(eb, false, "this is nested too deeply");
+            return None;
+        }
+
+        self.nesting += 1;
+        let res = parse(self);
+        self.nesting -= 1;
+        return res;
+    }
 }
 
 impl<'a> Parser<'a> {
-    fn parse_statement(&mut self) -> Option<Node<'a>> {
+    fn parse_statement(&mut self) -> Option<Node> {
         let tkind = self.current.kind;
 
         match tkind {
             TokenKind::Identifier => {
                 if self.peek().kind == TokenKind::Equal {
-                    let id = self.current.lexeme.to_string();
-
-                    self.next(1); // consume EQUAL
-                    let line = self.current.line;
-                    let offset = self.current.offset;
-                    self.next(1); // go next
-
-                    let val = self.parse_assignment()?;
-                    let nk = NodeKind::StmtConstant { id, val: Box::new(val) };
-
-                    // check for semicolon
-                    if self.peek().kind != TokenKind::Semicolon {
-                        self.next(1); // consume offending token
-                        let line = self.current.line;
-                        let offset = self.current.offset;
-
-                        // (error) expected semicolon
-                        let eb = ErrorBase::ExpectedToken {
-                            line,
-                            offset,
-                            offender: self.current.clone(),
-                        };
-                        let mut r = self.reporter.borrow_mut();
-                        r.error(eb, false, "expected ';'");
-                        return None;
-                    }
+                    return self.parse_binding(false);
+                }
 
-                    self.next(1); // consume semicolon
-                    return Some(Node::new(nk, line, offset));
+                return self.parse_expression_statement();
+            }
+            TokenKind::Let => {
+                self.next(1); // consume LET
+                if self.current.kind != TokenKind::Identifier || self.peek().kind != TokenKind::Equal {
+                    let eb = ErrorBase::SyntaxError { token: self.current.clone() };
+                    let mut r = self.reporter.borrow_mut();
+                    r.error(eb, false, "expected a variable declaration like 'let x = 0;'");
+                    return None;
                 }
 
-                let expr = self.parse_assignment()?;
-                match &expr.kind {
-                    NodeKind::ExprAssignment { id: _, op: _, val: _ } => {
-                        let line = expr.line;
-                        let offset = expr.offset;
-                        let nk = NodeKind::StmtExpression { expr: Box::new(expr) };
-                        return Some(Node::new(nk, line, offset));
-                    },
-                    _ => {}
+                return self.parse_binding(true);
+            }
+            TokenKind::PlusPlus | TokenKind::MinusMinus | TokenKind::LParen => {
+                return self.parse_expression_statement();
+            }
+            TokenKind::LBrace => return self.parse_block(),
+            TokenKind::If => return self.parse_if(),
+            TokenKind::While => {
+                let (start, line) = (self.current.span, self.current.line);
+                self.next(1); // consume WHILE
+
+                let cond = self.parse_expression()?;
+                self.next(1); // go to LBRACE
+                let body = self.parse_block()?;
+
+                let span = start.to(body.span);
+                let nk = NodeKind::StmtWhile { cond: Box::new(cond), body: Box::new(body) };
+                return Some(Node::new(nk, span, line));
+            }
+            TokenKind::Loop => {
+                let (start, line) = (self.current.span, self.current.line);
+                self.next(1); // consume LOOP
+
+                let body = self.parse_block()?;
+                let span = start.to(body.span);
+                let nk = NodeKind::StmtLoop { body: Box::new(body) };
+                return Some(Node::new(nk, span, line));
+            }
+            TokenKind::Fn => return self.parse_function(),
+            TokenKind::Return => {
+                let (start, line) = (self.current.span, self.current.line);
+
+                let mut val: Option<Box<Node>> = None;
+                if self.peek().kind != TokenKind::Semicolon {
+                    self.next(1); // consume RETURN
+                    val = Some(Box::new(self.parse_expression()?));
                 }
+
+                self.expect_semicolon()?;
+                let span = start.to(self.current.span);
+                return Some(Node::new(NodeKind::StmtReturn { val }, span, line));
+            }
+            TokenKind::Break | TokenKind::Continue => {
+                let (start, line) = (self.current.span, self.current.line);
+                let nk = if tkind == TokenKind::Break { NodeKind::StmtBreak } else { NodeKind::StmtContinue };
+
+                self.expect_semicolon()?;
+                return Some(Node::new(nk, start.to(self.current.span), line));
             }
 
             _ => {}
@@ -131,6 +216,195 @@ impl<'a> Parser<'a> {
         r.error(eb, false, "expected a valid statement here");
         return None;
     }
+
+    /// Parses a braces delimited block starting at the LBRACE, leaving the RBRACE as the
+    /// current token.
+    fn parse_block(&mut self) -> Option<Node> {
+        if self.current.kind != TokenKind::LBrace {
+            self.error_expected("expected '{'");
+            return None;
+        }
+
+        let (start, line) = (self.current.span, self.current.line);
+        self.next(1); // consume LBRACE
+
+        self.depth += 1;
+        let body = self.nested(|p| p.parse_block_body());
+        self.depth -= 1;
+
+        let span = start.to(self.current.span);
+        return Some(Node::new(NodeKind::StmtBlock { body: body? }, span, line));
+    }
+
+    fn parse_block_body(&mut self) -> Option<Vec<Node>> {
+        let mut body = Vec::<Node>::new();
+        while self.current.kind != TokenKind::RBrace {
+            if self.current.kind == TokenKind::Eof {
+                self.error_expected("expected '}'");
+                return None;
+            }
+
+            match self.parse_statement() {
+                Some(n) => body.push(n),
+                None => self.synchronize(),
+            }
+            self.next(1);
+        }
+
+        return Some(body);
+    }
+
+    /// Parses `fn id(a: type, ...): type { ... }` starting at the FN, the return type is optional.
+    fn parse_function(&mut self) -> Option<Node> {
+        let (start, line) = (self.current.span, self.current.line);
+
+        if self.depth > 0 {
+            let eb = ErrorBase::SyntaxError { token: self.current.clone() };
+            let mut r = self.reporter.borrow_mut();
+            r.error(eb, false, "functions can only be declared at the top level");
+            return None;
+        }
+
+        self.expect(TokenKind::Identifier, "expected a function name")?;
+        let id = self.current.lexeme.to_string();
+        self.expect(TokenKind::LParen, "expected '('")?;
+
+        let mut params = Vec::<Param>::new();
+        if self.peek().kind == TokenKind::RParen {
+            self.next(1); // consume RPAREN
+        } else {
+            loop {
+                self.expect(TokenKind::Identifier, "expected a parameter name")?;
+                let param_id = self.current.lexeme.to_string();
+                let param_start = self.current.span;
+                self.expect(TokenKind::Colon, "expected ':' followed by the parameter's type")?;
+                let ty = self.parse_type_name()?;
+                let span = param_start.to(ty.span);
+                params.push(Param { id: param_id, ty, span });
+
+                self.next(1); // consume COMMA or RPAREN
+                match self.current.kind {
+                    TokenKind::Comma => continue,
+                    TokenKind::RParen => break,
+                    _ => {
+                        self.error_expected("expected ',' or ')'");
+                        return None;
+                    }
+                }
+            }
+        }
+
+        let mut ret: Option<TypeName> = None;
+        if self.peek().kind == TokenKind::Colon {
+            self.next(1); // consume COLON
+            ret = Some(self.parse_type_name()?);
+        }
+
+        self.next(1); // go to LBRACE
+        let body = self.parse_block()?;
+
+        let span = start.to(body.span);
+        let nk = NodeKind::StmtFunction { id, params, ret, body: Box::new(body) };
+        return Some(Node::new(nk, span, line));
+    }
+
+    /// Goes to the next token and parses it as the name of a type.
+    fn parse_type_name(&mut self) -> Option<TypeName> {
+        self.expect(TokenKind::Identifier, "expected a type")?;
+        let name = self.current.lexeme.to_string();
+        let start = self.current.span;
+
+        let optional = self.peek().kind == TokenKind::Question;
+        if optional {
+            self.next(1); // consume QUESTION
+        }
+        return Some(TypeName { name, optional, span: start.to(self.current.span) });
+    }
+
+    /// Parses `if cond { ... }` with any number of `else if` branches and an optional `else`.
+    fn parse_if(&mut self) -> Option<Node> {
+        let (start, line) = (self.current.span, self.current.line);
+        self.next(1); // consume IF
+
+        let cond = self.parse_expression()?;
+        self.next(1); // go to LBRACE
+        let then = self.parse_block()?;
+
+        let mut otherwise: Option<Box<Node>> = None;
+        if self.peek().kind == TokenKind::Else {
+            self.next(2); // consume RBRACE and ELSE
+            let branch = match self.current.kind {
+                TokenKind::If => self.nested(|p| p.parse_if())?,
+                _ => self.parse_block()?,
+            };
+            otherwise = Some(Box::new(branch));
+        }
+
+        let span = start.to(otherwise.as_ref().map_or(then.span, |branch| branch.span));
+        let nk = NodeKind::StmtIf { cond: Box::new(cond), then: Box::new(then), otherwise };
+        return Some(Node::new(nk, span, line));
+    }
+
+    /// Parses `id = val;` starting at the identifier, producing a `StmtVariable` when `mutable`
+    /// (the `let` has already been consumed) and a `StmtConstant` otherwise.
+    fn parse_binding(&mut self, mutable: bool) -> Option<Node> {
+        let id = self.current.lexeme.to_string();
+        let (start, line) = (self.current.span, self.current.line);
+
+        self.next(2); // consume IDENT and EQUAL
+
+        let val = Box::new(self.parse_expression()?);
+        let nk = if mutable {
+            NodeKind::StmtVariable { id, val }
+        } else {
+            NodeKind::StmtConstant { id, val }
+        };
+
+        self.expect_semicolon()?;
+        return Some(Node::new(nk, start.to(self.current.span), line));
+    }
+
+    /// Parses an expression used as a statement. Only expressions with side effects, being
+    /// assignments, calls and increments/decrements, are meaningful here.
+    fn parse_expression_statement(&mut self) -> Option<Node> {
+        let expr = self.parse_expression()?;
+        match &expr.kind {
+            NodeKind::ExprAssignment { id: _, op: _, val: _ } |
+            NodeKind::ExprCall { callee: _, args: _ } |
+            NodeKind::ExprUnary { op: TokenKind::PlusPlus | TokenKind::MinusMinus, operand: _, postfix: _ } => {
+                self.expect_semicolon()?;
+                let (span, line) = (expr.span.to(self.current.span), expr.line);
+                let nk = NodeKind::StmtExpression { expr: Box::new(expr) };
+                return Some(Node::new(nk, span, line));
+            }
+            _ => {}
+        }
+
+        // (error) invalid statement
+        let eb = ErrorBase::InvalidStatement { token: self.current.clone() };
+        let mut r = self.reporter.borrow_mut();
+        r.error(eb, false, "expression has no effect as a statement");
+        return None;
+    }
+
+    /// Consumes the semicolon ending a statement, reporting an error if it isn't there. When
+    /// the statement ends its line, the error suggests putting the semicolon after it.
+    fn expect_semicolon(&mut self) -> Option<()> {
+        let last = self.current.clone();
+        self.next(1);
+        if self.current.kind == TokenKind::Semicolon {
+            return Some(());
+        }
+
+        let eb = ErrorBase::ExpectedToken { offender: self.current.clone() };
+        let mut err = ChaoError::new(eb, ErrorSeverity::Error, false, "expected ';'");
+        if self.current.kind == TokenKind::Eof || self.current.line > last.line {
+            let end = Span::new(last.span.file, last.span.end, last.span.end);
+            err = err.with_fix(end, ";", "end the statement with ';'");
+        }
+        self.reporter.borrow_mut().dump(vec![err]);
+        return None;
+    }
 }
 
 impl<'a> Parser<'a> {
@@ -139,15 +413,46 @@ impl<'a> Parser<'a> {
     ///
     /// Things considered a nonterminal in this case include all literal values, including things like
     /// matrices, arrays, etc.
-    fn parse_literal(&mut self) -> Option<Node<'a>> {
+    fn parse_literal(&mut self) -> Option<Node> {
         let t = &self.current;
         match t.kind {
+            TokenKind::LParen => {
+                let start = t.span;
+                self.next(1); // consume LPAREN
+                let mut expr = self.nested(|p| p.parse_expression())?;
+
+                self.expect(TokenKind::RParen, "expected ')'")?;
+                expr.span = start.to(self.current.span);
+                return Some(expr);
+            }
             TokenKind::LiteralString => {
                 return Some(Node::str(t));
             }
             TokenKind::Identifier => {
                 return Some(Node::ident(t));
             }
+            TokenKind::True => {
+                return Some(Node::new(NodeKind::LiteralTrue, t.span, t.line));
+            }
+            TokenKind::False => {
+                return Some(Node::new(NodeKind::LiteralFalse, t.span, t.line));
+            }
+            TokenKind::Nil => {
+                return Some(Node::new(NodeKind::LiteralNil, t.span, t.line));
+            }
+            TokenKind::LiteralChar => {
+                match Node::char(t) {
+                    Ok(n) => {
+                        return Some(n);
+                    }
+                    Err(_) => {
+                        let eb = ErrorBase::ParseError { token: t.clone() };
+                        let mut r = self.reporter.borrow_mut();
+                        r.error(eb, false, "character literals must hold exactly one character");
+                        return None;
+                    }
+                }
+            }
 
             TokenKind::LiteralFloat => {
                 match Node::float(t) {
@@ -184,44 +489,148 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Begins by getting an lhs value with a call to parse_term, then checks if lookahead is a binary operator.
-    /// If so, will consume the operator and get a value with a call to `parse_assignment`. Returns the
-    /// expression as `ExprBinary`
-    fn parse_factor(&mut self) -> Option<Node<'a>> {
+    /// Parses prefix operators, `-x`, `!x`, `~x`, `++x` and `--x`, by recursing into itself for
+    /// the operand. Falls through to `parse_postfix` once no more prefix operators are found.
+    fn parse_unary(&mut self) -> Option<Node> {
+        match self.current.kind {
+            | TokenKind::Minus
+            | TokenKind::Bang
+            | TokenKind::Tilde
+            | TokenKind::PlusPlus
+            | TokenKind::MinusMinus => {
+                let (start, line) = (self.current.span, self.current.line);
+                let op = self.current.kind;
+                self.next(1); // consume operator
+
+                let operand = self.nested(|p| p.parse_unary())?;
+                let span = start.to(operand.span);
+                let nk = NodeKind::ExprUnary { op, operand: Box::new(operand), postfix: false };
+                return Some(Node::new(nk, span, line));
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    /// Parses a nonterminal followed by any number of calls or postfix `++` or `--` operators.
+    fn parse_postfix(&mut self) -> Option<Node> {
         let mut expr = self.parse_literal()?;
 
-        if self.peek().kind == TokenKind::Plus || self.peek().kind == TokenKind::Minus {
+        loop {
+            match self.peek().kind {
+                TokenKind::PlusPlus | TokenKind::MinusMinus => {
+                    self.next(1); // consume operator
+                    let (span, line) = (expr.span.to(self.current.span), self.current.line);
+                    let op = self.current.kind;
+
+                    let nk = NodeKind::ExprUnary { op, operand: Box::new(expr), postfix: true };
+                    expr = Node::new(nk, span, line);
+                }
+                TokenKind::LParen => {
+                    self.next(1); // consume LPAREN
+                    let line = self.current.line;
+
+                    let args = self.parse_arguments()?;
+                    let span = expr.span.to(self.current.span);
+                    let nk = NodeKind::ExprCall { callee: Box::new(expr), args };
+                    expr = Node::new(nk, span, line);
+                }
+                _ => break,
+            }
+        }
+
+        return Some(expr);
+    }
+
+    /// Parses a comma separated argument list starting at the LPAREN, leaving the RPAREN as
+    /// the current token.
+    fn parse_arguments(&mut self) -> Option<Vec<Node>> {
+        let mut args = Vec::<Node>::new();
+        if self.peek().kind == TokenKind::RParen {
+            self.next(1); // consume RPAREN
+            return Some(args);
+        }
+
+        loop {
+            self.next(1); // go to the argument
+            args.push(self.parse_expression()?);
+
+            self.next(1); // consume COMMA or RPAREN
+            match self.current.kind {
+                TokenKind::Comma => continue,
+                TokenKind::RParen => break,
+                _ => {
+                    self.error_expected("expected ',' or ')'");
+                    return None;
+                }
+            }
+        }
+
+        return Some(args);
+    }
+
+    /// Precedence climbing parser for binary operators. Parses a unary expression, then keeps folding
+    /// binary operators into the lhs for as long as the lookahead is an operator that binds at
+    /// least as tightly as `min_prec`. Right associative operators recurse with the same
+    /// precedence, everything else with one higher, so `a - b - c` groups as `(a - b) - c`.
+    fn parse_binary(&mut self, min_prec: u8) -> Option<Node> {
+        let mut expr = self.parse_unary()?;
+        let mut folded = 0;
+
+        while let Some(prec) = self.peek().kind.precedence() {
+            if prec < min_prec {
+                break;
+            }
+
             self.next(1); // consume operator
             let line = self.current.line;
-            let offset = self.current.offset;
-            let op = self.current.kind.clone();
+            let op = self.current.kind;
             self.next(1); // go next
 
-            let rhs = self.parse_factor()?;
+            let next_prec = if op.is_right_assoc() { prec } else { prec + 1 };
+
+            // every operator folded into the lhs makes the tree one level deeper
+            self.nesting += folded;
+            let rhs = self.nested(|p| p.parse_binary(next_prec));
+            self.nesting -= folded;
+            folded += 1;
+
+            let rhs = rhs?;
+            let span = expr.span.to(rhs.span);
             let nk = NodeKind::ExprBinary { lhs: Box::new(expr), op, rhs: Box::new(rhs) };
-            expr = Node::new(nk, line, offset);
+            expr = Node::new(nk, span, line);
         }
 
         return Some(expr);
     }
 
-    /// Begins by getting a nonterminal and checks if lookahead is ARROW. If so, will consume ARROW
-    /// and parse the value with a recursive call to itself. Returns the `ExprAssignment`.
-    fn parse_assignment(&mut self) -> Option<Node<'a>> {
-        let mut expr = self.parse_factor()?;
+    /// Begins by getting a binary expression and checks if lookahead is ARROW, `+=` or `-=`. If
+    /// so, will consume it and parse the value with a recursive call to itself. Returns the
+    /// `ExprAssignment`, with `x += v` written out as `x -> x + v`.
+    fn parse_expression(&mut self) -> Option<Node> {
+        let mut expr = self.parse_binary(0)?;
 
         // this needs some stupid garbage to avoid simultaneous mutable borrows
         // just storing everything in local variables without directly owning or referencing a token
-        if self.peek().kind == TokenKind::Arrow {
+        if matches!(self.peek().kind, TokenKind::Arrow | TokenKind::PlusEqual | TokenKind::MinusEqual) {
             self.next(1); // consume ARROW
             let line = self.current.line;
-            let offset = self.current.offset;
-            let op = self.current.kind.clone();
+            let op = self.current.kind;
             self.next(1); // go next
 
-            let val = self.parse_assignment()?;
+            let mut val = self.nested(|p| p.parse_expression())?;
+            let span = expr.span.to(val.span);
+
+            // targets that aren't identifiers are left for the resolver to reject
+            if let NodeKind::LiteralIdent { id } = &expr.kind
+                && op != TokenKind::Arrow
+            {
+                let read = Node::new(NodeKind::LiteralIdent { id: id.clone() }, expr.span, expr.line);
+                let op = if op == TokenKind::PlusEqual { TokenKind::Plus } else { TokenKind::Minus };
+                let nk = NodeKind::ExprBinary { lhs: Box::new(read), op, rhs: Box::new(val) };
+                val = Node::new(nk, span, line);
+            }
             let nk = NodeKind::ExprAssignment { id: Box::new(expr), op, val: Box::new(val) };
-            expr = Node::new(nk, line, offset);
+            expr = Node::new(nk, span, line);
         }
 
         return Some(expr);
@@ -1,74 +1,320 @@
-use std::{ cell::RefCell, env, fs, rc::Rc };
-use common::{ ast::Node, token::Token };
+use std::{ cell::RefCell, env, fs, io, path::Path, rc::Rc, thread };
+use analysis::{ cfg::Cfg, irgen::IrProgram };
+use cli::{ Command, Engine, Stage, Target, EXIT_FAILURE, EXIT_USAGE };
+use common::{ ast::Node, error::Reporter, explain, token::Token };
 
+mod cli;
 mod frontend;
 mod common;
 mod analysis;
+mod backend;
+mod runtime;
+mod repl;
+mod fix;
 
-fn src_by_lines(source: &String) -> Vec<String> {
-    let src = source.clone();
-    let lines: Vec<String> = src
-        .lines()
-        .map(|l| l.to_string())
+/// Splits the source on newlines only, keeping the `\r` of CRLF line endings so byte offsets
+/// into the lines add up to byte offsets into the source.
+fn src_by_lines(source: &str) -> Vec<String> {
+    let lines: Vec<String> = source
+        .split_inclusive('\n')
+        .map(|l| l.strip_suffix('\n').unwrap_or(l).to_string())
         .collect();
     return lines;
 }
 
+/// Whether the compiler's internal state is dumped to stderr as it goes, which debug builds
+/// do when `CHAO_DEBUG` is set.
+fn debug_dumps() -> bool {
+    return cfg!(debug_assertions) && env::var_os("CHAO_DEBUG").is_some();
+}
+
+/// Reads the program at `path`, or from stdin when `path` is `-`. Returns the name to show
+/// in diagnostics along with the source.
+fn read_source(path: &String) -> Result<(String, String), String> {
+    if path == "-" {
+        return io::read_to_string(io::stdin())
+            .map(|src| ("<stdin>".to_string(), src))
+            .map_err(|e| format!("could not read from stdin: {}", e));
+    }
+    return fs::read_to_string(path)
+        .map(|src| (path.clone(), src))
+        .map_err(|e| format!("could not read '{}': {}", path, e));
+}
+
+/// Prints every error collected so far and hands back the failure exit code.
+fn fail(reporter: &Rc<RefCell<Reporter>>) -> i32 {
+    reporter.borrow_mut().print_all();
+    return EXIT_FAILURE;
+}
+
+/// Lowers a resolved program to IR, reporting anything that can't be lowered yet.
+fn lower<'a>(ast: Vec<Node>, reporter: &Rc<RefCell<Reporter<'a>>>) -> Option<IrProgram> {
+    let mut ir_compiler = analysis::irgen::IrCompiler::new();
+    return match ir_compiler.compile(ast) {
+        Ok(ir) => Some(ir),
+        Err(e) => {
+            reporter.borrow_mut().dump(vec![e]);
+            None
+        }
+    };
+}
+
+/// Where `build` writes its output, next to the source with the extension of the target
+/// unless `-o` says otherwise. Reading from stdin without `-o` is rejected by the cli.
+fn output_path(path: &String, output: &Option<String>, ext: &str) -> String {
+    return match output {
+        Some(o) => o.clone(),
+        None => Path::new(path).with_extension(ext).to_string_lossy().to_string(),
+    };
+}
+
+/// Stack size of the thread the compiler runs on. The interpreter recurses for every call of
+/// the program it runs, and has to get `MAX_CALL_DEPTH` deep before reporting an overflow.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let compiler = thread::Builder::new().stack_size(STACK_SIZE).spawn(run);
+    // `run` exits on its own, so getting here means the thread couldn't start or panicked
+    if !compiler.is_ok_and(|c| c.join().is_ok()) {
+        std::process::exit(EXIT_FAILURE);
+    }
+}
 
-    let arg2: String = args.get(2).unwrap_or(&String::new()).to_owned();
+fn run() {
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let path: &String = &args.get(1).unwrap_or_else(|| {
-        eprintln!("No file path specified!");
-        std::process::exit(1);
+    let (command, format) = cli::parse(&args).unwrap_or_else(|msg| {
+        eprintln!("error: {}\n\n{}", msg, cli::USAGE);
+        std::process::exit(EXIT_USAGE);
     });
 
-    let file = fs::read_to_string(path).unwrap_or_else(|_| {
-        eprintln!("Error reading file from path: {}", path);
-        std::process::exit(1);
+    let path = match &command {
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            std::process::exit(0);
+        }
+        Command::Repl => {
+            std::process::exit(repl::Repl::new().run());
+        }
+        Command::Explain { code } => {
+            match explain::explain(code) {
+                Some((code, kind, text)) => print!("{} {}\n\n{}", code, kind, text),
+                None => {
+                    eprintln!("error: unknown error code '{}'", code);
+                    std::process::exit(EXIT_USAGE);
+                }
+            }
+            std::process::exit(0);
+        }
+        Command::Run { path, engine: _ } => path,
+        Command::Check { path } => path,
+        Command::Fix { path } => path,
+        Command::Build { path, target: _, output: _ } => path,
+        Command::Emit { path, stage: _, optimize: _ } => path,
+    };
+
+    let (name, file) = read_source(path).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        std::process::exit(EXIT_USAGE);
     });
 
+    // fixes are written back before checking, so only what they couldn't fix is reported
+    let file = match &command {
+        Command::Fix { path } => {
+            let (fixed, count) = fix::fix_all(&file, &name);
+            if count > 0 && let Err(e) = fs::write(path, &fixed) {
+                eprintln!("error: could not write '{}': {}", path, e);
+                std::process::exit(EXIT_USAGE);
+            }
+            println!("applied {} fix{} to '{}'", count, if count == 1 { "" } else { "es" }, name);
+            fixed
+        }
+        _ => file,
+    };
+
     // Split the file into lines
     let lines = src_by_lines(&file);
+    let reporter = Rc::new(RefCell::new(Reporter::new(&lines, &name, 0, format)));
+    let code = compile(&command, &name, &lines, &reporter);
+    reporter.borrow_mut().finish();
+    std::process::exit(code);
+}
 
-    // Initialize the error reporter
-    let reporter = Rc::new(RefCell::new(common::error::Reporter::new(&lines, path)));
-
-    // Initialize the lexer and parser
-    let lex = frontend::lexer::Lexer::new(&lines, reporter.clone());
-    let par = frontend::parser::Parser::new(lex, reporter.clone());
+/// Runs the compiler pipeline as far as `command` needs, returning the exit code.
+fn compile<'a>(
+    command: &Command,
+    name: &str,
+    lines: &'a Vec<String>,
+    reporter: &Rc<RefCell<Reporter<'a>>>
+) -> i32 {
 
-    if par.is_err() {
-        std::process::exit(0);
+    if let Command::Emit { path: _, stage: Stage::Tokens, optimize: _ } = command {
+        let mut lex = frontend::lexer::Lexer::new(lines, reporter.clone());
+        lex.scan();
+        if reporter.borrow().has_errors() {
+            return fail(reporter);
+        }
+        for token in &lex.tokens {
+            println!("{}", token);
+        }
+        return 0;
     }
-    let mut parser = par.unwrap();
-    parser.parse();
-
-    reporter.borrow_mut().print_all();
 
+    // Initialize the lexer and parser
+    let lex = frontend::lexer::Lexer::new(lines, reporter.clone());
+    let mut parser = match frontend::parser::Parser::new(lex, reporter.clone()) {
+        Ok(parser) => parser,
+        Err(_) => return fail(reporter),
+    };
+    parser.parse();
 
-    if arg2.is_empty() {
-        std::process::exit(0);
+    // don't go any further if the source could not be parsed
+    if reporter.borrow().has_errors() {
+        return fail(reporter);
     }
 
     // name and type resolution
-    let mut ast = Vec::<Node>::new();
-    _ = std::mem::replace(&mut ast, parser.tree);
+    let ast: Vec<Node> = std::mem::take(&mut parser.tree);
+
+    if let Command::Emit { path: _, stage: Stage::Ast, optimize: _ } = command {
+        println!("{:#?}", ast);
+        return 0;
+    }
 
     let mut resolver = analysis::resolver::Resolver::new();
-    let resolver_errors = resolver.resolve(ast);
-    if resolver_errors.is_err() {
-        reporter.borrow_mut().dump(resolver_errors.unwrap_err());
+    if let Err(errs) = resolver.resolve(&ast) {
+        reporter.borrow_mut().dump(errs);
+        return fail(reporter);
+    }
+
+    // warnings don't stop anything, but they'd get in the way of output meant for other tools
+    if !matches!(command, Command::Emit { path: _, stage: _, optimize: _ }) {
+        reporter.borrow_mut().dump(resolver.take_warnings(true));
         reporter.borrow_mut().print_all();
-        std::process::exit(1);
     }
-    
-    if arg2.as_str() == "--ir" {
-        //let mut ir_compiler = analysis::irgen::IrCompiler::new();
-        //let ir = ir_compiler.compile(ast);
-        //println!("{:#?}", ir);
+
+    match command {
+        Command::Check { path: _ } | Command::Fix { path: _ } => {
+            return 0;
+        }
+        Command::Emit { path: _, stage: stage @ (Stage::Ir | Stage::Ssa | Stage::Cfg | Stage::Asm | Stage::Llvm), optimize } => {
+            let Some(ir) = lower(ast, reporter) else {
+                return fail(reporter);
+            };
+            // SSA form and LLVM are printed before going back out of it, the other stages after
+            if matches!(stage, Stage::Ssa | Stage::Llvm) {
+                let mut cfgs = analysis::ssa::construct(&ir);
+                if *optimize {
+                    analysis::opt::run(&mut cfgs);
+                }
+                if *stage == Stage::Llvm {
+                    print!("{}", backend::llvm::generate(&cfgs));
+                    return 0;
+                }
+                for (i, cfg) in cfgs.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("{}", cfg);
+                }
+                return 0;
+            }
+
+            let ir = if *optimize { analysis::opt::optimize(&ir) } else { ir };
+            if *stage == Stage::Ir {
+                print!("{}", ir);
+            } else if *stage == Stage::Asm {
+                print!("{}", backend::x86_64::generate(&ir));
+            } else {
+                let cfgs: Vec<Cfg> = ir.functions.iter().map(Cfg::build).collect();
+                print!("{}", analysis::cfg::to_dot(&cfgs));
+            }
+            return 0;
+        }
+        Command::Build { path, target: Target::C, output } => {
+            let Some(ir) = lower(ast, reporter) else {
+                return fail(reporter);
+            };
+            let output = output_path(path, output, "c");
+            if let Err(e) = fs::write(&output, backend::c::generate(&analysis::opt::optimize(&ir))) {
+                eprintln!("error: could not write '{}': {}", output, e);
+                return EXIT_USAGE;
+            }
+            return 0;
+        }
+        Command::Build { path, target: Target::Wasm, output } => {
+            let Some(ir) = lower(ast, reporter) else {
+                return fail(reporter);
+            };
+            let wat = match backend::wasm::generate(&analysis::opt::optimize(&ir)) {
+                Ok(wat) => wat,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    return EXIT_FAILURE;
+                }
+            };
+            let output = output_path(path, output, "wat");
+            if let Err(e) = fs::write(&output, wat) {
+                eprintln!("error: could not write '{}': {}", output, e);
+                return EXIT_USAGE;
+            }
+            return 0;
+        }
+        Command::Build { path, target: Target::X86_64, output } => {
+            let Some(ir) = lower(ast, reporter) else {
+                return fail(reporter);
+            };
+            // executables have no extension, so one is added when the source doesn't have one
+            let mut output = output_path(path, output, "");
+            if output == *path {
+                output.push_str(".out");
+            }
+            if let Err(e) = backend::linker::link(&backend::x86_64::generate(&analysis::opt::optimize(&ir)), &output) {
+                eprintln!("error: {}", e);
+                return EXIT_FAILURE;
+            }
+            return 0;
+        }
+        // execution, the tree walking interpreter is kept around for comparison
+        Command::Run { path: _, engine: Engine::Interp } => {
+            let mut interpreter = runtime::interpreter::Interpreter::new();
+            if let Err(e) = interpreter.run(&ast) {
+                reporter.borrow_mut().dump(vec![e]);
+                return fail(reporter);
+            }
+            return 0;
+        }
+        _ => {}
     }
 
-    reporter.borrow_mut().print_all();
+    let compiler = runtime::compiler::Compiler::new();
+    let chunk = match compiler.compile(&ast) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            reporter.borrow_mut().dump(vec![e]);
+            return fail(reporter);
+        }
+    };
+
+    match command {
+        Command::Emit { path: _, stage: Stage::Bytecode, optimize: _ } => {
+            println!("{}", chunk.disassemble(name));
+        }
+        Command::Build { path, target: Target::Bytecode, output } => {
+            // bytecode can't be loaded back yet, so what gets written is the listing
+            let output = output_path(path, output, "bytecode");
+            if let Err(e) = fs::write(&output, chunk.disassemble(name)) {
+                eprintln!("error: could not write '{}': {}", output, e);
+                return EXIT_USAGE;
+            }
+        }
+        _ => {
+            let mut vm = runtime::vm::Vm::new();
+            if let Err(e) = vm.run(chunk) {
+                reporter.borrow_mut().dump(vec![e]);
+                return fail(reporter);
+            }
+        }
+    }
+    return 0;
 }
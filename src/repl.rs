@@ -0,0 +1,196 @@
+use std::{ cell::RefCell, io::{ self, BufRead, Write }, rc::Rc };
+use crate::{
+    analysis::resolver::Resolver,
+    cli::EXIT_USAGE,
+    common::{ ast::Node, error::{ ErrorFormat, Reporter } },
+    frontend::{ lexer::Lexer, parser::Parser },
+    runtime::interpreter::Interpreter,
+    src_by_lines,
+};
+
+const PROMPT: &str = "> ";
+const CONTINUE: &str = "... ";
+
+/// What a chunk of input turned out to be.
+enum Input {
+    Statements(Vec<Node>),
+    /// A lone expression without a semicolon, its value is echoed back
+    Expression(Node),
+    /// The input stopped in the middle of something, more lines are needed
+    Incomplete,
+    /// The input could not be parsed, its errors are waiting in the reporter
+    Invalid,
+}
+
+/// An interactive session. Every input is checked by one long lived resolver and run by one
+/// interpreter, so anything declared earlier stays visible to later inputs. Tokens borrow from
+/// their source and the interpreter keeps the nodes of functions, so each input is leaked to
+/// live as long as the session.
+pub(crate) struct Repl {
+    resolver: Resolver,
+    interpreter: Interpreter<'static>,
+    path: &'static String,
+    /// How many inputs have been read, each one is its own file so that errors raised by code
+    /// from an earlier input aren't shown against a later one
+    inputs: usize,
+}
+
+impl Repl {
+    pub(crate) fn new() -> Repl {
+        return Repl {
+            resolver: Resolver::new(),
+            interpreter: Interpreter::new(),
+            path: Box::leak(Box::new("<repl>".to_string())),
+            inputs: 0,
+        };
+    }
+
+    /// Reads inputs until stdin is closed or `:quit` is entered, returning the exit code.
+    pub(crate) fn run(&mut self) -> i32 {
+        let stdin = io::stdin();
+        let mut buffer = String::new();
+
+        loop {
+            print!("{}", if buffer.is_empty() { PROMPT } else { CONTINUE });
+            _ = io::stdout().flush();
+
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => {
+                    println!();
+                    return 0;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("error: could not read from stdin: {}", e);
+                    return EXIT_USAGE;
+                }
+            }
+
+            if buffer.is_empty() && line.trim() == ":quit" {
+                return 0;
+            }
+
+            // an empty line gives up on an incomplete input and shows what's wrong with it
+            let force = !buffer.is_empty() && line.trim().is_empty();
+            buffer.push_str(&line);
+            if buffer.trim().is_empty() {
+                buffer.clear();
+                continue;
+            }
+
+            self.inputs += 1;
+            let (input, reporter) = self.parse(&buffer, force);
+            match input {
+                Input::Incomplete => continue,
+                Input::Invalid => {}
+                Input::Statements(ast) => self.exec(ast, &reporter),
+                Input::Expression(expr) => self.eval(expr, &reporter),
+            }
+            reporter.borrow_mut().print_all();
+            buffer.clear();
+        }
+    }
+}
+
+impl Repl {
+    fn parse(&self, source: &str, force: bool) -> (Input, Rc<RefCell<Reporter<'static>>>) {
+        let lines: &'static Vec<String> = Box::leak(Box::new(src_by_lines(source)));
+
+        // try the input as a lone expression first, throwing away the errors if it isn't one
+        let (mut parser, reporter) = self.parser(lines);
+        if let Some(expr) = parser.parse_single_expression()
+            && !reporter.borrow().has_errors()
+        {
+            return (Input::Expression(expr), reporter);
+        }
+
+        let (mut parser, reporter) = self.parser(lines);
+        parser.parse();
+        if !reporter.borrow().has_errors() {
+            return (Input::Statements(std::mem::take(&mut parser.tree)), reporter);
+        }
+
+        if !force && is_incomplete(source) {
+            return (Input::Incomplete, reporter);
+        }
+        return (Input::Invalid, reporter);
+    }
+
+    fn parser(&self, lines: &'static Vec<String>) -> (Parser<'static>, Rc<RefCell<Reporter<'static>>>) {
+        let reporter = Rc::new(RefCell::new(Reporter::new(lines, self.path, self.inputs, ErrorFormat::Human)));
+        let lex = Lexer::new(lines, reporter.clone());
+
+        // the lexer always ends the input with EOF, so this can't fail
+        let parser = Parser::new(lex, reporter.clone()).ok().unwrap();
+        return (parser, reporter);
+    }
+
+    fn exec(&mut self, ast: Vec<Node>, reporter: &Rc<RefCell<Reporter<'static>>>) {
+        let ast: &'static [Node] = Box::leak(ast.into_boxed_slice());
+
+        // declarations only stick around when the whole input succeeds
+        let checkpoint = self.resolver.checkpoint();
+        if let Err(errs) = self.resolver.resolve(ast) {
+            self.resolver.restore(checkpoint);
+            self.resolver.take_warnings(false);
+            reporter.borrow_mut().dump(errs);
+            return;
+        }
+
+        // globals can still be read by later inputs, so only closed scopes are warned about
+        reporter.borrow_mut().dump(self.resolver.take_warnings(false));
+
+        // globals assigned before the error are put back too, so the input has no effect at all
+        let state = self.interpreter.checkpoint();
+        if let Err(e) = self.interpreter.exec(ast) {
+            self.resolver.restore(checkpoint);
+            self.interpreter.restore(state);
+            reporter.borrow_mut().dump(vec![e]);
+        }
+    }
+
+    fn eval(&mut self, expr: Node, reporter: &Rc<RefCell<Reporter<'static>>>) {
+        let expr: &'static Node = Box::leak(Box::new(expr));
+
+        let has_value = match self.resolver.resolve_expression(expr) {
+            Ok(has_value) => has_value,
+            Err(e) => {
+                reporter.borrow_mut().dump(vec![e]);
+                return;
+            }
+        };
+
+        let state = self.interpreter.checkpoint();
+        match self.interpreter.eval(expr) {
+            Ok(v) if has_value => println!("{}", v),
+            Ok(_) => {}
+            Err(e) => {
+                self.interpreter.restore(state);
+                reporter.borrow_mut().dump(vec![e]);
+            }
+        }
+    }
+}
+
+/// Whether the input looks like it stops partway through, either leaving a bracket open or
+/// not ending a statement or block. Brackets inside string and character literals don't
+/// count, literals end with their line like they do in the lexer.
+fn is_incomplete(source: &str) -> bool {
+    let mut open: i32 = 0;
+    let mut quote: Option<char> = None;
+    for ch in source.chars() {
+        match (ch, quote) {
+            ('\n', _) => quote = None,
+            ('"' | '\'', None) => quote = Some(ch),
+            (_, Some(q)) if ch == q => quote = None,
+            (_, Some(_)) => {}
+            ('(' | '{', None) => open += 1,
+            (')' | '}', None) => open -= 1,
+            _ => {}
+        }
+    }
+
+    let trimmed = source.trim_end();
+    return open > 0 || !(trimmed.ends_with(';') || trimmed.ends_with('}'));
+}
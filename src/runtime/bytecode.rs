@@ -0,0 +1,357 @@
+use crate::common::{ span::Span, token::TokenKind };
+use super::value::Value;
+
+/// Every instruction understood by the virtual machine. Operands, when present, are stored
+/// in the bytes directly following the opcode.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OpCode {
+    /// Pushes the constant at the 2 byte pool index operand
+    Constant = 0,
+    Nil,
+    True,
+    False,
+    Pop,
+
+    /// Pops the stack into a new global named by the constant operand
+    DefineGlobal,
+    /// Pushes the global named by the constant operand
+    GetGlobal,
+    /// Overwrites the global named by the constant operand with the top of the stack
+    SetGlobal,
+    /// Pushes a copy of the stack slot given by the 2 byte operand
+    GetLocal,
+    /// Overwrites the stack slot given by the 2 byte operand with the top of the stack
+    SetLocal,
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Increment,
+    Decrement,
+    Negate,
+    Not,
+    BitNot,
+
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+
+    /// Jumps forward by the 2 byte operand
+    Jump,
+    /// Jumps forward by the 2 byte operand if the top of the stack is falsey, without popping it
+    JumpIfFalse,
+    /// Jumps forward by the 2 byte operand if the top of the stack is truthy, without popping it
+    JumpIfTrue,
+    /// Jumps backward by the 2 byte operand
+    Loop,
+
+    /// Calls the value sitting below the number of arguments given by the 2 byte operand
+    Call,
+    /// Pops the return value, discards the current call frame and pushes the value back
+    Return,
+}
+
+/// Every opcode in discriminant order, used to decode raw bytes.
+const OPCODES: [OpCode; 38] = [
+    OpCode::Constant,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::DefineGlobal,
+    OpCode::GetGlobal,
+    OpCode::SetGlobal,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Modulo,
+    OpCode::Power,
+    OpCode::Increment,
+    OpCode::Decrement,
+    OpCode::Negate,
+    OpCode::Not,
+    OpCode::BitNot,
+    OpCode::Equal,
+    OpCode::NotEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::BitAnd,
+    OpCode::BitOr,
+    OpCode::BitXor,
+    OpCode::ShiftLeft,
+    OpCode::ShiftRight,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::JumpIfTrue,
+    OpCode::Loop,
+    OpCode::Call,
+    OpCode::Return,
+];
+
+impl OpCode {
+    /// Decodes a raw byte back into an opcode, returning `None` for unknown bytes.
+    pub(crate) fn from_byte(byte: u8) -> Option<OpCode> {
+        return OPCODES.get(byte as usize).copied();
+    }
+
+    /// Returns the opcode implementing a binary operator token, if there is one. The logical
+    /// operators have no opcode since they compile to jumps.
+    pub(crate) fn from_binary(op: TokenKind) -> Option<OpCode> {
+        let code = match op {
+            TokenKind::Plus => Self::Add,
+            TokenKind::Minus => Self::Subtract,
+            TokenKind::Star => Self::Multiply,
+            TokenKind::Slash => Self::Divide,
+            TokenKind::Percent => Self::Modulo,
+            TokenKind::StarStar => Self::Power,
+            TokenKind::EqualEqual => Self::Equal,
+            TokenKind::BangEqual => Self::NotEqual,
+            TokenKind::Less => Self::Less,
+            TokenKind::LessEqual => Self::LessEqual,
+            TokenKind::Greater => Self::Greater,
+            TokenKind::GreaterEqual => Self::GreaterEqual,
+            TokenKind::Amp => Self::BitAnd,
+            TokenKind::Pipe => Self::BitOr,
+            TokenKind::Caret => Self::BitXor,
+            TokenKind::LessLess => Self::ShiftLeft,
+            TokenKind::GreaterGreater => Self::ShiftRight,
+            _ => return None,
+        };
+        return Some(code);
+    }
+
+    /// The inverse of `from_binary`, used by the virtual machine to share operator semantics
+    /// with the interpreter.
+    pub(crate) fn as_binary(&self) -> Option<TokenKind> {
+        let op = match self {
+            Self::Add => TokenKind::Plus,
+            Self::Subtract => TokenKind::Minus,
+            Self::Multiply => TokenKind::Star,
+            Self::Divide => TokenKind::Slash,
+            Self::Modulo => TokenKind::Percent,
+            Self::Power => TokenKind::StarStar,
+            Self::Equal => TokenKind::EqualEqual,
+            Self::NotEqual => TokenKind::BangEqual,
+            Self::Less => TokenKind::Less,
+            Self::LessEqual => TokenKind::LessEqual,
+            Self::Greater => TokenKind::Greater,
+            Self::GreaterEqual => TokenKind::GreaterEqual,
+            Self::BitAnd => TokenKind::Amp,
+            Self::BitOr => TokenKind::Pipe,
+            Self::BitXor => TokenKind::Caret,
+            Self::ShiftLeft => TokenKind::LessLess,
+            Self::ShiftRight => TokenKind::GreaterGreater,
+            _ => return None,
+        };
+        return Some(op);
+    }
+
+    /// Returns the opcode implementing a prefix unary operator token, if there is one.
+    pub(crate) fn from_unary(op: TokenKind) -> Option<OpCode> {
+        let code = match op {
+            TokenKind::Minus => Self::Negate,
+            TokenKind::Bang => Self::Not,
+            TokenKind::Tilde => Self::BitNot,
+            TokenKind::PlusPlus => Self::Increment,
+            TokenKind::MinusMinus => Self::Decrement,
+            _ => return None,
+        };
+        return Some(code);
+    }
+
+    /// The inverse of `from_unary`.
+    pub(crate) fn as_unary(&self) -> Option<TokenKind> {
+        let op = match self {
+            Self::Negate => TokenKind::Minus,
+            Self::Not => TokenKind::Bang,
+            Self::BitNot => TokenKind::Tilde,
+            Self::Increment => TokenKind::PlusPlus,
+            Self::Decrement => TokenKind::MinusMinus,
+            _ => return None,
+        };
+        return Some(op);
+    }
+
+    /// Returns the number of operand bytes following this opcode.
+    pub(crate) fn operand_len(&self) -> usize {
+        match self {
+            Self::Constant | Self::DefineGlobal | Self::GetGlobal | Self::SetGlobal => 2,
+            Self::GetLocal | Self::SetLocal | Self::Call => 2,
+            Self::Jump | Self::JumpIfFalse | Self::JumpIfTrue | Self::Loop => 2,
+            _ => 0,
+        }
+    }
+
+    fn is_jump(&self) -> bool {
+        return matches!(self, Self::Jump | Self::JumpIfFalse | Self::JumpIfTrue | Self::Loop);
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Constant => "CONSTANT",
+            Self::Nil => "NIL",
+            Self::True => "TRUE",
+            Self::False => "FALSE",
+            Self::Pop => "POP",
+            Self::DefineGlobal => "DEFINE_GLOBAL",
+            Self::GetGlobal => "GET_GLOBAL",
+            Self::SetGlobal => "SET_GLOBAL",
+            Self::GetLocal => "GET_LOCAL",
+            Self::SetLocal => "SET_LOCAL",
+            Self::Add => "ADD",
+            Self::Subtract => "SUBTRACT",
+            Self::Multiply => "MULTIPLY",
+            Self::Divide => "DIVIDE",
+            Self::Modulo => "MODULO",
+            Self::Power => "POWER",
+            Self::Increment => "INCREMENT",
+            Self::Decrement => "DECREMENT",
+            Self::Negate => "NEGATE",
+            Self::Not => "NOT",
+            Self::BitNot => "BIT_NOT",
+            Self::Equal => "EQUAL",
+            Self::NotEqual => "NOT_EQUAL",
+            Self::Less => "LESS",
+            Self::LessEqual => "LESS_EQUAL",
+            Self::Greater => "GREATER",
+            Self::GreaterEqual => "GREATER_EQUAL",
+            Self::BitAnd => "BIT_AND",
+            Self::BitOr => "BIT_OR",
+            Self::BitXor => "BIT_XOR",
+            Self::ShiftLeft => "SHIFT_LEFT",
+            Self::ShiftRight => "SHIFT_RIGHT",
+            Self::Jump => "JUMP",
+            Self::JumpIfFalse => "JUMP_IF_FALSE",
+            Self::JumpIfTrue => "JUMP_IF_TRUE",
+            Self::Loop => "LOOP",
+            Self::Call => "CALL",
+            Self::Return => "RETURN",
+        }
+    }
+}
+
+/// A compiled unit of bytecode along with its constant pool. Every byte in `code` has a
+/// matching entry in `positions` holding the line and span it was compiled from.
+#[derive(Debug, Default)]
+pub(crate) struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub positions: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    pub(crate) fn new() -> Chunk {
+        return Chunk::default();
+    }
+
+    pub(crate) fn write(&mut self, byte: u8, line: usize, span: Span) {
+        self.code.push(byte);
+        self.positions.push((line, span));
+    }
+
+    pub(crate) fn write_op(&mut self, op: OpCode, line: usize, span: Span) {
+        self.write(op as u8, line, span);
+    }
+
+    pub(crate) fn write_u16(&mut self, val: u16, line: usize, span: Span) {
+        let [hi, lo] = val.to_be_bytes();
+        self.write(hi, line, span);
+        self.write(lo, line, span);
+    }
+
+    pub(crate) fn read_u16(&self, at: usize) -> u16 {
+        return u16::from_be_bytes([self.code[at], self.code[at + 1]]);
+    }
+
+    /// Overwrites the 2 byte operand at `at`, used to back patch forward jumps.
+    pub(crate) fn patch_u16(&mut self, at: usize, val: u16) {
+        let [hi, lo] = val.to_be_bytes();
+        self.code[at] = hi;
+        self.code[at + 1] = lo;
+    }
+
+    /// Adds a value to the constant pool, reusing an existing slot for equal values.
+    /// Returns `None` once the pool can no longer be indexed by a 2 byte operand.
+    pub(crate) fn add_constant(&mut self, val: Value) -> Option<u16> {
+        if let Some(i) = self.constants.iter().position(|c| *c == val) {
+            return Some(i as u16);
+        }
+
+        if self.constants.len() > u16::MAX as usize {
+            return None;
+        }
+        self.constants.push(val);
+        return Some((self.constants.len() - 1) as u16);
+    }
+}
+
+impl Chunk {
+    /// Returns a human readable listing of every instruction in the chunk.
+    pub(crate) fn disassemble(&self, name: &str) -> String {
+        let mut out = format!("== {} ==\n", name);
+        let mut ip = 0;
+
+        while ip < self.code.len() {
+            let (line, _) = self.positions[ip];
+            let same_line = ip > 0 && self.positions[ip - 1].0 == line;
+            let line_col = if same_line { "   |".to_string() } else { format!("{:4}", line) };
+
+            let op = match OpCode::from_byte(self.code[ip]) {
+                Some(op) => op,
+                None => {
+                    out.push_str(&format!("{:04} {} <unknown 0x{:02x}>\n", ip, line_col, self.code[ip]));
+                    ip += 1;
+                    continue;
+                }
+            };
+
+            if op.is_jump() {
+                let dist = self.read_u16(ip + 1) as usize;
+                let target = if op == OpCode::Loop { ip + 3 - dist } else { ip + 3 + dist };
+                out.push_str(&format!("{:04} {} {:<16} {:5} -> {:04}\n", ip, line_col, op.name(), dist, target));
+            } else if op == OpCode::GetLocal || op == OpCode::SetLocal || op == OpCode::Call {
+                let slot = self.read_u16(ip + 1);
+                out.push_str(&format!("{:04} {} {:<16} {:5}\n", ip, line_col, op.name(), slot));
+            } else if op.operand_len() == 2 {
+                let idx = self.read_u16(ip + 1);
+                let constant = self.constants
+                    .get(idx as usize)
+                    .map(|c| format!("{:?}", c))
+                    .unwrap_or("<invalid>".to_string());
+                out.push_str(&format!("{:04} {} {:<16} {:5} {}\n", ip, line_col, op.name(), idx, constant));
+            } else {
+                out.push_str(&format!("{:04} {} {}\n", ip, line_col, op.name()));
+            }
+
+            ip += 1 + op.operand_len();
+        }
+
+        // functions are listed after the chunk that declares them
+        for c in &self.constants {
+            if let Value::Function(func) = c {
+                out.push('\n');
+                out.push_str(&func.chunk.disassemble(&func.name));
+            }
+        }
+
+        return out;
+    }
+}
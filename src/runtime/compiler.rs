@@ -0,0 +1,381 @@
+use crate::common::{
+    ast::{ Node, NodeKind },
+    error::{ ChaoError, ErrorBase, ErrorSeverity },
+    span::Span,
+    token::TokenKind,
+};
+use std::rc::Rc;
+use super::{ bytecode::{ Chunk, OpCode }, value::{ Function, Value } };
+
+/// A variable declared inside a block, living in a stack slot instead of the globals table.
+struct Local {
+    id: String,
+    depth: usize,
+}
+
+/// Book keeping for the innermost loop being compiled so `break` and `continue` know where
+/// to jump and how many locals to discard on the way.
+struct LoopCtx {
+    start: usize,
+    depth: usize,
+    breaks: Vec<usize>,
+}
+
+/// Where a variable lives at runtime.
+enum Slot {
+    Local(u16),
+    Global(u16),
+}
+
+/// Lowers the resolved AST into a bytecode `Chunk` for the virtual machine. Declarations at
+/// the top level become globals, declarations inside blocks become stack slots.
+pub(crate) struct Compiler {
+    chunk: Chunk,
+    locals: Vec<Local>,
+    depth: usize,
+    loops: Vec<LoopCtx>,
+}
+
+impl Compiler {
+    pub(crate) fn new() -> Compiler {
+        return Compiler {
+            chunk: Chunk::new(),
+            locals: vec![],
+            depth: 0,
+            loops: vec![],
+        };
+    }
+
+    pub(crate) fn compile<'a>(mut self, ast: &[Node]) -> Result<Chunk, ChaoError<'a>> {
+        for node in ast {
+            self.statement(node)?;
+        }
+
+        let (line, span) = self.chunk.positions.last().copied().unwrap_or((1, Span::default()));
+        self.chunk.write_op(OpCode::Return, line, span);
+
+        // (debug) print the disassembled chunk
+        if crate::debug_dumps() {
+            eprintln!("{}", self.chunk.disassemble("script"));
+        }
+
+        return Ok(self.chunk);
+    }
+}
+
+impl Compiler {
+    fn statement<'a>(&mut self, node: &Node) -> Result<(), ChaoError<'a>> {
+        match &node.kind {
+            NodeKind::StmtConstant { id, val } | NodeKind::StmtVariable { id, val } => {
+                self.expression(val)?;
+
+                // locals just leave their value on the stack as their slot
+                if self.depth > 0 {
+                    if self.locals.len() > u16::MAX as usize {
+                        return Err(compile_error(node, "too many local variables"));
+                    }
+                    self.locals.push(Local { id: id.clone(), depth: self.depth });
+                    return Ok(());
+                }
+
+                let idx = self.constant(node, Value::String(id.clone()))?;
+                self.emit_with(node, OpCode::DefineGlobal, idx);
+                return Ok(());
+            }
+            NodeKind::StmtExpression { expr } => {
+                self.expression(expr)?;
+                self.emit(node, OpCode::Pop);
+                return Ok(());
+            }
+            NodeKind::StmtBlock { body } => {
+                self.depth += 1;
+                let res = body.iter().try_for_each(|n| self.statement(n));
+                self.depth -= 1;
+
+                // discard the block's locals even if compilation failed to keep them in sync
+                while self.locals.last().is_some_and(|l| l.depth > self.depth) {
+                    self.locals.pop();
+                    self.emit(node, OpCode::Pop);
+                }
+                return res;
+            }
+            NodeKind::StmtIf { cond, then, otherwise } => {
+                self.expression(cond)?;
+                let to_else = self.emit_jump(node, OpCode::JumpIfFalse);
+                self.emit(node, OpCode::Pop);
+                self.statement(then)?;
+
+                let to_end = self.emit_jump(node, OpCode::Jump);
+                self.patch_jump(node, to_else)?;
+                self.emit(node, OpCode::Pop);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise)?;
+                }
+                return self.patch_jump(node, to_end);
+            }
+            NodeKind::StmtWhile { cond, body } => {
+                let start = self.chunk.code.len();
+                self.expression(cond)?;
+                let exit = self.emit_jump(node, OpCode::JumpIfFalse);
+                self.emit(node, OpCode::Pop);
+
+                self.loops.push(LoopCtx { start, depth: self.depth, breaks: vec![] });
+                self.statement(body)?;
+                self.emit_loop(node, start)?;
+
+                self.patch_jump(node, exit)?;
+                self.emit(node, OpCode::Pop);
+                return self.end_loop(node);
+            }
+            NodeKind::StmtLoop { body } => {
+                let start = self.chunk.code.len();
+                self.loops.push(LoopCtx { start, depth: self.depth, breaks: vec![] });
+                self.statement(body)?;
+                self.emit_loop(node, start)?;
+                return self.end_loop(node);
+            }
+            NodeKind::StmtBreak | NodeKind::StmtContinue => {
+                let (start, depth) = match self.loops.last() {
+                    Some(ctx) => (ctx.start, ctx.depth),
+                    None => {
+                        return Err(compile_error(node, "'break' and 'continue' can only be used inside a loop"));
+                    }
+                };
+
+                // drop the locals of every block being jumped out of
+                let count = self.locals.iter().filter(|l| l.depth > depth).count();
+                for _ in 0..count {
+                    self.emit(node, OpCode::Pop);
+                }
+
+                if let NodeKind::StmtContinue = node.kind {
+                    return self.emit_loop(node, start);
+                }
+                let jump = self.emit_jump(node, OpCode::Jump);
+                self.loops.last_mut().unwrap().breaks.push(jump);
+                return Ok(());
+            }
+            NodeKind::StmtFunction { id, params, ret: _, body } => {
+                if params.len() > u16::MAX as usize {
+                    return Err(compile_error(node, "too many parameters"));
+                }
+
+                // the callee occupies the first slot of its frame, followed by the arguments
+                let mut locals = vec![Local { id: String::new(), depth: 1 }];
+                for p in params {
+                    locals.push(Local { id: p.id.clone(), depth: 1 });
+                }
+
+                let mut compiler = Compiler { chunk: Chunk::new(), locals, depth: 1, loops: vec![] };
+                compiler.statement(body)?;
+
+                // falling off the end of the body returns nil
+                compiler.emit(body, OpCode::Nil);
+                compiler.emit(body, OpCode::Return);
+
+                let func = Function { name: id.clone(), arity: params.len(), chunk: compiler.chunk };
+                self.load(node, Value::Function(Rc::new(func)))?;
+                let idx = self.constant(node, Value::String(id.clone()))?;
+                self.emit_with(node, OpCode::DefineGlobal, idx);
+                return Ok(());
+            }
+            NodeKind::StmtReturn { val } => {
+                match val {
+                    Some(val) => self.expression(val)?,
+                    None => self.emit(node, OpCode::Nil),
+                }
+                self.emit(node, OpCode::Return);
+                return Ok(());
+            }
+            _ => Err(compile_error(node, "this statement cannot be compiled")),
+        }
+    }
+
+    fn expression<'a>(&mut self, node: &Node) -> Result<(), ChaoError<'a>> {
+        match &node.kind {
+            NodeKind::LiteralInt { val } => self.load(node, Value::Integer(*val)),
+            NodeKind::LiteralFloat { val } => self.load(node, Value::Float(*val)),
+            NodeKind::LiteralStr { val } => self.load(node, Value::String(val.clone())),
+            NodeKind::LiteralChar { val } => self.load(node, Value::Char(*val)),
+            NodeKind::LiteralTrue => {
+                self.emit(node, OpCode::True);
+                return Ok(());
+            }
+            NodeKind::LiteralFalse => {
+                self.emit(node, OpCode::False);
+                return Ok(());
+            }
+            NodeKind::LiteralNil => {
+                self.emit(node, OpCode::Nil);
+                return Ok(());
+            }
+            NodeKind::LiteralIdent { id } => self.emit_get(node, id),
+            NodeKind::ExprAssignment { id, op: _, val } => {
+                let name = match &id.kind {
+                    NodeKind::LiteralIdent { id } => id,
+                    _ => {
+                        return Err(compile_error(id, "cannot assign to this expression"));
+                    }
+                };
+
+                self.expression(val)?;
+                return self.emit_set(node, name);
+            }
+            NodeKind::ExprUnary { op: op @ (TokenKind::PlusPlus | TokenKind::MinusMinus), operand, postfix } => {
+                let name = match &operand.kind {
+                    NodeKind::LiteralIdent { id } => id,
+                    _ => {
+                        return Err(compile_error(operand, "can only increment or decrement an identifier"));
+                    }
+                };
+
+                // postfix keeps a copy of the old value underneath the updated one
+                if *postfix {
+                    self.emit_get(operand, name)?;
+                }
+                self.emit_get(operand, name)?;
+                self.emit(node, OpCode::from_unary(*op).unwrap());
+                self.emit_set(node, name)?;
+                if *postfix {
+                    self.emit(node, OpCode::Pop);
+                }
+                return Ok(());
+            }
+            NodeKind::ExprUnary { op, operand, postfix: _ } => {
+                self.expression(operand)?;
+                match OpCode::from_unary(*op) {
+                    Some(code) => self.emit(node, code),
+                    None => {
+                        return Err(compile_error(node, "this operator cannot be compiled"));
+                    }
+                }
+                return Ok(());
+            }
+            NodeKind::ExprBinary { lhs, op: op @ (TokenKind::AmpAmp | TokenKind::PipePipe), rhs } => {
+                // leave the lhs on the stack as the result if it decides the outcome
+                self.expression(lhs)?;
+                let jump = if *op == TokenKind::AmpAmp { OpCode::JumpIfFalse } else { OpCode::JumpIfTrue };
+                let exit = self.emit_jump(node, jump);
+                self.emit(node, OpCode::Pop);
+                self.expression(rhs)?;
+                return self.patch_jump(node, exit);
+            }
+            NodeKind::ExprBinary { lhs, op, rhs } => {
+                self.expression(lhs)?;
+                self.expression(rhs)?;
+                match OpCode::from_binary(*op) {
+                    Some(code) => self.emit(node, code),
+                    None => {
+                        return Err(compile_error(node, "this operator cannot be compiled"));
+                    }
+                }
+                return Ok(());
+            }
+            NodeKind::ExprCall { callee, args } => {
+                if args.len() > u16::MAX as usize {
+                    return Err(compile_error(node, "too many arguments"));
+                }
+
+                self.expression(callee)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                self.emit_with(node, OpCode::Call, args.len() as u16);
+                return Ok(());
+            }
+            _ => Err(compile_error(node, "this expression cannot be compiled")),
+        }
+    }
+}
+
+impl Compiler {
+    fn emit(&mut self, node: &Node, op: OpCode) {
+        self.chunk.write_op(op, node.line, node.span);
+    }
+
+    fn emit_with(&mut self, node: &Node, op: OpCode, operand: u16) {
+        self.chunk.write_op(op, node.line, node.span);
+        self.chunk.write_u16(operand, node.line, node.span);
+    }
+
+    /// Finds the slot of the innermost variable named `id`, falling back to a global.
+    fn slot<'a>(&mut self, node: &Node, id: &String) -> Result<Slot, ChaoError<'a>> {
+        match self.locals.iter().rposition(|l| l.id == *id) {
+            Some(i) => Ok(Slot::Local(i as u16)),
+            None => Ok(Slot::Global(self.constant(node, Value::String(id.clone()))?)),
+        }
+    }
+
+    fn emit_get<'a>(&mut self, node: &Node, id: &String) -> Result<(), ChaoError<'a>> {
+        match self.slot(node, id)? {
+            Slot::Local(i) => self.emit_with(node, OpCode::GetLocal, i),
+            Slot::Global(i) => self.emit_with(node, OpCode::GetGlobal, i),
+        }
+        return Ok(());
+    }
+
+    fn emit_set<'a>(&mut self, node: &Node, id: &String) -> Result<(), ChaoError<'a>> {
+        match self.slot(node, id)? {
+            Slot::Local(i) => self.emit_with(node, OpCode::SetLocal, i),
+            Slot::Global(i) => self.emit_with(node, OpCode::SetGlobal, i),
+        }
+        return Ok(());
+    }
+
+    /// Emits a backwards jump to `start`.
+    fn emit_loop<'a>(&mut self, node: &Node, start: usize) -> Result<(), ChaoError<'a>> {
+        let dist = self.chunk.code.len() + 3 - start;
+        match u16::try_from(dist) {
+            Ok(dist) => {
+                self.emit_with(node, OpCode::Loop, dist);
+                return Ok(());
+            }
+            Err(_) => Err(compile_error(node, "loop body is too large")),
+        }
+    }
+
+    /// Pops the innermost loop and points all of its breaks at the next instruction.
+    fn end_loop<'a>(&mut self, node: &Node) -> Result<(), ChaoError<'a>> {
+        let ctx = self.loops.pop().unwrap();
+        for jump in ctx.breaks {
+            self.patch_jump(node, jump)?;
+        }
+        return Ok(());
+    }
+
+    /// Emits a jump with a placeholder operand and returns the operand's position for patching.
+    fn emit_jump(&mut self, node: &Node, op: OpCode) -> usize {
+        self.emit_with(node, op, u16::MAX);
+        return self.chunk.code.len() - 2;
+    }
+
+    /// Points the jump whose operand lives at `at` to the next instruction to be emitted.
+    fn patch_jump<'a>(&mut self, node: &Node, at: usize) -> Result<(), ChaoError<'a>> {
+        let dist = self.chunk.code.len() - at - 2;
+        match u16::try_from(dist) {
+            Ok(dist) => {
+                self.chunk.patch_u16(at, dist);
+                return Ok(());
+            }
+            Err(_) => Err(compile_error(node, "too much code to jump over")),
+        }
+    }
+
+    fn constant<'a>(&mut self, node: &Node, val: Value) -> Result<u16, ChaoError<'a>> {
+        match self.chunk.add_constant(val) {
+            Some(idx) => Ok(idx),
+            None => Err(compile_error(node, "too many constants in one chunk")),
+        }
+    }
+
+    fn load<'a>(&mut self, node: &Node, val: Value) -> Result<(), ChaoError<'a>> {
+        let idx = self.constant(node, val)?;
+        self.emit_with(node, OpCode::Constant, idx);
+        return Ok(());
+    }
+}
+
+fn compile_error<'a>(node: &Node, msg: &'static str) -> ChaoError<'a> {
+    let eb = ErrorBase::CompileError { span: node.span };
+    return ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use crate::common::{
+    ast::{ Node, NodeKind },
+    error::{ ChaoError, ErrorBase, ErrorSeverity },
+    token::TokenKind,
+};
+use super::{ value::{ Native, Value }, MAX_CALL_DEPTH };
+
+/// How control leaves a statement, used to unwind out of loop and function bodies.
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// A copy of the globals and functions, used by the REPL to undo an input that failed.
+pub(crate) struct Checkpoint<'n>(HashMap<String, Value>, HashMap<String, &'n Node>);
+
+/// Walks the resolved AST and evaluates it directly.
+pub(crate) struct Interpreter<'n> {
+    /// Innermost scope last, the first scope holds the globals
+    scopes: Vec<HashMap<String, Value>>,
+    /// Declarations of the user functions, called by walking their body
+    functions: HashMap<String, &'n Node>,
+    depth: usize,
+}
+
+impl<'n> Interpreter<'n> {
+    pub(crate) fn new() -> Interpreter<'n> {
+        let mut globals = HashMap::new();
+        for native in Native::all() {
+            globals.insert(native.name().to_string(), Value::Native(native));
+        }
+
+        return Interpreter {
+            scopes: vec![globals],
+            functions: HashMap::new(),
+            depth: 0,
+        };
+    }
+
+    /// Executes every statement in order, stopping at the first runtime error.
+    pub(crate) fn run<'a>(&mut self, ast: &'n [Node]) -> Result<(), ChaoError<'a>> {
+        self.exec(ast)?;
+
+        // (debug) print the final state of the globals
+        if crate::debug_dumps() {
+            eprintln!("{:#?}", self.scopes[0]);
+        }
+
+        return Ok(());
+    }
+
+    /// Executes top level statements, keeping whatever they declare around for later calls.
+    pub(crate) fn exec<'a>(&mut self, ast: &'n [Node]) -> Result<(), ChaoError<'a>> {
+        for node in ast {
+            self.execute(node)?;
+        }
+        return Ok(());
+    }
+
+    pub(crate) fn eval<'a>(&mut self, expr: &'n Node) -> Result<Value, ChaoError<'a>> {
+        return self.evaluate(expr);
+    }
+
+    pub(crate) fn checkpoint(&self) -> Checkpoint<'n> {
+        return Checkpoint(self.scopes[0].clone(), self.functions.clone());
+    }
+
+    /// Puts the globals and functions back the way they were, a runtime error unwinds every
+    /// other scope already.
+    pub(crate) fn restore(&mut self, checkpoint: Checkpoint<'n>) {
+        self.scopes.truncate(1);
+        self.scopes[0] = checkpoint.0;
+        self.functions = checkpoint.1;
+    }
+}
+
+impl<'n> Interpreter<'n> {
+    fn execute<'a>(&mut self, node: &'n Node) -> Result<Flow, ChaoError<'a>> {
+        match &node.kind {
+            NodeKind::StmtConstant { id, val } | NodeKind::StmtVariable { id, val } => {
+                let v = self.evaluate(val)?;
+                self.scopes.last_mut().unwrap().insert(id.clone(), v);
+                return Ok(Flow::Normal);
+            }
+            NodeKind::StmtExpression { expr } => {
+                self.evaluate(expr)?;
+                return Ok(Flow::Normal);
+            }
+            NodeKind::StmtBlock { body } => {
+                self.scopes.push(HashMap::new());
+                let res = self.execute_all(body);
+                self.scopes.pop();
+                return res;
+            }
+            NodeKind::StmtIf { cond, then, otherwise } => {
+                if self.evaluate(cond)?.is_truthy() {
+                    return self.execute(then);
+                }
+                match otherwise {
+                    Some(otherwise) => self.execute(otherwise),
+                    None => Ok(Flow::Normal),
+                }
+            }
+            NodeKind::StmtWhile { cond, body } => {
+                while self.evaluate(cond)?.is_truthy() {
+                    match self.execute(body)? {
+                        Flow::Break => break,
+                        Flow::Return(v) => {
+                            return Ok(Flow::Return(v));
+                        }
+                        _ => {}
+                    }
+                }
+                return Ok(Flow::Normal);
+            }
+            NodeKind::StmtLoop { body } => {
+                loop {
+                    match self.execute(body)? {
+                        Flow::Break => break,
+                        Flow::Return(v) => {
+                            return Ok(Flow::Return(v));
+                        }
+                        _ => {}
+                    }
+                }
+                return Ok(Flow::Normal);
+            }
+            NodeKind::StmtFunction { id, params: _, ret: _, body: _ } => {
+                self.functions.insert(id.clone(), node);
+                return Ok(Flow::Normal);
+            }
+            NodeKind::StmtReturn { val } => {
+                let v = match val {
+                    Some(val) => self.evaluate(val)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(v));
+            }
+            NodeKind::StmtBreak => Ok(Flow::Break),
+            NodeKind::StmtContinue => Ok(Flow::Continue),
+            _ => Err(runtime_error(node, "this statement cannot be executed")),
+        }
+    }
+
+    /// Executes statements until one of them breaks out of the normal flow.
+    fn execute_all<'a>(&mut self, body: &'n [Node]) -> Result<Flow, ChaoError<'a>> {
+        for node in body {
+            match self.execute(node)? {
+                Flow::Normal => {}
+                flow => {
+                    return Ok(flow);
+                }
+            }
+        }
+        return Ok(Flow::Normal);
+    }
+
+    fn lookup(&self, id: &String) -> Option<&Value> {
+        return self.scopes.iter().rev().find_map(|s| s.get(id));
+    }
+
+    /// Overwrites the innermost binding named `id`, returning `false` if there is none.
+    fn assign(&mut self, id: &String, val: Value) -> bool {
+        match self.scopes.iter_mut().rev().find_map(|s| s.get_mut(id)) {
+            Some(slot) => {
+                *slot = val;
+                return true;
+            }
+            None => false,
+        }
+    }
+
+    /// Calls the user function declared by `decl`, running its body in a fresh scope that
+    /// only sees the globals and its own parameters.
+    fn call<'a>(&mut self, node: &Node, decl: &'n Node, args: Vec<Value>) -> Result<Value, ChaoError<'a>> {
+        let (params, body) = match &decl.kind {
+            NodeKind::StmtFunction { id: _, params, ret: _, body } => (params, body),
+            _ => {
+                return Err(runtime_error(node, "only functions can be called"));
+            }
+        };
+        if params.len() != args.len() {
+            return Err(runtime_error(node, "wrong number of arguments"));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(runtime_error(node, "stack overflow"));
+        }
+
+        let mut scope = HashMap::new();
+        for (p, v) in params.iter().zip(args) {
+            scope.insert(p.id.clone(), v);
+        }
+
+        let caller = self.scopes.split_off(1);
+        self.scopes.push(scope);
+        self.depth += 1;
+        let res = self.execute(body);
+        self.depth -= 1;
+        self.scopes.truncate(1);
+        self.scopes.extend(caller);
+
+        match res? {
+            Flow::Return(v) => Ok(v),
+            _ => Ok(Value::Nil),
+        }
+    }
+
+    fn evaluate<'a>(&mut self, node: &'n Node) -> Result<Value, ChaoError<'a>> {
+        match &node.kind {
+            NodeKind::LiteralInt { val } => Ok(Value::Integer(*val)),
+            NodeKind::LiteralFloat { val } => Ok(Value::Float(*val)),
+            NodeKind::LiteralStr { val } => Ok(Value::String(val.clone())),
+            NodeKind::LiteralChar { val } => Ok(Value::Char(*val)),
+            NodeKind::LiteralTrue => Ok(Value::Bool(true)),
+            NodeKind::LiteralFalse => Ok(Value::Bool(false)),
+            NodeKind::LiteralNil => Ok(Value::Nil),
+            NodeKind::LiteralIdent { id } => {
+                match self.lookup(id) {
+                    Some(v) => Ok(v.clone()),
+                    None => Err(runtime_error(node, "identifier is not defined")),
+                }
+            }
+            NodeKind::ExprAssignment { id, op: _, val } => {
+                let name = match &id.kind {
+                    NodeKind::LiteralIdent { id } => id,
+                    _ => {
+                        return Err(runtime_error(id, "cannot assign to this expression"));
+                    }
+                };
+
+                let v = self.evaluate(val)?;
+                if !self.assign(name, v.clone()) {
+                    return Err(runtime_error(id, "identifier is not defined"));
+                }
+                return Ok(v);
+            }
+            NodeKind::ExprUnary { op: op @ (TokenKind::PlusPlus | TokenKind::MinusMinus), operand, postfix } => {
+                let name = match &operand.kind {
+                    NodeKind::LiteralIdent { id } => id,
+                    _ => {
+                        return Err(runtime_error(operand, "can only increment or decrement an identifier"));
+                    }
+                };
+
+                let old = self.evaluate(operand)?;
+                let new = Value::unary(*op, old.clone()).map_err(|msg| runtime_error(node, msg))?;
+                self.assign(name, new.clone());
+                return Ok(if *postfix { old } else { new });
+            }
+            NodeKind::ExprUnary { op, operand, postfix: _ } => {
+                let v = self.evaluate(operand)?;
+                return Value::unary(*op, v).map_err(|msg| runtime_error(node, msg));
+            }
+            NodeKind::ExprBinary { lhs, op: TokenKind::AmpAmp, rhs } => {
+                if !self.evaluate(lhs)?.is_truthy() {
+                    return Ok(Value::Bool(false));
+                }
+                return Ok(Value::Bool(self.evaluate(rhs)?.is_truthy()));
+            }
+            NodeKind::ExprBinary { lhs, op: TokenKind::PipePipe, rhs } => {
+                if self.evaluate(lhs)?.is_truthy() {
+                    return Ok(Value::Bool(true));
+                }
+                return Ok(Value::Bool(self.evaluate(rhs)?.is_truthy()));
+            }
+            NodeKind::ExprBinary { lhs, op, rhs } => {
+                let l = self.evaluate(lhs)?;
+                let r = self.evaluate(rhs)?;
+                return Value::binary(*op, l, r).map_err(|msg| runtime_error(node, msg));
+            }
+            NodeKind::ExprCall { callee, args } => {
+                let mut vals = Vec::with_capacity(args.len());
+                for arg in args {
+                    vals.push(self.evaluate(arg)?);
+                }
+
+                let name = match &callee.kind {
+                    NodeKind::LiteralIdent { id } => id,
+                    _ => {
+                        return Err(runtime_error(callee, "only functions can be called"));
+                    }
+                };
+                if let Some(decl) = self.functions.get(name).copied() {
+                    return self.call(node, decl, vals);
+                }
+                match self.lookup(name) {
+                    Some(Value::Native(native)) => {
+                        let native = *native;
+                        return native.call(vals).map_err(|msg| runtime_error(node, msg));
+                    }
+                    _ => Err(runtime_error(callee, "only functions can be called")),
+                }
+            }
+            _ => Err(runtime_error(node, "this expression cannot be evaluated")),
+        }
+    }
+}
+
+fn runtime_error<'a>(node: &Node, msg: &'static str) -> ChaoError<'a> {
+    let eb = ErrorBase::RuntimeError { span: node.span };
+    return ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+}
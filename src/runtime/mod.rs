@@ -0,0 +1,9 @@
+pub(crate) mod value;
+pub(crate) mod interpreter;
+pub(crate) mod bytecode;
+pub(crate) mod compiler;
+pub(crate) mod vm;
+
+/// How deeply calls can nest before the program is aborted with a stack overflow. Both engines
+/// share it so a program fails at the same depth on either, the native runtimes use it too.
+pub(crate) const MAX_CALL_DEPTH: usize = 1024;
@@ -0,0 +1,210 @@
+use std::{ fmt::{ Debug, Display }, rc::Rc };
+use crate::common::token::TokenKind;
+use super::bytecode::Chunk;
+
+/// A value produced while executing a Chao program.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Integer(i32),
+    Float(f32),
+    String(String),
+    Char(char),
+    Bool(bool),
+    Nil,
+    /// A compiled user function, only produced by the bytecode compiler
+    Function(Rc<Function>),
+    Native(Native),
+}
+
+/// A user function compiled to its own chunk of bytecode.
+pub(crate) struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        return std::ptr::eq(self, other);
+    }
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
+
+/// Functions built into the language and implemented in Rust.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Native {
+    Print,
+}
+
+impl Native {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Print => "print",
+        }
+    }
+
+    /// Every builtin, used to seed the globals of both engines.
+    pub(crate) fn all() -> [Native; 1] {
+        return [Native::Print];
+    }
+
+    pub(crate) fn call(&self, args: Vec<Value>) -> Result<Value, &'static str> {
+        match self {
+            Self::Print => {
+                let [val] = args.as_slice() else {
+                    return Err("print takes one argument");
+                };
+                println!("{}", val);
+                return Ok(Value::Nil);
+            }
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::String(v) => write!(f, "{}", v),
+            Self::Char(v) => write!(f, "{}", v),
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::Nil => write!(f, "nil"),
+            Self::Function(func) => write!(f, "<fn {}>", func.name),
+            Self::Native(n) => write!(f, "<native fn {}>", n.name()),
+        }
+    }
+}
+
+impl Value {
+    /// Applies a binary operator to two values that have already been evaluated. Shared by the
+    /// interpreter and the virtual machine so both engines agree on semantics. The logical
+    /// operators are not handled here since they have to short circuit.
+    pub(crate) fn binary(op: TokenKind, l: Value, r: Value) -> Result<Value, &'static str> {
+        let val = match (l, r) {
+            (Value::Integer(a), Value::Integer(b)) => {
+                let res = match op {
+                    TokenKind::Plus => a.checked_add(b).ok_or("integer overflow in addition")?,
+                    TokenKind::Minus => a.checked_sub(b).ok_or("integer overflow in subtraction")?,
+                    TokenKind::Star => a.checked_mul(b).ok_or("integer overflow in multiplication")?,
+                    TokenKind::Slash => {
+                        if b == 0 {
+                            return Err("division by zero");
+                        }
+                        a.checked_div(b).ok_or("integer overflow in division")?
+                    }
+                    TokenKind::Percent => {
+                        if b == 0 {
+                            return Err("division by zero");
+                        }
+                        a.checked_rem(b).ok_or("integer overflow in remainder")?
+                    }
+                    TokenKind::StarStar => {
+                        let exp = u32::try_from(b).map_err(|_| "negative exponent in integer power")?;
+                        a.checked_pow(exp).ok_or("integer overflow in power")?
+                    }
+                    TokenKind::Amp => a & b,
+                    TokenKind::Pipe => a | b,
+                    TokenKind::Caret => a ^ b,
+                    TokenKind::LessLess => {
+                        let amt = u32::try_from(b).map_err(|_| "shift amount out of range")?;
+                        a.checked_shl(amt).ok_or("shift amount out of range")?
+                    }
+                    TokenKind::GreaterGreater => {
+                        let amt = u32::try_from(b).map_err(|_| "shift amount out of range")?;
+                        a.checked_shr(amt).ok_or("shift amount out of range")?
+                    }
+                    TokenKind::EqualEqual => return Ok(Value::Bool(a == b)),
+                    TokenKind::BangEqual => return Ok(Value::Bool(a != b)),
+                    TokenKind::Less => return Ok(Value::Bool(a < b)),
+                    TokenKind::LessEqual => return Ok(Value::Bool(a <= b)),
+                    TokenKind::Greater => return Ok(Value::Bool(a > b)),
+                    TokenKind::GreaterEqual => return Ok(Value::Bool(a >= b)),
+                    _ => return Err("invalid operand types for this operator"),
+                };
+                Value::Integer(res)
+            }
+            (Value::Float(a), Value::Float(b)) => {
+                match op {
+                    TokenKind::Plus => Value::Float(a + b),
+                    TokenKind::Minus => Value::Float(a - b),
+                    TokenKind::Star => Value::Float(a * b),
+                    TokenKind::Slash => Value::Float(a / b),
+                    TokenKind::Percent => Value::Float(a % b),
+                    TokenKind::StarStar => Value::Float(a.powf(b)),
+                    TokenKind::EqualEqual => Value::Bool(a == b),
+                    TokenKind::BangEqual => Value::Bool(a != b),
+                    TokenKind::Less => Value::Bool(a < b),
+                    TokenKind::LessEqual => Value::Bool(a <= b),
+                    TokenKind::Greater => Value::Bool(a > b),
+                    TokenKind::GreaterEqual => Value::Bool(a >= b),
+                    _ => return Err("invalid operand types for this operator"),
+                }
+            }
+            (Value::String(a), Value::String(b)) => {
+                match op {
+                    TokenKind::Plus => Value::String(a + &b),
+                    TokenKind::EqualEqual => Value::Bool(a == b),
+                    TokenKind::BangEqual => Value::Bool(a != b),
+                    TokenKind::Less => Value::Bool(a < b),
+                    TokenKind::LessEqual => Value::Bool(a <= b),
+                    TokenKind::Greater => Value::Bool(a > b),
+                    TokenKind::GreaterEqual => Value::Bool(a >= b),
+                    _ => return Err("invalid operand types for this operator"),
+                }
+            }
+            (Value::Char(a), Value::Char(b)) => {
+                match op {
+                    TokenKind::EqualEqual => Value::Bool(a == b),
+                    TokenKind::BangEqual => Value::Bool(a != b),
+                    TokenKind::Less => Value::Bool(a < b),
+                    TokenKind::LessEqual => Value::Bool(a <= b),
+                    TokenKind::Greater => Value::Bool(a > b),
+                    TokenKind::GreaterEqual => Value::Bool(a >= b),
+                    _ => return Err("invalid operand types for this operator"),
+                }
+            }
+            (l, r) => {
+                match op {
+                    TokenKind::EqualEqual => Value::Bool(l == r),
+                    TokenKind::BangEqual => Value::Bool(l != r),
+                    _ => return Err("invalid operand types for this operator"),
+                }
+            }
+        };
+        return Ok(val);
+    }
+
+    /// Applies a prefix unary operator to an evaluated operand. For `++` and `--` this only
+    /// computes the new value, writing it back is left to the caller.
+    pub(crate) fn unary(op: TokenKind, v: Value) -> Result<Value, &'static str> {
+        let val = match (op, v) {
+            (TokenKind::Minus, Value::Integer(a)) => {
+                Value::Integer(a.checked_neg().ok_or("integer overflow in negation")?)
+            }
+            (TokenKind::Minus, Value::Float(a)) => Value::Float(-a),
+            (TokenKind::Tilde, Value::Integer(a)) => Value::Integer(!a),
+            (TokenKind::Bang, v) => Value::Bool(!v.is_truthy()),
+            (TokenKind::PlusPlus, Value::Integer(a)) => {
+                Value::Integer(a.checked_add(1).ok_or("integer overflow in increment")?)
+            }
+            (TokenKind::MinusMinus, Value::Integer(a)) => {
+                Value::Integer(a.checked_sub(1).ok_or("integer overflow in decrement")?)
+            }
+            (TokenKind::PlusPlus, Value::Float(a)) => Value::Float(a + 1.0),
+            (TokenKind::MinusMinus, Value::Float(a)) => Value::Float(a - 1.0),
+            _ => return Err("invalid operand type for this operator"),
+        };
+        return Ok(val);
+    }
+
+    /// Whether the value counts as true when used as a condition.
+    pub(crate) fn is_truthy(&self) -> bool {
+        return !matches!(self, Value::Bool(false) | Value::Nil);
+    }
+}
@@ -0,0 +1,213 @@
+use std::{ collections::HashMap, rc::Rc };
+use crate::common::error::{ ChaoError, ErrorBase, ErrorSeverity };
+use super::{ bytecode::{ Chunk, OpCode }, value::{ Function, Native, Value }, MAX_CALL_DEPTH };
+
+/// The state of a caller that is suspended while a function runs.
+struct Frame {
+    function: Rc<Function>,
+    ip: usize,
+    /// Index of the stack slot holding the callee, local slots are relative to it
+    base: usize,
+}
+
+/// A stack based virtual machine that executes a compiled `Chunk`.
+pub(crate) struct Vm {
+    stack: Vec<Value>,
+    globals: HashMap<String, Value>,
+    frames: Vec<Frame>,
+}
+
+impl Vm {
+    pub(crate) fn new() -> Vm {
+        let mut globals = HashMap::new();
+        for native in Native::all() {
+            globals.insert(native.name().to_string(), Value::Native(native));
+        }
+
+        return Vm {
+            stack: vec![],
+            globals,
+            frames: vec![],
+        };
+    }
+
+    pub(crate) fn run<'a>(&mut self, chunk: Chunk) -> Result<(), ChaoError<'a>> {
+        let mut function = Rc::new(Function { name: "script".to_string(), arity: 0, chunk });
+        let mut ip = 0;
+        let mut base = 0;
+
+        while ip < function.chunk.code.len() {
+            let chunk = &function.chunk;
+            let at = ip;
+            let op = match OpCode::from_byte(chunk.code[ip]) {
+                Some(op) => op,
+                None => {
+                    return Err(runtime_error(chunk, at, "encountered an unknown instruction"));
+                }
+            };
+            ip += 1;
+
+            match op {
+                OpCode::Constant => {
+                    let idx = chunk.read_u16(ip) as usize;
+                    ip += 2;
+                    self.stack.push(chunk.constants[idx].clone());
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+
+                OpCode::DefineGlobal => {
+                    let name = global_name(chunk, ip);
+                    ip += 2;
+                    let val = self.pop();
+                    self.globals.insert(name, val);
+                }
+                OpCode::GetGlobal => {
+                    let name = global_name(chunk, ip);
+                    ip += 2;
+                    match self.globals.get(&name) {
+                        Some(v) => self.stack.push(v.clone()),
+                        None => {
+                            return Err(runtime_error(chunk, at, "identifier is not defined"));
+                        }
+                    }
+                }
+                OpCode::SetGlobal => {
+                    let name = global_name(chunk, ip);
+                    ip += 2;
+                    let val = self.stack.last().cloned().unwrap_or(Value::Nil);
+                    match self.globals.get_mut(&name) {
+                        Some(slot) => *slot = val,
+                        None => {
+                            return Err(runtime_error(chunk, at, "identifier is not defined"));
+                        }
+                    }
+                }
+
+                OpCode::GetLocal => {
+                    let slot = chunk.read_u16(ip) as usize;
+                    ip += 2;
+                    let val = self.stack[base + slot].clone();
+                    self.stack.push(val);
+                }
+                OpCode::SetLocal => {
+                    let slot = chunk.read_u16(ip) as usize;
+                    ip += 2;
+                    self.stack[base + slot] = self.stack.last().cloned().unwrap_or(Value::Nil);
+                }
+
+                OpCode::Jump => {
+                    let dist = chunk.read_u16(ip) as usize;
+                    ip += 2 + dist;
+                }
+                OpCode::Loop => {
+                    let dist = chunk.read_u16(ip) as usize;
+                    ip = ip + 2 - dist;
+                }
+                OpCode::JumpIfFalse | OpCode::JumpIfTrue => {
+                    let dist = chunk.read_u16(ip) as usize;
+                    ip += 2;
+                    let truthy = self.stack.last().is_some_and(|v| v.is_truthy());
+                    if truthy == (op == OpCode::JumpIfTrue) {
+                        ip += dist;
+                    }
+                }
+
+                OpCode::Call => {
+                    let argc = chunk.read_u16(ip) as usize;
+                    ip += 2;
+
+                    let callee_at = self.stack.len() - argc - 1;
+                    match self.stack[callee_at].clone() {
+                        Value::Function(callee) => {
+                            if callee.arity != argc {
+                                return Err(runtime_error(chunk, at, "wrong number of arguments"));
+                            }
+                            if self.frames.len() >= MAX_CALL_DEPTH {
+                                return Err(runtime_error(chunk, at, "stack overflow"));
+                            }
+
+                            let caller = std::mem::replace(&mut function, callee);
+                            self.frames.push(Frame { function: caller, ip, base });
+                            ip = 0;
+                            base = callee_at;
+                        }
+                        Value::Native(native) => {
+                            let args = self.stack.split_off(callee_at + 1);
+                            self.pop();
+                            match native.call(args) {
+                                Ok(v) => self.stack.push(v),
+                                Err(msg) => {
+                                    return Err(runtime_error(chunk, at, msg));
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(runtime_error(chunk, at, "only functions can be called"));
+                        }
+                    }
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    match self.frames.pop() {
+                        Some(frame) => {
+                            self.stack.truncate(base);
+                            self.stack.push(result);
+                            function = frame.function;
+                            ip = frame.ip;
+                            base = frame.base;
+                        }
+                        None => break,
+                    }
+                }
+
+                _ => {
+                    let res = if let Some(tk) = op.as_unary() {
+                        let v = self.pop();
+                        Value::unary(tk, v)
+                    } else if let Some(tk) = op.as_binary() {
+                        let r = self.pop();
+                        let l = self.pop();
+                        Value::binary(tk, l, r)
+                    } else {
+                        Err("encountered an unknown instruction")
+                    };
+
+                    match res {
+                        Ok(v) => self.stack.push(v),
+                        Err(msg) => {
+                            return Err(runtime_error(chunk, at, msg));
+                        }
+                    }
+                }
+            }
+        }
+
+        // (debug) print the final state of the globals
+        if crate::debug_dumps() {
+            eprintln!("{:#?}", self.globals);
+        }
+
+        return Ok(());
+    }
+
+    fn pop(&mut self) -> Value {
+        return self.stack.pop().unwrap_or(Value::Nil);
+    }
+}
+
+/// Reads the global name stored in the constant pool at the operand located at `at`.
+fn global_name(chunk: &Chunk, at: usize) -> String {
+    let idx = chunk.read_u16(at) as usize;
+    return chunk.constants[idx].to_string();
+}
+
+fn runtime_error<'a>(chunk: &Chunk, at: usize, msg: &'static str) -> ChaoError<'a> {
+    let (_, span) = chunk.positions[at];
+    let eb = ErrorBase::RuntimeError { span };
+    return ChaoError::new(eb, ErrorSeverity::Error, false, msg);
+}
@@ -0,0 +1,429 @@
+//! Checks the command line interface itself: subcommands, exit codes and reading from stdin.
+
+use std::{ fs, io::Write, path::Path, process::{ Command, Output, Stdio } };
+
+fn chao(args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_Chao"))
+        .args(args)
+        .output()
+        .expect("could not run the compiler");
+}
+
+fn chao_stdin(args: &[&str], source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_Chao"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run the compiler");
+    child.stdin.take().unwrap().write_all(source.as_bytes()).unwrap();
+    return child.wait_with_output().unwrap();
+}
+
+#[test]
+fn help_succeeds() {
+    for args in [&["help"][..], &["--help"], &["run", "-h"]] {
+        let out = chao(args);
+        assert_eq!(out.status.code(), Some(0));
+        assert!(String::from_utf8_lossy(&out.stdout).contains("Usage: chao"));
+    }
+}
+
+#[test]
+fn invalid_command_lines_are_usage_errors() {
+    let cases: [&[&str]; 15] = [
+        &[],
+        &["frobnicate", "x.chao"],
+        &["run"],
+        &["run", "a.chao", "b.chao"],
+        &["emit", "x.chao"],
+        &["emit", "--stage=nope", "x.chao"],
+        &["check", "--engine=vm", "x.chao"],
+        &["run", "--opt", "x.chao"],
+        &["emit", "--stage=ast", "--opt", "x.chao"],
+        &["build", "--target=c", "-"],
+        &["build", "-"],
+        &["check", "--error-format=xml", "x.chao"],
+        &["explain"],
+        &["explain", "E9999"],
+        &["fix", "-"],
+    ];
+    for args in cases {
+        let out = chao(args);
+        assert_eq!(out.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&out.stderr).contains("error:"), "{:?}", args);
+    }
+}
+
+#[test]
+fn missing_file_is_a_usage_error() {
+    let out = chao(&["run", "does/not/exist.chao"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn reads_source_from_stdin() {
+    let out = chao_stdin(&["run", "-"], "print(40 + 2);\n");
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).lines().any(|l| l == "42"));
+}
+
+#[test]
+fn diagnostics_name_stdin_and_exit_non_zero() {
+    let out = chao_stdin(&["check", "-"], "let x = ;\n");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("<stdin>:1"));
+}
+
+#[test]
+fn check_rejects_void_operands() {
+    let out = chao_stdin(&["check", "-"], "let x = 1 + print(2);\n");
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("cannot apply '+' to 'int' and 'void'"), "{}", stdout);
+}
+
+#[test]
+fn diagnostics_underline_whole_expressions() {
+    let out = chao_stdin(&["check", "-"], "let a = 1;\nprint(a +\n  true);\n");
+    assert_eq!(out.status.code(), Some(1));
+
+    // the header has the column, and both operands are underlined over the two lines
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("<stdin>:2:7 "), "{}", stdout);
+    assert!(stdout.contains("~ print(a +\n~ \x1b[93m      ^^^\x1b[m"), "{}", stdout);
+    assert!(stdout.contains("~   true);\n~ \x1b[93m  ^^^^\x1b[m"), "{}", stdout);
+}
+
+#[test]
+fn diagnostics_explain_themselves() {
+    let source = "let n = 1;\nn -> \"s\";\nx = 1;\nx -> 2;\nprint(n + true);\n";
+    let out = chao_stdin(&["check", "-"], source);
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("cannot reassign 'n' from 'int' to 'str'"), "{}", stdout);
+    assert!(stdout.contains("~ declared as 'int' here:\n~\n~ let n = 1;"), "{}", stdout);
+    assert!(stdout.contains("~ declared as a constant here:\n~\n~ x = 1;"), "{}", stdout);
+    assert!(stdout.contains("\nhelp: declare it with 'let x = ...;' to make it mutable"), "{}", stdout);
+    assert!(stdout.contains("cannot apply '+' to 'int' and 'bool'"), "{}", stdout);
+
+    // labels keep their own location in machine readable output
+    let out = chao_stdin(&["check", "--error-format=json", "-"], source);
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert!(
+        stderr.contains("\"labels\":[{\"message\":\"declared as a constant here\",\"line\":3,\"column\":1,"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("\"help\":[\"declare it with 'let x = ...;' to make it mutable\"]"), "{}", stderr);
+}
+
+#[test]
+fn diagnostics_have_codes_that_explain_them() {
+    let out = chao_stdin(&["check", "-"], "print(count);\n");
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("<stdin>:1:7 \x1b[93mE0003 Unknown Identifier\x1b[m:"), "{}", stdout);
+
+    // every code has an explanation, in either case, with an example
+    for n in 1..=22 {
+        let code = format!("E{:04}", n);
+        let out = chao(&["explain", &code.to_lowercase()]);
+        assert_eq!(out.status.code(), Some(0), "{}", code);
+        let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+        assert!(stdout.starts_with(&format!("{} ", code)), "{}", stdout);
+        assert!(stdout.contains("Erroneous example"), "{}", stdout);
+    }
+    let out = chao(&["explain", "E0003"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("E0003 Unknown Identifier\n"));
+}
+
+#[test]
+fn emits_every_stage() {
+    let source = "let x = 1 + 2;\nprint(x);\n";
+    for (stage, expect) in [
+        ("tokens", "Plus"),
+        ("ast", "ExprBinary"),
+        ("ir", "call print(x)"),
+        ("ssa", "call print(x)"),
+        ("cfg", "digraph"),
+        ("asm", "chao_script:"),
+        ("llvm", "define internal void @chao_script()"),
+        ("bytecode", "DEFINE_GLOBAL"),
+    ] {
+        let out = chao_stdin(&["emit", &format!("--stage={}", stage), "-"], source);
+        assert_eq!(out.status.code(), Some(0), "{}", stage);
+        assert!(String::from_utf8_lossy(&out.stdout).contains(expect), "{}", stage);
+    }
+}
+
+#[test]
+fn emits_typed_ir() {
+    let source = "fn half(n: float): float { return n / 2.0; }\nx = half(3.0);\nprint(x);\n";
+    let out = chao_stdin(&["emit", "--stage=ir", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+
+    let ir = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(ir.contains("fn half(n: float) -> float {"), "{}", ir);
+    assert!(ir.contains("t0: float = n / 2.0"), "{}", ir);
+    assert!(ir.contains("t0: float = call half(3.0)"), "{}", ir);
+    assert!(ir.contains("let x: float = t0"), "{}", ir);
+    assert!(ir.contains("    call print(x)"), "{}", ir);
+}
+
+#[test]
+fn emits_cfg_with_dominators_and_dead_blocks() {
+    let source = "fn f(n: int): int { if n < 2 { return n; } return 0; }\nprint(f(1));\n";
+    let out = chao_stdin(&["emit", "--stage=cfg", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+
+    let dot = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(dot.contains("digraph program {"), "{}", dot);
+    assert!(dot.contains("label=\"f\""), "{}", dot);
+    assert!(dot.contains("f1_b0 -> f1_b1;"), "{}", dot);
+    assert!(dot.contains("idom=b0"), "{}", dot);
+    assert!(dot.contains("style=dashed"), "{}", dot);
+}
+
+#[test]
+fn emits_ssa_with_phis_at_joins() {
+    let source = "fn f(n: int): int {\n    let i = 0;\n    while i < n { i++; }\n    return i;\n}\nprint(f(3));\n";
+    let out = chao_stdin(&["emit", "--stage=ssa", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+
+    let ssa = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(ssa.contains("i#1: int = phi [b0: i, b2: i#2]"), "{}", ssa);
+    assert!(ssa.contains("let i#2: int = t2"), "{}", ssa);
+    assert!(ssa.contains("return i#1"), "{}", ssa);
+}
+
+#[test]
+fn folds_constants_only_when_asked() {
+    let source = "x = 3 + 4;\nfn f(n: int): int { return n * x; }\nprint(f(2));\n";
+
+    let out = chao_stdin(&["emit", "--stage=ir", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+    let ir = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(ir.contains("t0: int = 3 + 4"), "{}", ir);
+
+    let out = chao_stdin(&["emit", "--stage=ir", "--opt", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+    let ir = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(!ir.contains("let x"), "{}", ir);
+    assert!(ir.contains("t0: int = n * 7"), "{}", ir);
+}
+
+#[test]
+fn removes_dead_code_from_ir() {
+    let source = "fn f(n: int): int {\n    let a = n > 1;\n    let b = a;\n    return n;\n}\nprint(f(2));\n";
+
+    let out = chao_stdin(&["emit", "--stage=ir", "--opt", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+    let ir = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(!ir.contains("n > 1"), "{}", ir);
+    assert!(!ir.contains("let b"), "{}", ir);
+
+    let out = chao_stdin(&["emit", "--stage=ir", "-"], source);
+    let ir = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(ir.contains("n > 1"), "{}", ir);
+}
+
+#[test]
+fn unused_bindings_are_warnings() {
+    let source = "let unused = 1;\n_quiet = 2;\nfn f() { k = 3; }\nf();\nprint(\"ran\");\n";
+    let out = chao_stdin(&["run", "-"], source);
+    assert_eq!(out.status.code(), Some(0));
+
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("WARNING"), "{}", stdout);
+    assert!(stdout.contains("<stdin>:1"), "{}", stdout);
+    assert!(stdout.contains("<stdin>:3"), "{}", stdout);
+    assert!(!stdout.contains("<stdin>:2"), "{}", stdout);
+    assert!(stdout.contains("this variable is never read"), "{}", stdout);
+    assert!(stdout.contains("this constant is never read"), "{}", stdout);
+    assert!(stdout.contains("ran"), "{}", stdout);
+}
+
+#[test]
+fn writes_diagnostics_as_json_lines() {
+    let out = chao_stdin(&["check", "--error-format=json", "-"], "let x = 1;\nprint(x + é);\nprint(y);\n");
+    assert_eq!(out.status.code(), Some(1));
+
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stderr);
+    // the span covers every byte of the character, the column counts it once
+    assert_eq!(
+        lines[0],
+        "{\"code\":\"E0016\",\"kind\":\"Illegal Character\",\"severity\":\"error\",\"message\":\"illegal character found\",\
+         \"file\":\"<stdin>\",\"line\":2,\"column\":11,\"span\":{\"start\":21,\"end\":23},\
+         \"labels\":[],\"notes\":[],\"help\":[],\"fixes\":[]}"
+    );
+    assert!(lines[1].contains("\"column\":12,\"span\":{\"start\":23,\"end\":24}"), "{}", lines[1]);
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("ERROR"));
+}
+
+#[test]
+fn counts_both_bytes_of_crlf_line_endings_in_offsets() {
+    let source = "count = 3;\r\nprint(cuont);\r\n";
+    let out = chao_stdin(&["check", "--error-format=json", "-"], source);
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert_eq!(&source[18..23], "cuont");
+    assert!(stderr.contains("\"line\":2,\"column\":7,\"span\":{\"start\":18,\"end\":23},\"labels\""), "{}", stderr);
+    assert!(stderr.contains("\"replacement\":\"count\",\"line\":2,\"column\":7,\"span\":{\"start\":18,\"end\":23}"), "{}", stderr);
+
+    let out = chao_stdin(&["check", "--error-format=sarif", "-"], source);
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert!(stderr.contains("\"endColumn\":12,\"byteOffset\":18,\"byteLength\":5"), "{}", stderr);
+    assert!(stderr.contains("\"deletedRegion\":{\"byteOffset\":18,\"byteLength\":5}"), "{}", stderr);
+}
+
+#[test]
+fn writes_diagnostics_as_a_sarif_log() {
+    let out = chao_stdin(&["run", "--error-format=sarif", "-"], "let unused = 1;\nprint(\"ran\");\n");
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("ran"));
+
+    // warnings still make it into the log, which is written once at the end
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    assert!(stderr.starts_with("{\"$schema\":"), "{}", stderr);
+    assert!(stderr.contains("\"version\":\"2.1.0\""), "{}", stderr);
+    assert!(stderr.contains("\"rules\":[{\"id\":\"E0018\",\"name\":\"Unused Binding\"}]"), "{}", stderr);
+    assert!(stderr.contains("\"ruleId\":\"E0018\",\"level\":\"warning\""), "{}", stderr);
+    assert!(stderr.contains("\"region\":{\"startLine\":1,\"startColumn\":5"), "{}", stderr);
+
+    let out = chao_stdin(&["check", "--error-format=sarif", "-"], "print(1);\n");
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert!(stderr.contains("\"results\":[]"), "{}", stderr);
+}
+
+#[test]
+fn diagnostics_suggest_fixes() {
+    let source = "count = 3;\nprint(cuont)\nprint(1);\n";
+    let out = chao_stdin(&["check", "-"], source);
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("SUGGESTION\x1b[m] end the statement with ';':\n~\n~ print(cuont);"), "{}", stdout);
+
+    let out = chao_stdin(&["check", "--error-format=json", "-"], "count = 3;\nprint(cuont);\n");
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert!(
+        stderr.contains("\"fixes\":[{\"message\":\"'count' has a similar name\",\"replacement\":\"count\",\"line\":2,"),
+        "{}",
+        stderr
+    );
+
+    let out = chao_stdin(&["check", "--error-format=sarif", "-"], "count = 3;\nprint(cuont);\n");
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    assert!(
+        stderr.contains("\"deletedRegion\":{\"byteOffset\":17,\"byteLength\":5},\"insertedContent\":{\"text\":\"count\"}"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn fix_applies_suggestions_in_place() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fix");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("typos.chao");
+
+    // fixing the semicolons lets the resolver see the misspelled names
+    fs::write(&source, "count = 3;\nlet total = 0\ntotal -> total + cuont;\nprint(totl)\n").unwrap();
+    let out = chao(&["fix", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("applied 4 fixes"));
+    assert_eq!(
+        fs::read_to_string(&source).unwrap(),
+        "count = 3;\nlet total = 0;\ntotal -> total + count;\nprint(total);\n"
+    );
+
+    // what can't be fixed is still reported
+    fs::write(&source, "print(nothing_like_it);\n").unwrap();
+    let out = chao(&["fix", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("E0003 Unknown Identifier"));
+    assert_eq!(fs::read_to_string(&source).unwrap(), "print(nothing_like_it);\n");
+
+    // short names and ties only get pointed out, picking one could change what the program means
+    let typos = "let a = 1;\nprint(a);\nprint(c);\nlet cat = 2;\nlet bat = 3;\nprint(cat + bat + hat);\n";
+    fs::write(&source, typos).unwrap();
+    let out = chao(&["fix", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    assert!(stdout.contains("applied 0 fixes"), "{}", stdout);
+    assert!(stdout.contains("help: 'a' has a similar name"), "{}", stdout);
+    assert_eq!(fs::read_to_string(&source).unwrap(), typos);
+
+    fs::write(&source, "let cat = 2;\nlet bat = 3;\nprint(cat + bat + hat);\n").unwrap();
+    let out = chao(&["fix", source.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("help: 'bat', 'cat' have similar names"));
+}
+
+#[test]
+fn fix_keeps_line_endings() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fix_crlf");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("typos.chao");
+
+    fs::write(&source, "count = 3;\r\nlet total = 0\r\ntotal -> total + cuont;\nprint(totl)").unwrap();
+    let out = chao(&["fix", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(&source).unwrap(),
+        "count = 3;\r\nlet total = 0;\r\ntotal -> total + count;\nprint(total);"
+    );
+}
+
+#[test]
+fn builds_c_next_to_the_source() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("build_c");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("hello.chao");
+    fs::write(&source, "print(\"hello\");\n").unwrap();
+
+    let out = chao(&["build", "--target=c", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let c = fs::read_to_string(dir.join("hello.c")).unwrap();
+    assert!(c.contains("int main(void)"), "{}", c);
+    assert!(c.contains("chao_print(1, (chao_value[]){ chao_box_str(\"hello\") });"), "{}", c);
+}
+
+#[test]
+fn builds_the_bytecode_listing_next_to_the_source() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("build_bytecode");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("hello.chao");
+    fs::write(&source, "print(\"hello\");\n").unwrap();
+
+    let out = chao(&["build", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let listing = fs::read_to_string(dir.join("hello.bytecode")).unwrap();
+    assert!(listing.contains("hello"), "{}", listing);
+}
+
+#[test]
+fn builds_an_executable_next_to_the_source() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("build_x86_64");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("hello.chao");
+    fs::write(&source, "fn twice(s: str): str { return s + s; }\nprint(twice(\"hello\"));\n").unwrap();
+
+    let out = chao(&["build", "--target=x86_64", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0), "{}", String::from_utf8_lossy(&out.stderr));
+    let run = Command::new(dir.join("hello")).output().unwrap();
+    assert_eq!(run.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hellohello\n");
+}
+
+#[test]
+fn wasm_target_reports_what_it_does_not_support() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("build_wasm");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("strings.chao");
+    fs::write(&source, "print(\"hello\");\n").unwrap();
+
+    let out = chao(&["build", "--target=wasm", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("strings are not supported by the wasm target"));
+    assert!(!dir.join("strings.wat").exists());
+}
@@ -0,0 +1,332 @@
+//! Runs every program in `tests/corpus` through the compiler. Programs in `pass` have to run
+//! successfully on every engine, programs in `fail` have to be rejected with the diagnostic
+//! named by the start of their file name, e.g. `unknown_type-parameter.chao` expects an
+//! `Unknown Type` error. No input is ever allowed to crash the compiler.
+//!
+//! Every program is also built for each native target, to C compiled with the local `cc` and
+//! to x86-64 linked with the local `as` and `ld`. The binaries have to print the same output
+//! as the VM and fail the same way at runtime.
+//!
+//! Programs the wasm target supports are turned into binary modules and validated, then run
+//! under node with `tests/wasm/host.cjs` when node is installed. LLVM modules are run with
+//! `lli` when LLVM is installed.
+
+use std::{ fs, path::{ Path, PathBuf }, process::{ Command, Output } };
+
+const ENGINES: [&[&str]; 2] = [&["run"], &["run", "--engine=interp"]];
+const TARGETS: [&str; 2] = ["c", "x86_64"];
+const STAGES: [&[&str]; 9] = [
+    &["check"],
+    &["emit", "--stage=ir"],
+    &["emit", "--stage=ir", "--opt"],
+    &["emit", "--stage=ssa"],
+    &["emit", "--stage=ssa", "--opt"],
+    &["emit", "--stage=cfg"],
+    &["emit", "--stage=asm"],
+    &["emit", "--stage=llvm"],
+    &["emit", "--stage=bytecode"],
+];
+
+fn programs(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus").join(dir);
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("could not read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "chao"))
+        .collect();
+    paths.sort();
+    return paths;
+}
+
+fn run(path: &Path, args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_Chao"))
+        .args(args)
+        .arg(path)
+        .output()
+        .expect("could not run the compiler");
+}
+
+/// Turns `unknown_type-parameter` into `Unknown Type`.
+fn expected_kind(path: &Path) -> String {
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let kind = stem.split('-').next().unwrap();
+    let words: Vec<String> = kind
+        .split('_')
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    return words.join(" ");
+}
+
+/// Builds a program for a native target, returning the binary. C is compiled with the system
+/// C compiler, x86-64 is linked by the compiler itself.
+fn build_native(path: &Path, target: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(target);
+    fs::create_dir_all(&dir).unwrap();
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let binary = dir.join(stem);
+    let output = if target == "c" { dir.join(format!("{}.c", stem)) } else { binary.clone() };
+
+    let flag = format!("--target={}", target);
+    let args = ["build", flag.as_str(), "-o", output.to_str().unwrap()];
+    check_exit(path, &args, &run(path, &args), 0);
+    if target != "c" {
+        return binary;
+    }
+
+    let cc = Command::new("cc")
+        .arg(&output)
+        .arg("-o")
+        .arg(&binary)
+        .arg("-lm")
+        .output()
+        .expect("could not run cc");
+    assert!(
+        cc.status.success(),
+        "cc rejected the C built from {}:\n{}",
+        path.display(),
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    return binary;
+}
+
+/// Builds a program to wasm and validates it, returning the binary module. `None` when the
+/// program uses something the wasm target doesn't support yet.
+fn build_wasm(path: &Path) -> Option<PathBuf> {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("wasm");
+    fs::create_dir_all(&dir).unwrap();
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let text = dir.join(format!("{}.wat", stem));
+    let binary = dir.join(format!("{}.wasm", stem));
+
+    let args = ["build", "--target=wasm", "-o", text.to_str().unwrap()];
+    let out = run(path, &args);
+    if String::from_utf8_lossy(&out.stderr).contains("not supported by the wasm target") {
+        check_exit(path, &args, &out, 1);
+        return None;
+    }
+    check_exit(path, &args, &out, 0);
+
+    let bytes = wat::parse_file(&text).unwrap_or_else(|e| panic!("{}: {}", text.display(), e));
+    if let Err(e) = wasmparser::validate(&bytes) {
+        panic!("{} is not a valid module: {}", text.display(), e);
+    }
+    fs::write(&binary, bytes).unwrap();
+    return Some(binary);
+}
+
+/// Runs a module under node, `None` when node isn't installed.
+fn run_wasm(binary: &Path) -> Option<Output> {
+    let host = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wasm/host.cjs");
+    return Command::new("node").arg(host).arg(binary).output().ok();
+}
+
+/// Runs a compiled program with its address space limited, well below what the strings some
+/// of the programs throw away add up to, so a runtime that never frees them runs out.
+fn run_limited(program: &Path, args: &[&Path]) -> Output {
+    return Command::new("sh")
+        .arg("-c")
+        .arg("ulimit -v 262144 && exec \"$@\"")
+        .arg("sh")
+        .arg(program)
+        .args(args)
+        .output()
+        .expect("could not run the native binary");
+}
+
+/// Emits a program as an LLVM module and runs it with `lli`, `None` when LLVM isn't installed.
+fn run_llvm(path: &Path) -> Option<Output> {
+    let args = ["emit", "--stage=llvm", "--opt"];
+    let out = run(path, &args);
+    check_exit(path, &args, &out, 0);
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("llvm");
+    fs::create_dir_all(&dir).unwrap();
+    let module = dir.join(format!("{}.ll", path.file_stem().unwrap().to_str().unwrap()));
+    fs::write(&module, &out.stdout).unwrap();
+    Command::new("lli").arg("--version").output().ok()?;
+    return Some(run_limited(Path::new("lli"), &[&module]));
+}
+
+/// What a program prints when run on the VM, with diagnostics kept out of it.
+fn vm_output(path: &Path) -> String {
+    let out = run(path, &["run", "--error-format=json"]);
+    check_exit(path, &["run"], &out, 0);
+    return String::from_utf8_lossy(&out.stdout).to_string();
+}
+
+fn check_exit(path: &Path, args: &[&str], out: &Output, expected: i32) {
+    let flag = args.join(" ");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        !stderr.contains("panicked"),
+        "{} {} crashed the compiler:\n{}",
+        path.display(),
+        flag,
+        stderr
+    );
+    assert_eq!(
+        out.status.code(),
+        Some(expected),
+        "{} {} exited with {:?}:\n{}{}",
+        path.display(),
+        flag,
+        out.status,
+        stdout,
+        stderr
+    );
+}
+
+#[test]
+fn pass_programs_run_on_every_engine() {
+    let paths = programs("pass");
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        for args in ENGINES.iter().chain(STAGES.iter()) {
+            let out = run(&path, args);
+            check_exit(&path, args, &out, 0);
+        }
+
+        // the bytecode listing would otherwise be written next to the program
+        let listing = Path::new(env!("CARGO_TARGET_TMPDIR")).join("listing.bytecode");
+        let args = ["build", "-o", listing.to_str().unwrap()];
+        check_exit(&path, &args, &run(&path, &args), 0);
+
+        // both engines have to print exactly the same
+        let interp = run(&path, &["run", "--engine=interp", "--error-format=json"]);
+        assert_eq!(
+            String::from_utf8_lossy(&interp.stdout),
+            vm_output(&path),
+            "{} printed differently on the interpreter",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn fail_programs_report_their_diagnostic() {
+    let paths = programs("fail");
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let kind = expected_kind(&path);
+        for args in ENGINES {
+            let out = run(&path, args);
+            check_exit(&path, args, &out, 1);
+
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            assert!(
+                stdout.contains(&kind),
+                "{} {} did not report '{}':\n{}",
+                path.display(),
+                args.join(" "),
+                kind,
+                stdout
+            );
+        }
+    }
+}
+
+#[test]
+fn pass_programs_compile_natively() {
+    for path in programs("pass") {
+        let vm_out = vm_output(&path);
+
+        for target in TARGETS {
+            let binary = build_native(&path, target);
+            let native = run_limited(&binary, &[]);
+            check_exit(&path, &["build", target], &native, 0);
+
+            let native_out = String::from_utf8_lossy(&native.stdout).to_string();
+            assert_eq!(native_out, vm_out, "{} printed differently when built for {}", path.display(), target);
+        }
+    }
+}
+
+#[test]
+fn runtime_errors_fail_natively() {
+    let paths: Vec<PathBuf> = programs("fail")
+        .into_iter()
+        .filter(|path| expected_kind(path) == "Runtime Error")
+        .collect();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        for target in TARGETS {
+            let binary = build_native(&path, target);
+            let native = run_limited(&binary, &[]);
+            check_exit(&path, &["build", target], &native, 1);
+
+            let stderr = String::from_utf8_lossy(&native.stderr);
+            assert!(stderr.contains("runtime error"), "{} {}:\n{}", path.display(), target, stderr);
+        }
+    }
+}
+
+#[test]
+fn pass_programs_compile_to_wasm() {
+    let mut built = 0;
+    for path in programs("pass") {
+        let Some(binary) = build_wasm(&path) else {
+            continue;
+        };
+        built += 1;
+        let Some(wasm) = run_wasm(&binary) else {
+            continue;
+        };
+        check_exit(&path, &["build", "wasm"], &wasm, 0);
+
+        let wasm_out = String::from_utf8_lossy(&wasm.stdout).to_string();
+        assert_eq!(wasm_out, vm_output(&path), "{} printed differently when built for wasm", path.display());
+    }
+    assert!(built > 0);
+}
+
+#[test]
+fn runtime_errors_fail_on_wasm() {
+    for path in programs("fail").into_iter().filter(|path| expected_kind(path) == "Runtime Error") {
+        let Some(binary) = build_wasm(&path) else {
+            continue;
+        };
+        let Some(wasm) = run_wasm(&binary) else {
+            continue;
+        };
+        check_exit(&path, &["build", "wasm"], &wasm, 1);
+
+        let stderr = String::from_utf8_lossy(&wasm.stderr);
+        assert!(stderr.contains("runtime error"), "{}:\n{}", path.display(), stderr);
+    }
+}
+
+#[test]
+fn pass_programs_run_as_llvm() {
+    for path in programs("pass") {
+        let Some(llvm) = run_llvm(&path) else {
+            return;
+        };
+        check_exit(&path, &["emit", "--stage=llvm", "--opt"], &llvm, 0);
+
+        let llvm_out = String::from_utf8_lossy(&llvm.stdout).to_string();
+        assert_eq!(llvm_out, vm_output(&path), "{} printed differently as LLVM", path.display());
+    }
+}
+
+#[test]
+fn runtime_errors_fail_as_llvm() {
+    for path in programs("fail").into_iter().filter(|path| expected_kind(path) == "Runtime Error") {
+        let Some(llvm) = run_llvm(&path) else {
+            return;
+        };
+        check_exit(&path, &["emit", "--stage=llvm", "--opt"], &llvm, 1);
+
+        let stderr = String::from_utf8_lossy(&llvm.stderr);
+        assert!(stderr.contains("runtime error"), "{}:\n{}", path.display(), stderr);
+    }
+}
@@ -0,0 +1,99 @@
+//! Drives `chao repl` through stdin and checks that state carries over between inputs.
+
+use std::{ io::Write, process::{ Command, Stdio } };
+
+/// Feeds `input` to a REPL session and returns its exit code along with what it printed.
+fn session(input: &str) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_Chao"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("could not run the compiler");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    return (out.status.code(), String::from_utf8_lossy(&out.stdout).into_owned());
+}
+
+/// Whether any line of the output ends with `val`, past whatever prompts precede it. Inputs
+/// spanning several lines leave a prompt for each of them in front.
+fn echoed(stdout: &str, val: &str) -> bool {
+    return stdout.lines().any(|l| {
+        let mut rest = l;
+        while let Some(r) = rest.strip_prefix("> ").or_else(|| rest.strip_prefix("... ")) {
+            rest = r;
+        }
+        rest == val
+    });
+}
+
+#[test]
+fn declarations_persist_between_inputs() {
+    let (code, stdout) = session("let x = 20;\nx -> x + 1;\nx * 2\n");
+    assert_eq!(code, Some(0));
+    assert!(echoed(&stdout, "42"), "{}", stdout);
+}
+
+#[test]
+fn multi_line_input_is_joined() {
+    let (code, stdout) = session("fn sq(n: int): int {\n    return n * n;\n}\nsq(\n7)\n");
+    assert_eq!(code, Some(0));
+    assert!(echoed(&stdout, "49"), "{}", stdout);
+}
+
+#[test]
+fn errors_are_reported_without_ending_the_session() {
+    let (code, stdout) = session("let a = missing;\nlet b = 1 / 0;\nlet c = 3;\nc\n");
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("Unknown Identifier"), "{}", stdout);
+    assert!(stdout.contains("division by zero"), "{}", stdout);
+    assert!(echoed(&stdout, "3"), "{}", stdout);
+}
+
+#[test]
+fn failed_inputs_declare_nothing() {
+    let (_, stdout) = session("let b = 1 / 0;\nb\n");
+    assert_eq!(stdout.matches("Unknown Identifier").count(), 1, "{}", stdout);
+}
+
+#[test]
+fn errors_from_earlier_inputs_show_no_source() {
+    // the overflow is in the function from the first input, not in the call
+    let (code, stdout) = session("fn f(n: int): int {\n    return n * 2147483647;\n}\nf(2)\n");
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("<repl> \x1b[93mE0021 Runtime Error"), "{}", stdout);
+    assert!(!stdout.contains("~ f(2)"), "{}", stdout);
+}
+
+#[test]
+fn blank_line_gives_up_on_incomplete_input() {
+    let (code, stdout) = session("let d = \n\n1\n");
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("ERROR"), "{}", stdout);
+    assert!(echoed(&stdout, "1"), "{}", stdout);
+}
+
+#[test]
+fn quit_ends_the_session() {
+    let (code, stdout) = session(":quit\n1 + 1\n");
+    assert_eq!(code, Some(0));
+    assert!(!echoed(&stdout, "2"), "{}", stdout);
+}
+
+#[test]
+fn failed_inputs_assign_nothing() {
+    let (code, stdout) = session("let x = 1;\nlet z = 0;\nx -> 2; let y = 1 / z;\nx\n");
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("division by zero"), "{}", stdout);
+    assert!(echoed(&stdout, "1"), "{}", stdout);
+}
+
+#[test]
+fn brackets_in_literals_are_not_counted() {
+    // the input is complete but invalid, so its error shows up right away
+    let (code, stdout) = session("let = '(';\nlet = \"{\";\n1\n");
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout.matches("ERROR").count(), 2, "{}", stdout);
+    assert!(echoed(&stdout, "1"), "{}", stdout);
+}
@@ -0,0 +1,74 @@
+// Runs a Chao program built with `--target=wasm` and turned into a binary module, printing
+// the same way the VM does. Usage: node host.cjs program.wasm
+"use strict";
+
+const fs = require("fs");
+
+let out = "";
+let memory = null;
+
+// the fewest digits that read back as the same float, the nearest ones first and ties
+// rounding up, written out without an exponent
+function formatFloat(v) {
+    if (Number.isNaN(v)) return "NaN";
+    if (v === Infinity) return "inf";
+    if (v === -Infinity) return "-inf";
+    const sign = v < 0 || Object.is(v, -0) ? "-" : "";
+    v = Math.abs(v);
+    if (v === 0) return sign + "0";
+
+    let digits = "";
+    let exp = 0;
+    for (let p = 1; p <= 9 && digits === ""; p++) {
+        const [mantissa, e] = v.toExponential(p - 1).split("e");
+        const n = BigInt(mantissa.replace(".", ""));
+        const scale = Number(e) - (p - 1);
+        const near = Number(`${n}e${scale}`);
+        const other = near > v ? n - 1n : n + 1n;
+        for (const candidate of [n, other]) {
+            if (Math.fround(Number(`${candidate}e${scale}`)) === v) {
+                digits = candidate.toString();
+                exp = scale + digits.length - 1;
+                break;
+            }
+        }
+    }
+    digits = digits.replace(/(.)0+$/, "$1");
+
+    if (exp < 0) return sign + "0." + "0".repeat(-exp - 1) + digits;
+    if (exp >= digits.length - 1) return sign + digits + "0".repeat(exp - digits.length + 1);
+    return sign + digits.slice(0, exp + 1) + "." + digits.slice(exp + 1);
+}
+
+class Panic extends Error {}
+
+const imports = {
+    chao: {
+        write_int: (n) => { out += n.toString(); },
+        write_float: (f) => { out += formatFloat(f); },
+        write_bool: (b) => { out += b ? "true" : "false"; },
+        write_char: (c) => { out += String.fromCodePoint(c); },
+        fmod: (a, b) => Math.fround(a % b),
+        pow: (a, b) => {
+            if (a === 1 || b === 0) return 1;
+            if (a === -1 && !Number.isFinite(b) && !Number.isNaN(b)) return 1;
+            return Math.fround(Math.pow(a, b));
+        },
+        panic: (ptr, len) => {
+            throw new Panic(Buffer.from(memory.buffer, ptr, len).toString("utf8"));
+        },
+    },
+};
+
+const compiled = new WebAssembly.Module(fs.readFileSync(process.argv[2]));
+const instance = new WebAssembly.Instance(compiled, imports);
+memory = instance.exports.memory;
+try {
+    instance.exports.main();
+    process.stdout.write(out);
+} catch (e) {
+    process.stdout.write(out);
+    if (!(e instanceof Panic)) throw e;
+    process.stderr.write(`runtime error: ${e.message}\n`);
+    process.exitCode = 1;
+}